@@ -1,24 +1,25 @@
 use std::f64;
-use std::io;
 use std::mem;
-use std::os::unix::io::RawFd;
 use std::rc::Rc;
 
 use cairo::XCBSurface;
-use failure::{format_err, Error, ResultExt};
-use futures::{future, Async, Future, Poll, Stream};
+use failure::{format_err, ResultExt};
+use futures::StreamExt;
 use log::*;
-use mio::event::Evented;
-use mio::unix::EventedFd;
-use mio::{PollOpt, Ready, Token};
-use tokio_core::reactor::{Handle, PollEvented};
+use serde::{Deserialize, Serialize};
+use xcb::randr;
 use xcb_util::ewmh;
 
-use crate::text::{ComputedText, Text};
-use crate::widgets::{Widget, WidgetList};
-use crate::Result;
+use crate::ipc::{self, Requests};
+use crate::text::{Attributes, Color, ComputedText, Padding, Text};
+use crate::widgets::{Button, Click, Event, EventSenders, Modifiers, WidgetList};
+use crate::x11::{self, Subscription, WindowWatcher};
+use crate::{Reloads, Result};
 
-fn get_root_visual_type(conn: &xcb::Connection, screen: &xcb::Screen<'_>) -> xcb::Visualtype {
+pub(crate) fn get_root_visual_type(
+    conn: &xcb::Connection,
+    screen: &xcb::Screen<'_>,
+) -> xcb::Visualtype {
     for root in conn.get_setup().roots() {
         for allowed_depth in root.allowed_depths() {
             for visual in allowed_depth.visuals() {
@@ -31,10 +32,26 @@ fn get_root_visual_type(conn: &xcb::Connection, screen: &xcb::Screen<'_>) -> xcb
     panic!("No visual type found");
 }
 
+/// Returns a 32-bit TrueColor visual, whose pixels have an alpha channel, if
+/// the screen has one.
+fn get_argb_visual_type(screen: &xcb::Screen<'_>) -> Option<xcb::Visualtype> {
+    for allowed_depth in screen.allowed_depths() {
+        if allowed_depth.depth() != 32 {
+            continue;
+        }
+        for visual in allowed_depth.visuals() {
+            if visual.class() == xcb::VISUAL_CLASS_TRUE_COLOR as u8 {
+                return Some(visual);
+            }
+        }
+    }
+    None
+}
+
 /// Creates a `cairo::Surface` for the XCB window with the given `id`.
-fn cairo_surface_for_xcb_window(
+pub(crate) fn cairo_surface_for_xcb_window(
     conn: &xcb::Connection,
-    screen: &xcb::Screen<'_>,
+    mut visual: xcb::Visualtype,
     id: u32,
     width: i32,
     height: i32,
@@ -44,8 +61,7 @@ fn cairo_surface_for_xcb_window(
     };
     let visual = unsafe {
         cairo::XCBVisualType::from_raw_none(
-            &mut get_root_visual_type(conn, screen).base as *mut xcb::ffi::xcb_visualtype_t
-                as *mut cairo_sys::xcb_visualtype_t,
+            &mut visual.base as *mut xcb::ffi::xcb_visualtype_t as *mut cairo_sys::xcb_visualtype_t,
         )
     };
     let drawable = cairo::XCBDrawable(id);
@@ -65,117 +81,1245 @@ fn cairo_surface_for_xcb_window(
 /// # use cnx::{Cnx, Position};
 /// let mut cnx = Cnx::new(Position::Top);
 /// ```
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Position {
     /// Position the Cnx bar at the top of the screen.
     Top,
     /// Position the Cnx bar at the bottom of the screen.
     Bottom,
+    /// Position the Cnx bar on the left of the screen.
+    ///
+    /// Widgets are stacked vertically: those in [`Region::Left`] at the top,
+    /// and those in [`Region::Right`] at the bottom. The bar is as wide as its
+    /// widest text, unless its [`BarStyle`] says otherwise.
+    ///
+    /// [`Region::Left`]: enum.Region.html#variant.Left
+    /// [`Region::Right`]: enum.Region.html#variant.Right
+    /// [`BarStyle`]: struct.BarStyle.html
+    Left,
+    /// Position the Cnx bar on the right of the screen. Widgets are stacked
+    /// vertically, as for [`Position::Left`].
+    ///
+    /// [`Position::Left`]: #variant.Left
+    Right,
+}
+
+/// How tall the bar is, as part of its [`BarStyle`].
+///
+/// For a bar on the left or right of the screen, this is how wide the bar is
+/// instead, and "tallest" below means "widest".
+///
+/// [`BarStyle`]: struct.BarStyle.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BarHeight {
+    /// As tall as the tallest text, plus the border.
+    Fit,
+    /// Exactly this many pixels tall, including the border.
+    Fixed(u16),
+    /// As tall as the tallest text plus the border, but at least this many
+    /// pixels tall.
+    Minimum(u16),
+}
+
+/// The appearance of the bar as a whole, as opposed to that of the widgets'
+/// texts.
+///
+/// Passed to [`Cnx::new()`]. A [`Position`] can be passed instead, for a bar
+/// with the default style: the bar is as tall as its tallest text and spans
+/// the full width of the monitor, without a background color, border or
+/// rounded corners.
+///
+/// Parts of the bar without a background color are transparent if
+/// [`Cnx::set_transparent()`] is used, and black otherwise. The same goes for
+/// the space outside of rounded corners.
+///
+/// [`Cnx::new()`]: struct.Cnx.html#method.new
+/// [`Position`]: enum.Position.html
+/// [`Cnx::set_transparent()`]: struct.Cnx.html#method.set_transparent
+///
+/// # Examples
+///
+/// A "floating" bar, which leaves a gap between itself and the edges of the
+/// monitor:
+///
+/// ```
+/// # use cnx::{BarHeight, BarStyle, Cnx, Position};
+/// # use cnx::text::{Color, Padding};
+/// # fn run() -> ::cnx::Result<()> {
+/// let style = BarStyle::new(Position::Top)
+///     .with_background(Color::from_hex("#282828")?)
+///     .with_margins(Padding::new(8.0, 8.0, 8.0, 0.0))
+///     .with_border(2.0, Color::from_hex("#458588")?)
+///     .with_corner_radius(6.0)
+///     .with_height(BarHeight::Minimum(32));
+/// let mut cnx = Cnx::new(style)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct BarStyle {
+    position: Position,
+    background: Option<Color>,
+    margins: Padding,
+    border_width: f64,
+    border_color: Color,
+    corner_radius: f64,
+    height: BarHeight,
+    rotate_text: bool,
+    separator: Option<Separator>,
+}
+
+impl BarStyle {
+    /// Creates the default style for a bar at the given position.
+    pub fn new(position: Position) -> BarStyle {
+        BarStyle {
+            position,
+            background: None,
+            margins: Padding::new(0.0, 0.0, 0.0, 0.0),
+            border_width: 0.0,
+            border_color: Color::black(),
+            corner_radius: 0.0,
+            height: BarHeight::Fit,
+            rotate_text: false,
+            separator: None,
+        }
+    }
+
+    /// Sets the color drawn behind the widgets' texts.
+    pub fn with_background(mut self, color: Color) -> BarStyle {
+        self.background = Some(color);
+        self
+    }
+
+    /// Sets the space left between the bar and each edge of the monitor. The
+    /// space reserved for the bar includes the margins either side of it.
+    pub fn with_margins(mut self, margins: Padding) -> BarStyle {
+        self.margins = margins;
+        self
+    }
+
+    /// Draws a border of the given width (in pixels) and color around the
+    /// bar. The widgets' texts are laid out inside the border.
+    pub fn with_border(mut self, width: f64, color: Color) -> BarStyle {
+        self.border_width = width.max(0.0);
+        self.border_color = color;
+        self
+    }
+
+    /// Rounds the corners of the bar's background and border.
+    pub fn with_corner_radius(mut self, radius: f64) -> BarStyle {
+        self.corner_radius = radius.max(0.0);
+        self
+    }
+
+    /// Sets how tall the bar is, or how wide it is if it's on the left or
+    /// right of the screen.
+    pub fn with_height(mut self, height: BarHeight) -> BarStyle {
+        self.height = height;
+        self
+    }
+
+    /// Sets whether texts are turned a quarter turn clockwise, to read down
+    /// the bar, if it's on the left or right of the screen. This makes for a
+    /// narrower bar. Texts are never rotated on a bar at the top or bottom.
+    pub fn with_rotated_text(mut self, rotate_text: bool) -> BarStyle {
+        self.rotate_text = rotate_text;
+        self
+    }
+
+    /// Draws the given [`Separator`] between adjacent widgets in the same
+    /// region. Widgets which aren't showing anything aren't separated.
+    ///
+    /// [`Separator`]: enum.Separator.html
+    pub fn with_separator(mut self, separator: Separator) -> BarStyle {
+        self.separator = Some(separator);
+        self
+    }
+
+    /// Returns whether the bar runs down the left or right of the screen.
+    pub(crate) fn is_vertical(&self) -> bool {
+        match self.position {
+            Position::Top | Position::Bottom => false,
+            Position::Left | Position::Right => true,
+        }
+    }
+
+    /// Returns the color to draw behind texts without a background color.
+    pub(crate) fn background(&self) -> Color {
+        self.background.clone().unwrap_or_else(Color::transparent)
+    }
+
+    /// Returns the thickness (height, or width for a vertical bar) of a bar
+    /// whose thickest text is `text_thickness`.
+    fn thickness(&self, text_thickness: f64) -> f64 {
+        let fit = text_thickness + 2.0 * self.border_width;
+        match self.height {
+            BarHeight::Fit => fit,
+            BarHeight::Fixed(height) => f64::from(height),
+            BarHeight::Minimum(height) => fit.max(f64::from(height)),
+        }
+    }
+
+    /// Paints the background and border of a bar of the given size.
+    fn paint(&self, context: &cairo::Context, width: f64, height: f64) {
+        if let Some(ref background) = self.background {
+            rounded_rectangle(context, 0.0, 0.0, width, height, self.corner_radius);
+            background.apply_to_context(context);
+            context.fill();
+        }
+        if self.border_width > 0.0 {
+            // Strokes are centred on the path, so inset it by half the width
+            // of the border to keep the border within the bar.
+            let inset = self.border_width / 2.0;
+            rounded_rectangle(
+                context,
+                inset,
+                inset,
+                width - self.border_width,
+                height - self.border_width,
+                self.corner_radius - inset,
+            );
+            self.border_color.apply_to_context(context);
+            context.set_line_width(self.border_width);
+            context.stroke();
+        }
+    }
+}
+
+impl From<Position> for BarStyle {
+    fn from(position: Position) -> BarStyle {
+        BarStyle::new(position)
+    }
+}
+
+/// What's drawn between adjacent widgets, as part of the bar's [`BarStyle`].
+///
+/// [`BarStyle`]: struct.BarStyle.html
+///
+/// # Examples
+///
+/// ```
+/// # use cnx::{BarStyle, Cnx, Position, Separator};
+/// # use cnx::text::Color;
+/// # fn run() -> ::cnx::Result<()> {
+/// let style = BarStyle::new(Position::Top).with_separator(Separator::Line {
+///     width: 1.0,
+///     color: Color::from_hex("#665c54")?,
+///     gap: 4.0,
+/// });
+/// let mut cnx = Cnx::new(style)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub enum Separator {
+    /// Leaves a gap of this many pixels between widgets.
+    Space(f64),
+    /// Draws this text between widgets, such as `"|"` or `"•"`.
+    Text(String, Attributes),
+    /// Draws a line across the bar, with a gap either side of it.
+    Line {
+        /// The width of the line, in pixels.
+        width: f64,
+        /// The color of the line.
+        color: Color,
+        /// The space either side of the line, in pixels.
+        gap: f64,
+    },
+    /// Draws a powerline-style arrow of the given width, in pixels.
+    ///
+    /// The arrow is filled with the background colors of the widgets either
+    /// side of it, so that the widget nearer the middle of the bar appears to
+    /// end in a point. In [`Region::Right`], arrows point towards the start of
+    /// the bar instead.
+    ///
+    /// [`Region::Right`]: enum.Region.html#variant.Right
+    Arrow(f64),
+}
+
+impl Separator {
+    /// Returns the text drawn by a `Separator::Text`, ready to be laid out.
+    fn compute_text(
+        &self,
+        surface: &cairo::Surface,
+        rotated: bool,
+    ) -> Result<Option<ComputedText>> {
+        let (text, attr) = match *self {
+            Separator::Text(ref text, ref attr) => (text, attr),
+            _ => return Ok(None),
+        };
+        let text = Text::new(attr.clone(), text.clone());
+        let mut computed = text.compute(surface)?;
+        computed.rotated = rotated;
+        Ok(Some(computed))
+    }
+
+    /// Returns the space the separator takes up along the bar, given the text
+    /// returned by `compute_text()`.
+    fn length(&self, text: Option<&ComputedText>, vertical: bool) -> f64 {
+        match *self {
+            Separator::Space(width) | Separator::Arrow(width) => width,
+            Separator::Text(..) => text.map_or(0.0, |text| extents(text, vertical).0),
+            Separator::Line { width, gap, .. } => width + 2.0 * gap,
+        }
+    }
+
+    /// Draws the separator in the space it's been given.
+    fn render(
+        &self,
+        context: &cairo::Context,
+        text: Option<&ComputedText>,
+        place: &SeparatorPlace<'_>,
+    ) -> Result<()> {
+        match *self {
+            Separator::Space(_) => {}
+            Separator::Text(..) => {
+                if let Some(text) = text {
+                    let mut text = text.clone();
+                    let (length, thickness) = extents(&text, place.vertical);
+                    // Squeeze the text if the bar is short of space.
+                    if length > place.length {
+                        if place.vertical && !text.rotated {
+                            text.height = place.length;
+                        } else {
+                            text.width = place.length;
+                        }
+                    }
+                    let along = ((place.length - length.min(place.length)) / 2.0).round();
+                    let across = ((place.thickness - thickness) / 2.0).round();
+                    let (x, y) = place.point(along, across);
+                    text.x = x;
+                    text.y = y;
+                    text.render(&context.get_target(), place.background)?;
+                }
+            }
+            Separator::Line {
+                width,
+                ref color,
+                gap,
+            } => {
+                let (x0, y0) = place.point(gap.min(place.length), 0.0);
+                let (x1, y1) = place.point((gap + width).min(place.length), place.thickness);
+                context.rectangle(x0, y0, x1 - x0, y1 - y0);
+                color.apply_to_context(context);
+                context.fill();
+            }
+            Separator::Arrow(_) => {
+                // The point of the arrow belongs to the widget it comes out
+                // of, and the rest to the widget it points at.
+                let (length, thickness) = (place.length, place.thickness);
+                let (point, rest, corners) = if place.forwards {
+                    (
+                        place.before,
+                        place.after,
+                        [(0.0, 0.0), (length, thickness / 2.0), (0.0, thickness)],
+                    )
+                } else {
+                    (
+                        place.after,
+                        place.before,
+                        [(length, 0.0), (0.0, thickness / 2.0), (length, thickness)],
+                    )
+                };
+
+                // Replace what's there, as texts do, so that translucent
+                // background colors look the same either side of the arrow.
+                context.set_operator(cairo::Operator::Source);
+                let (x0, y0) = place.point(0.0, 0.0);
+                let (x1, y1) = place.point(length, thickness);
+                context.rectangle(x0, y0, x1 - x0, y1 - y0);
+                rest.apply_to_context(context);
+                context.fill();
+                for &(along, across) in &corners {
+                    let (x, y) = place.point(along, across);
+                    context.line_to(x, y);
+                }
+                context.close_path();
+                point.apply_to_context(context);
+                context.fill();
+                context.set_operator(cairo::Operator::Over);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The space a separator has been laid out in, and the background colors of
+/// the widgets either side of it.
+struct SeparatorPlace<'a> {
+    /// Where the space starts, along and across the bar.
+    start: (f64, f64),
+    length: f64,
+    thickness: f64,
+    vertical: bool,
+    /// Whether arrows point towards the end of the bar.
+    forwards: bool,
+    before: &'a Color,
+    after: &'a Color,
+    background: &'a Color,
+}
+
+impl<'a> SeparatorPlace<'a> {
+    /// Returns the point `along` and `across` the space, as `(x, y)`.
+    fn point(&self, along: f64, across: f64) -> (f64, f64) {
+        let (along, across) = (self.start.0 + along, self.start.1 + across);
+        if self.vertical {
+            (across, along)
+        } else {
+            (along, across)
+        }
+    }
+}
+
+/// Adds a rectangle with rounded corners to the current path.
+fn rounded_rectangle(
+    context: &cairo::Context,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    radius: f64,
+) {
+    let radius = radius.min(width / 2.0).min(height / 2.0).max(0.0);
+    let quarter = f64::consts::FRAC_PI_2;
+    context.new_sub_path();
+    context.arc(x + width - radius, y + radius, radius, -quarter, 0.0);
+    context.arc(
+        x + width - radius,
+        y + height - radius,
+        radius,
+        0.0,
+        quarter,
+    );
+    context.arc(
+        x + radius,
+        y + height - radius,
+        radius,
+        quarter,
+        2.0 * quarter,
+    );
+    context.arc(x + radius, y + radius, radius, 2.0 * quarter, 3.0 * quarter);
+    context.close_path();
+}
+
+/// An enum specifying the region of the bar in which a widget is shown.
+///
+/// Passed to [`Cnx::add_widget_to()`] (or [`cnx_add_widget!()`]) when adding
+/// a widget to a [`Cnx`] instance. Widgets in the same region are laid out
+/// left-to-right in the order they were added.
+///
+/// The centre region stays centred on the bar, regardless of the width of the
+/// left and right regions, unless doing so would make it overlap one of them.
+/// In that case it is moved towards the side with more space. If there isn't
+/// enough space for all three regions, the centre region is squeezed first,
+/// followed by the left region, so that the right region is always shown in
+/// full.
+///
+/// Stretch texts in the left or right region grow to fill the space between
+/// their region and the next region. Stretch texts in the centre region grow
+/// equally in both directions, so that it remains centred.
+///
+/// [`Cnx::add_widget_to()`]: struct.Cnx.html#method.add_widget_to
+/// [`cnx_add_widget!()`]: macro.cnx_add_widget.html
+/// [`Cnx`]: struct.Cnx.html
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use]
+/// # extern crate cnx;
+/// #
+/// # use cnx::*;
+/// # use cnx::text::*;
+/// # use cnx::widgets::*;
+/// #
+/// # fn run() -> ::cnx::Result<()> {
+/// let attr = Attributes {
+///     font: Font::new("SourceCodePro 21"),
+///     fg_color: Color::white(),
+///     bg_color: None,
+///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+/// };
+///
+/// let mut cnx = Cnx::new(Position::Top)?;
+/// cnx_add_widget!(cnx, Region::Center, ActiveWindowTitle::new(&cnx, attr.clone()));
+/// cnx_add_widget!(cnx, Region::Right, Clock::new(&cnx, attr.clone()));
+/// # Ok(())
+/// # }
+/// # fn main() { run().unwrap(); }
+/// ```
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Region {
+    /// Show the widget in the region aligned to the left edge of the bar.
+    Left,
+    /// Show the widget in the region centred on the bar.
+    Center,
+    /// Show the widget in the region aligned to the right edge of the bar.
+    Right,
+}
+
+/// Returns how much space a text takes up along the bar, and across it.
+fn extents(text: &ComputedText, vertical: bool) -> (f64, f64) {
+    let (width, height) = text.size();
+    if vertical {
+        (height, width)
+    } else {
+        (width, height)
+    }
+}
+
+/// Returns the background color of a text, or the bar's background if it
+/// doesn't have one of its own.
+fn bg_color<'a>(text: Option<&'a ComputedText>, background: &'a Color) -> &'a Color {
+    text.and_then(|text| text.attr.bg_color.as_ref())
+        .unwrap_or(background)
+}
+
+/// The width of a text and whether it should stretch, used as the input to
+/// `layout_regions()`. On a vertical bar, the "width" is the text's length
+/// down the bar.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Span {
+    width: f64,
+    stretch: bool,
+}
+
+/// Lays out the texts of the left, centre and right regions on a bar of the
+/// given width, returning the `(x, width)` of each text in each region.
+///
+/// See the documentation of [`Region`] for the rules used to lay out and
+/// squeeze the regions.
+fn layout_regions(bar_width: f64, regions: [&[Span]; 3]) -> [Vec<(f64, f64)>; 3] {
+    let fixed_width =
+        |spans: &[Span]| -> f64 { spans.iter().filter(|s| !s.stretch).map(|s| s.width).sum() };
+    let num_stretched = |spans: &[Span]| spans.iter().filter(|s| s.stretch).count();
+
+    let [left, center, right] = regions;
+    let right_width = fixed_width(right);
+    // If there isn't room for everything, squeeze the centre and then the
+    // left region. Squeezed texts will be ellipsized when rendered.
+    let mut left_width = fixed_width(left);
+    let mut center_width = fixed_width(center);
+    let center_scale = scale_to_fit(center_width, bar_width - left_width - right_width);
+    center_width *= center_scale;
+    let left_scale = scale_to_fit(left_width, bar_width - right_width);
+    left_width *= left_scale;
+
+    let (mut left_gap, mut right_gap, center_x, center_stretch) = if center.is_empty() {
+        // Without a centre region, the left and right regions share all of the
+        // free space between them.
+        let free = (bar_width - left_width - right_width).max(0.0);
+        let per_stretched = free / (num_stretched(left) + num_stretched(right)) as f64;
+        let left_gap = per_stretched * num_stretched(left) as f64;
+        let right_gap = per_stretched * num_stretched(right) as f64;
+        (left_gap, right_gap, left_width + left_gap, 0.0)
+    } else {
+        // Centre the centre region, but keep it clear of the other regions.
+        let center_x = ((bar_width - center_width) / 2.0)
+            .min(bar_width - right_width - center_width)
+            .max(left_width);
+        let mut left_gap = center_x - left_width;
+        let mut right_gap = bar_width - right_width - center_x - center_width;
+        // Stretch the centre region equally in both directions.
+        let mut center_stretch = 0.0;
+        if num_stretched(center) > 0 {
+            let half = left_gap.min(right_gap).max(0.0);
+            left_gap -= half;
+            right_gap -= half;
+            center_stretch = half * 2.0;
+        }
+        (
+            left_gap,
+            right_gap,
+            center_x - center_stretch / 2.0,
+            center_stretch,
+        )
+    };
+    // Only stretch texts take up the free space next to a region.
+    if num_stretched(left) == 0 {
+        left_gap = 0.0;
+    }
+    if num_stretched(right) == 0 {
+        right_gap = 0.0;
+    }
+    left_gap = left_gap.max(0.0);
+    right_gap = right_gap.max(0.0);
+
+    let place = |spans: &[Span], mut x: f64, scale: f64, stretch: f64| {
+        let per_stretched = stretch / num_stretched(spans) as f64;
+        spans
+            .iter()
+            .map(|span| {
+                let width = if span.stretch {
+                    per_stretched
+                } else {
+                    span.width * scale
+                };
+                let placed = (x, width);
+                x += width;
+                placed
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let right_x = bar_width - right_width - right_gap;
+    [
+        place(left, 0.0, left_scale, left_gap),
+        place(center, center_x, center_scale, center_stretch),
+        place(right, right_x, 1.0, right_gap),
+    ]
+}
+
+/// Returns the factor by which texts of the given `width` must be scaled to
+/// fit in the `available` space.
+fn scale_to_fit(width: f64, available: f64) -> f64 {
+    if width > available && width > 0.0 {
+        available.max(0.0) / width
+    } else {
+        1.0
+    }
+}
+
+/// A monitor (a RandR output with an active CRTC) on which a bar is shown.
+#[derive(Clone, Debug, PartialEq)]
+struct Monitor {
+    name: String,
+    x: i16,
+    y: i16,
+    width: u16,
+    height: u16,
+    primary: bool,
+}
+
+/// Returns the monitors attached to the given `screen`.
+///
+/// Outputs which are disconnected or have no CRTC are ignored, as are outputs
+/// which mirror a CRTC we've already seen. If RandR isn't available (or
+/// doesn't tell us about any monitors), a single monitor spanning the entire
+/// screen is returned instead.
+fn monitors(conn: &xcb::Connection, screen: &xcb::Screen<'_>) -> Vec<Monitor> {
+    let root = screen.root();
+    let whole_screen = || {
+        vec![Monitor {
+            name: "default".to_owned(),
+            x: 0,
+            y: 0,
+            width: screen.width_in_pixels(),
+            height: screen.height_in_pixels(),
+            primary: true,
+        }]
+    };
+
+    let resources = match randr::get_screen_resources_current(conn, root).get_reply() {
+        Ok(resources) => resources,
+        Err(_) => {
+            debug!("RandR unavailable, using a single bar for the whole screen");
+            return whole_screen();
+        }
+    };
+    let primary = randr::get_output_primary(conn, root)
+        .get_reply()
+        .map(|reply| reply.output())
+        .unwrap_or(0);
+
+    let timestamp = resources.config_timestamp();
+    let mut crtcs_seen = Vec::new();
+    let mut monitors = Vec::new();
+    for &output in resources.outputs() {
+        let info = match randr::get_output_info(conn, output, timestamp).get_reply() {
+            Ok(info) => info,
+            Err(_) => continue,
+        };
+        let crtc = info.crtc();
+        if info.connection() != randr::CONNECTION_CONNECTED as u8
+            || crtc == 0
+            || crtcs_seen.contains(&crtc)
+        {
+            continue;
+        }
+        let crtc_info = match randr::get_crtc_info(conn, crtc, timestamp).get_reply() {
+            Ok(crtc_info) => crtc_info,
+            Err(_) => continue,
+        };
+        crtcs_seen.push(crtc);
+        monitors.push(Monitor {
+            name: String::from_utf8_lossy(info.name()).into_owned(),
+            x: crtc_info.x(),
+            y: crtc_info.y(),
+            width: crtc_info.width(),
+            height: crtc_info.height(),
+            primary: output == primary,
+        });
+    }
+
+    if monitors.is_empty() {
+        return whole_screen();
+    }
+    // Order the monitors left-to-right (and then top-to-bottom), so that the
+    // order of the bars is stable regardless of the order of RandR's outputs.
+    monitors.sort_by_key(|m| (m.x, m.y));
+    monitors
+}
+
+/// Manages one [`Bar`] for each monitor and keeps them up to date.
+///
+/// All bars share the X connection used by widgets. When monitors are added,
+/// removed or resized, the set of bars is updated to match.
+pub struct Bars {
+    conn: Rc<x11::Connection>,
+    screen_idx: usize,
+    watcher: WindowWatcher,
+    events: Option<Subscription<xcb::GenericEvent>>,
+    style: BarStyle,
+    outputs: Option<Vec<String>>,
+    transparent: bool,
+    randr_first_event: Option<u8>,
+    bars: Vec<Bar<XcbWindow>>,
+    regions: Vec<Region>,
+    contents: Vec<Vec<Text>>,
+    event_senders: EventSenders,
+    hidden: bool,
 }
 
-pub struct Bar {
-    conn: Rc<ewmh::Connection>,
+impl Bars {
+    pub fn new(
+        conn: Rc<x11::Connection>,
+        style: BarStyle,
+        outputs: Option<Vec<String>>,
+        transparent: bool,
+    ) -> Result<Bars> {
+        let screen_idx = conn.screen_idx() as usize;
+
+        let randr_first_event = conn
+            .get_extension_data(randr::id())
+            .filter(|data| data.present())
+            .map(|data| data.first_event());
+        if randr_first_event.is_some() {
+            // We need to tell the server which version of RandR we speak before
+            // it'll let us use GetScreenResourcesCurrent.
+            randr::query_version(&conn, 1, 3)
+                .get_reply()
+                .context("Failed to query RandR version")?;
+            let mask = randr::NOTIFY_MASK_SCREEN_CHANGE
+                | randr::NOTIFY_MASK_CRTC_CHANGE
+                | randr::NOTIFY_MASK_OUTPUT_CHANGE;
+            randr::select_input(&conn, conn.root(), mask as u16);
+        }
+
+        // RandR's events aren't about any particular window, so they're given
+        // to whoever watches the root window.
+        let (watcher, events) = conn.watch_windows();
+        watcher.watch(conn.root());
+
+        let mut bars = Bars {
+            conn,
+            screen_idx,
+            watcher,
+            events: Some(events),
+            style,
+            outputs,
+            transparent,
+            randr_first_event,
+            bars: Vec::new(),
+            regions: Vec::new(),
+            contents: Vec::new(),
+            event_senders: EventSenders::default(),
+            hidden: false,
+        };
+        bars.update_monitors()?;
+        Ok(bars)
+    }
+
+    /// Returns the monitors we should show a bar on, in the order they should
+    /// be shown.
+    fn monitors(&self) -> Result<Vec<Monitor>> {
+        let screen = self
+            .conn
+            .get_setup()
+            .roots()
+            .nth(self.screen_idx)
+            .ok_or_else(|| format_err!("Invalid screen"))?;
+        let monitors = monitors(&self.conn, &screen);
+
+        let outputs = match self.outputs {
+            Some(ref outputs) => outputs,
+            None => return Ok(monitors),
+        };
+        let selected: Vec<Monitor> = outputs
+            .iter()
+            .filter_map(|name| monitors.iter().find(|m| &m.name == name).cloned())
+            .collect();
+        if selected.is_empty() {
+            warn!(
+                "None of the requested outputs ({}) are connected",
+                outputs.join(", ")
+            );
+        }
+        Ok(selected)
+    }
+
+    /// Creates, resizes or destroys bars so that there is one for each
+    /// monitor we should be shown on.
+    fn update_monitors(&mut self) -> Result<()> {
+        let monitors = self.monitors()?;
+        // Embedded windows (e.g. the system tray) can only be shown on one
+        // bar, so we show them on the primary monitor's bar (or the first bar).
+        let primary = monitors.iter().position(|m| m.primary).unwrap_or(0);
+        let primary_name = monitors.get(primary).map(|m| m.name.clone());
+        let mut old_bars = mem::take(&mut self.bars);
+
+        // Destroy bars for monitors that have gone away, and take embedded
+        // windows away from any bar that no longer shows them. We do this
+        // first, so that we don't take windows away from the bar that's about
+        // to show them.
+        let watcher = &self.watcher;
+        old_bars.retain(|bar| {
+            let keep = monitors.iter().any(|m| m.name == bar.backend.monitor.name);
+            if !keep {
+                debug!("Destroying bar for monitor {}", bar.backend.monitor.name);
+                watcher.unwatch(bar.backend.window_id);
+            }
+            keep
+        });
+        for bar in &mut old_bars {
+            if Some(&bar.backend.monitor.name) != primary_name.as_ref() {
+                bar.backend.set_show_embeds(false);
+            }
+        }
+
+        for (i, monitor) in monitors.into_iter().enumerate() {
+            let show_embeds = i == primary;
+            let existing = old_bars
+                .iter()
+                .position(|bar| bar.backend.monitor.name == monitor.name);
+            let bar = match existing {
+                Some(idx) => {
+                    let mut bar = old_bars.remove(idx);
+                    let mut redraw = bar.backend.show_embeds != show_embeds;
+                    bar.backend.set_show_embeds(show_embeds);
+                    if bar.backend.monitor != monitor {
+                        debug!("Monitor {} changed: {:?}", monitor.name, monitor);
+                        bar.backend.set_monitor(monitor)?;
+                        redraw = true;
+                    }
+                    if redraw && bar.backend.is_mapped() {
+                        bar.redraw_entire_bar()?;
+                    }
+                    bar
+                }
+                None => {
+                    debug!("Creating bar for monitor {}: {:?}", monitor.name, monitor);
+                    let window = XcbWindow::new(
+                        self.conn.clone(),
+                        self.screen_idx,
+                        self.style.clone(),
+                        monitor,
+                        self.hidden,
+                        self.transparent,
+                    )?;
+                    self.watcher.watch(window.window_id);
+                    let mut bar = Bar::new(window, self.style.clone());
+                    bar.backend.set_show_embeds(show_embeds);
+                    bar.reset_contents(&self.regions);
+                    if self.contents.iter().any(|texts| !texts.is_empty()) {
+                        let update = self.contents.iter().cloned().map(Some).collect();
+                        bar.update_widget_contents(update)?;
+                        bar.redraw_entire_bar()?;
+                    }
+                    bar
+                }
+            };
+            self.bars.push(bar);
+        }
+
+        self.conn.flush();
+        Ok(())
+    }
+
+    fn handle_xcb_event(&mut self, event: &xcb::GenericEvent) -> Result<()> {
+        let response_type = event.response_type() & !0x80;
+
+        if response_type == xcb::EXPOSE {
+            let event: &xcb::ExposeEvent = unsafe { xcb::cast_event(event) };
+            if let Some(bar) = self
+                .bars
+                .iter_mut()
+                .find(|b| b.backend.window_id == event.window())
+            {
+                bar.redraw_entire_bar()?;
+            }
+        } else if response_type == xcb::BUTTON_PRESS || response_type == xcb::BUTTON_RELEASE {
+            // Button press and release events have the same layout.
+            let event: &xcb::ButtonPressEvent = unsafe { xcb::cast_event(event) };
+            let button = Button::from_x11(event.detail());
+            // Scrolling generates a press immediately followed by a release,
+            // so we only pass on the press.
+            if response_type == xcb::BUTTON_RELEASE && button.is_scroll() {
+                return Ok(());
+            }
+
+            let bar = self
+                .bars
+                .iter()
+                .find(|b| b.backend.window_id == event.event());
+            let x = f64::from(event.event_x());
+            let y = f64::from(event.event_y());
+            if let Some((widget_idx, index, text)) = bar.and_then(|bar| bar.text_at(x, y)) {
+                let (x, y) = (x - text.x, y - text.y);
+                // Give rotated texts the position as the text reads.
+                let (x, y) = if text.rotated {
+                    (y, text.height - x)
+                } else {
+                    (x, y)
+                };
+                let click = Click {
+                    button,
+                    modifiers: Modifiers::from_x11(event.state()),
+                    index,
+                    x,
+                    y,
+                };
+                let event = if response_type == xcb::BUTTON_PRESS {
+                    Event::ButtonPress(click)
+                } else {
+                    Event::ButtonRelease(click)
+                };
+                self.event_senders.send(widget_idx, event);
+            }
+        } else if let Some(first_event) = self.randr_first_event {
+            if response_type == first_event + randr::SCREEN_CHANGE_NOTIFY
+                || response_type == first_event + randr::NOTIFY
+            {
+                self.update_monitors()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn update_widget_contents(&mut self, new_contents: Vec<Option<Vec<Text>>>) -> Result<()> {
+        for (new, old) in new_contents.iter().zip(self.contents.iter_mut()) {
+            if let Some(new) = new {
+                *old = new.clone();
+            }
+        }
+
+        for bar in &mut self.bars {
+            if bar.update_widget_contents(new_contents.clone())? {
+                bar.redraw_entire_bar()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the widgets shown on every bar. The bars are redrawn as
+    /// soon as the new widgets produce their texts.
+    fn set_widgets(&mut self, regions: Vec<Region>, widget_list: &WidgetList) {
+        self.contents = vec![Vec::new(); regions.len()];
+        for bar in &mut self.bars {
+            bar.reset_contents(&regions);
+        }
+        self.regions = regions;
+        self.event_senders = widget_list.event_senders();
+    }
+
+    /// Moves and resizes every bar to match a new style. They're redrawn in
+    /// it as soon as their widgets produce texts.
+    fn set_style(&mut self, style: BarStyle) -> Result<()> {
+        if style == self.style {
+            return Ok(());
+        }
+        debug!("Changing bar style: {:?}", style);
+        for bar in &mut self.bars {
+            bar.set_style(style.clone());
+            bar.backend.set_style(style.clone())?;
+        }
+        self.style = style;
+        Ok(())
+    }
+
+    pub async fn run_event_loop(
+        mut self,
+        regions: Vec<Region>,
+        mut widget_list: WidgetList,
+        mut reloads: Reloads,
+        mut requests: Requests,
+    ) -> Result<()> {
+        self.set_widgets(regions, &widget_list);
+
+        // We take ownership of self, so this can only be called once.
+        let mut events = self.events.take().expect("Event loop run more than once");
+
+        loop {
+            let result = tokio::select! {
+                Some(event) = events.next() => event.and_then(|event| self.handle_xcb_event(&event)),
+                Some(update) = widget_list.next() => self.update_widget_contents(update),
+                Some((style, regions, new_widget_list)) = reloads.next() => {
+                    // Stop the old widgets before starting the new ones, so
+                    // that they can give up anything the new ones might need
+                    // (such as the system tray selection).
+                    widget_list = new_widget_list;
+                    self.set_widgets(regions, &widget_list);
+                    self.set_style(style)
+                }
+                Some((request, responder)) = requests.next() => {
+                    ipc::handle_request(&mut self, &mut widget_list, request).map(|response| {
+                        let _ = responder.send(response);
+                    })
+                }
+                else => break,
+            };
+            if let Err(e) = result {
+                error!("Error redrawing bar: {}", e);
+                return Err(e);
+            }
+            self.conn.flush();
+        }
+
+        Ok(())
+    }
+}
+
+impl ipc::Control for Bars {
+    fn is_hidden(&self) -> bool {
+        self.hidden
+    }
+
+    fn set_hidden(&mut self, hidden: bool) -> Result<()> {
+        self.hidden = hidden;
+        for bar in &mut self.bars {
+            bar.backend.set_hidden(hidden);
+        }
+        Ok(())
+    }
+
+    fn contents(&self) -> (&[Region], &[Vec<Text>]) {
+        (&self.regions, &self.contents)
+    }
+}
+
+/// Something a [`Bar`] can render itself to.
+///
+/// The bar itself only knows how to lay out and render its texts to a Cairo
+/// surface. The backend owns that surface and takes care of anything else
+/// needed to show it, such as managing an X window.
+pub(crate) trait Backend {
+    /// Returns the surface to render to.
+    fn surface(&self) -> &cairo::Surface;
+
+    /// Returns the length of the bar, in pixels: its width, or its height if
+    /// it's vertical.
+    fn length(&self) -> u16;
+
+    /// Resizes the bar to the given thickness: its height, or its width if
+    /// it's vertical. This is called before each time the entire bar is
+    /// redrawn, so the surface may be replaced if needed.
+    fn set_thickness(&mut self, thickness: u16) -> Result<()>;
+
+    /// Shows the given windows over the space taken by their texts. Each
+    /// window is given as its `(id, x, y, width, height)`.
+    fn place_embedded_windows(&mut self, _embeds: &[(u32, f64, f64, f64, f64)]) {}
+}
+
+/// A bar shown in an X window on one monitor.
+struct XcbWindow {
+    conn: Rc<x11::Connection>,
     window_id: u32,
     screen_idx: usize,
     surface: cairo::Surface,
-    width: u16,
-    height: u16,
-    position: Position,
-    contents: Vec<Vec<ComputedText>>,
+    monitor: Monitor,
+    thickness: u16,
+    mapped: bool,
+    hidden: bool,
+    show_embeds: bool,
+    embedded: Vec<u32>,
+    style: BarStyle,
+    // The colormap we created for our ARGB visual, if we're transparent.
+    colormap: Option<u32>,
 }
 
-impl Bar {
-    pub fn new(position: Position) -> Result<Bar> {
-        let (conn, screen_idx) =
-            xcb::Connection::connect(None).context("Failed to connect to X server")?;
-        let screen_idx = screen_idx as usize;
+impl XcbWindow {
+    fn new(
+        conn: Rc<x11::Connection>,
+        screen_idx: usize,
+        style: BarStyle,
+        monitor: Monitor,
+        hidden: bool,
+        transparent: bool,
+    ) -> Result<XcbWindow> {
         let id = conn.generate_id();
 
-        // We don't actually care about how tall our initial window is - we'll resize
+        // We don't actually care about how thick our initial window is - we'll resize
         // our window once we know how big it needs to be. However, it seems to need
         // to be bigger than 0px, or either Xcb/Cairo (or maybe QTile?) gets upset.
-        let height = 1;
+        let thickness = 1;
+        let (width, height) = if style.is_vertical() {
+            (thickness, monitor.height)
+        } else {
+            (monitor.width, thickness)
+        };
 
-        let (width, surface) = {
+        let (surface, colormap) = {
             let screen = conn
                 .get_setup()
                 .roots()
                 .nth(screen_idx)
                 .ok_or_else(|| format_err!("Invalid screen"))?;
-            let values = [
-                (xcb::CW_BACK_PIXEL, screen.black_pixel()),
-                (xcb::CW_EVENT_MASK, xcb::EVENT_MASK_EXPOSURE),
-            ];
 
-            let width = screen.width_in_pixels();
+            // To be transparent, we need a visual with an alpha channel, which
+            // a compositor can use to blend us with whatever is behind us.
+            let argb_visual = if transparent {
+                let visual = get_argb_visual_type(&screen);
+                if visual.is_none() {
+                    warn!("The X server has no 32-bit visual, so the bar can't be transparent");
+                }
+                visual
+            } else {
+                None
+            };
+
+            let mut values = vec![(
+                xcb::CW_EVENT_MASK,
+                xcb::EVENT_MASK_EXPOSURE
+                    | xcb::EVENT_MASK_BUTTON_PRESS
+                    | xcb::EVENT_MASK_BUTTON_RELEASE,
+            )];
+            let (depth, visual, colormap) = match argb_visual {
+                Some(visual) => {
+                    // A window whose visual differs from its parent's needs its
+                    // own colormap and border pixel, or creating it fails with
+                    // BadMatch.
+                    let colormap = conn.generate_id();
+                    xcb::create_colormap(
+                        &conn,
+                        xcb::COLORMAP_ALLOC_NONE as u8,
+                        colormap,
+                        screen.root(),
+                        visual.visual_id(),
+                    );
+                    values.push((xcb::CW_BACK_PIXEL, 0));
+                    values.push((xcb::CW_BORDER_PIXEL, 0));
+                    values.push((xcb::CW_COLORMAP, colormap));
+                    (32, visual, Some(colormap))
+                }
+                None => {
+                    values.push((xcb::CW_BACK_PIXEL, screen.black_pixel()));
+                    let visual = get_root_visual_type(&conn, &screen);
+                    (xcb::COPY_FROM_PARENT as u8, visual, None)
+                }
+            };
 
             xcb::create_window(
                 &conn,
-                xcb::COPY_FROM_PARENT as u8,
+                depth,
                 id,
                 screen.root(),
-                0,
-                0,
+                monitor.x,
+                monitor.y,
                 width,
                 height,
                 0,
                 xcb::WINDOW_CLASS_INPUT_OUTPUT as u16,
-                screen.root_visual(),
+                visual.visual_id(),
                 &values,
             );
 
             let surface = cairo_surface_for_xcb_window(
                 &conn,
-                &screen,
+                visual,
                 id,
                 i32::from(width),
                 i32::from(height),
             );
-
-            (width, surface)
+            (surface, colormap)
         };
 
-        let ewmh_conn = ewmh::Connection::connect(conn)
-            .map_err(|(e, _)| e)
-            .context("Failed to wrap xcb::Connection in ewmh::Connection")?;
-
-        #[allow(clippy::blacklisted_name)]
-        let bar = Bar {
-            conn: Rc::new(ewmh_conn),
+        let window = XcbWindow {
+            conn,
             window_id: id,
             screen_idx,
             surface,
-            width,
-            height,
-            position,
-            contents: Vec::new(),
+            monitor,
+            thickness,
+            mapped: false,
+            hidden,
+            show_embeds: false,
+            embedded: Vec::new(),
+            style,
+            colormap,
         };
-        bar.set_ewmh_properties();
+        window.set_ewmh_properties()?;
         // XXX We can't map the window until we've updated the window size, or nothing
         // gets rendered. I can't tell if this is something we're doing, something Cairo
         // is doing or something QTile is doing. This'll do for now and we'll see what
         // it is like with Lanta!
-        // bar.map_window();
-        bar.flush();
-        Ok(bar)
+        // window.map_window();
+        window.flush();
+        Ok(window)
     }
 
     fn flush(&self) {
         self.conn.flush();
     }
 
-    fn map_window(&self) {
-        xcb::map_window(&self.conn, self.window_id);
+    fn map_window(&mut self) {
+        if !self.hidden {
+            xcb::map_window(&self.conn, self.window_id);
+        }
+        self.mapped = true;
     }
 
-    fn set_ewmh_properties(&self) {
+    /// Hides or shows the window. Whilst it's hidden, the window is unmapped,
+    /// so that the space reserved by its struts is freed up.
+    fn set_hidden(&mut self, hidden: bool) {
+        self.hidden = hidden;
+        // Until the window has been configured, it'll be mapped (or not)
+        // once it is.
+        if !self.mapped {
+            return;
+        }
+        if hidden {
+            xcb::unmap_window(&self.conn, self.window_id);
+        } else {
+            // The window will be redrawn when it's exposed.
+            xcb::map_window(&self.conn, self.window_id);
+        }
+    }
+
+    fn is_mapped(&self) -> bool {
+        self.mapped
+    }
+
+    fn set_ewmh_properties(&self) -> Result<()> {
         ewmh::set_wm_window_type(
             &self.conn,
             self.window_id,
             &[self.conn.WM_WINDOW_TYPE_DOCK()],
         );
 
-        // TODO: Update _WM_STRUT_PARTIAL if the height/position of the bar changes?
+        // The strut is relative to the edge of the whole screen, not the edge of
+        // our monitor, so we need to know how big the screen currently is.
+        let root = self.screen()?.root();
+        let geometry = xcb::get_geometry(&self.conn, root)
+            .get_reply()
+            .context("Could not get screen geometry")?;
+
+        let monitor = &self.monitor;
+        let margins = &self.style.margins;
+        // We reserve the margins either side of the bar too, so that windows
+        // leave a gap around it.
+        let (margin_before, margin_after) = if self.style.is_vertical() {
+            (margins.left, margins.right)
+        } else {
+            (margins.top, margins.bottom)
+        };
+        let reserved = (margin_before + margin_after).max(0.0) as u32 + u32::from(self.thickness);
+        let start_x = u32::from(monitor.x as u16);
+        let end_x = start_x + u32::from(monitor.width) - 1;
+        let start_y = u32::from(monitor.y as u16);
+        let end_y = start_y + u32::from(monitor.height) - 1;
         let mut strut_partial = ewmh::StrutPartial {
             left: 0,
             right: 0,
@@ -190,11 +1334,35 @@ impl Bar {
             bottom_start_x: 0,
             bottom_end_x: 0,
         };
-        match self.position {
-            Position::Top => strut_partial.top = u32::from(self.height),
-            Position::Bottom => strut_partial.bottom = u32::from(self.height),
+        match self.style.position {
+            Position::Top => {
+                strut_partial.top = u32::from(monitor.y as u16) + reserved;
+                strut_partial.top_start_x = start_x;
+                strut_partial.top_end_x = end_x;
+            }
+            Position::Bottom => {
+                let monitor_bottom = i32::from(monitor.y) + i32::from(monitor.height);
+                let below_monitor = (i32::from(geometry.height()) - monitor_bottom).max(0) as u32;
+                strut_partial.bottom = below_monitor + reserved;
+                strut_partial.bottom_start_x = start_x;
+                strut_partial.bottom_end_x = end_x;
+            }
+            Position::Left => {
+                strut_partial.left = u32::from(monitor.x as u16) + reserved;
+                strut_partial.left_start_y = start_y;
+                strut_partial.left_end_y = end_y;
+            }
+            Position::Right => {
+                let monitor_right = i32::from(monitor.x) + i32::from(monitor.width);
+                let right_of_monitor = (i32::from(geometry.width()) - monitor_right).max(0) as u32;
+                strut_partial.right = right_of_monitor + reserved;
+                strut_partial.right_start_y = start_y;
+                strut_partial.right_end_y = end_y;
+            }
         }
         ewmh::set_wm_strut_partial(&self.conn, self.window_id, strut_partial);
+
+        Ok(())
     }
 
     fn screen(&self) -> Result<xcb::Screen<'_>> {
@@ -207,36 +1375,218 @@ impl Bar {
         Ok(screen)
     }
 
-    fn update_bar_height(&mut self, height: u16) -> Result<()> {
-        if self.height != height {
-            self.height = height;
+    /// Returns the width and height of the window.
+    fn size(&self) -> (u16, u16) {
+        if self.style.is_vertical() {
+            (self.thickness, self.length())
+        } else {
+            (self.length(), self.thickness)
+        }
+    }
 
-            // If we're at the bottom of the screen, we'll need to update the
-            // position of the window.
-            let y = match self.position {
-                Position::Top => 0,
-                Position::Bottom => self.screen()?.height_in_pixels() - self.height,
-            };
+    /// Moves/resizes the XCB window and Cairo surface to match our monitor and
+    /// thickness, and updates the EWMH properties to reserve the right space.
+    fn configure_window(&mut self) -> Result<()> {
+        // If we're at the bottom or right of the monitor, we'll need to update
+        // the position of the window whenever its thickness changes.
+        let (width, height) = self.size();
+        let margins = &self.style.margins;
+        let monitor = &self.monitor;
+        let left = monitor.x + margins.left as i16;
+        let top = monitor.y + margins.top as i16;
+        let (x, y) = match self.style.position {
+            Position::Top | Position::Left => (left, top),
+            Position::Bottom => {
+                let bottom = monitor.y + monitor.height as i16 - margins.bottom as i16;
+                (left, bottom - height as i16)
+            }
+            Position::Right => {
+                let right = monitor.x + monitor.width as i16 - margins.right as i16;
+                (right - width as i16, top)
+            }
+        };
+
+        // Update the geometry of the XCB window and the size of the Cairo surface.
+        let values = [
+            (xcb::CONFIG_WINDOW_X as u16, x as u32),
+            (xcb::CONFIG_WINDOW_Y as u16, y as u32),
+            (xcb::CONFIG_WINDOW_WIDTH as u16, u32::from(width)),
+            (xcb::CONFIG_WINDOW_HEIGHT as u16, u32::from(height)),
+            (xcb::CONFIG_WINDOW_STACK_MODE as u16, xcb::STACK_MODE_ABOVE),
+        ];
+        xcb::configure_window(&self.conn, self.window_id, &values);
+        self.map_window();
+        self.surface.set_size(i32::from(width), i32::from(height));
+
+        // Update EWMH properties - we might need to reserve more or less space.
+        self.set_ewmh_properties()
+    }
 
-            // Update the height/position of the XCB window and the height of the Cairo surface.
-            let values = [
-                (xcb::CONFIG_WINDOW_Y as u16, u32::from(y)),
-                (xcb::CONFIG_WINDOW_HEIGHT as u16, u32::from(self.height)),
-                (xcb::CONFIG_WINDOW_STACK_MODE as u16, xcb::STACK_MODE_ABOVE),
-            ];
-            xcb::configure_window(&self.conn, self.window_id, &values);
-            self.map_window();
-            self.surface
-                .set_size(i32::from(self.width), i32::from(self.height));
+    /// Sets whether this bar shows the windows embedded in texts. If it no
+    /// longer does, any windows it is currently showing are released.
+    fn set_show_embeds(&mut self, show_embeds: bool) {
+        self.show_embeds = show_embeds;
+        if !show_embeds {
+            self.release_embedded_windows();
+        }
+    }
 
-            // Update EWMH properties - we might need to reserve more or less space.
-            self.set_ewmh_properties();
+    /// Moves an embedded window to cover the space taken by its text, first
+    /// reparenting it into our window if we haven't already.
+    fn place_embedded_window(&mut self, window: u32, x: f64, y: f64, width: f64, height: f64) {
+        if !self.embedded.contains(&window) {
+            xcb::reparent_window(&self.conn, window, self.window_id, x as i16, y as i16);
+            // If we exit without releasing the window, the X server will give
+            // it back to the root window rather than destroying it with ours.
+            xcb::change_save_set(&self.conn, xcb::SET_MODE_INSERT as u8, window);
+            xcb::map_window(&self.conn, window);
+            self.embedded.push(window);
+        }
+
+        // X doesn't allow windows to be 0px wide or tall.
+        let values = [
+            (xcb::CONFIG_WINDOW_X as u16, x as i32 as u32),
+            (xcb::CONFIG_WINDOW_Y as u16, y as i32 as u32),
+            (xcb::CONFIG_WINDOW_WIDTH as u16, (width as u32).max(1)),
+            (xcb::CONFIG_WINDOW_HEIGHT as u16, (height as u32).max(1)),
+        ];
+        xcb::configure_window(&self.conn, window, &values);
+    }
+
+    /// Gives any embedded windows back to the root window.
+    fn release_embedded_windows(&mut self) {
+        if let Ok(root) = self.screen().map(|screen| screen.root()) {
+            for window in self.embedded.drain(..) {
+                xcb::unmap_window(&self.conn, window);
+                xcb::reparent_window(&self.conn, window, root, 0, 0);
+            }
+        }
+    }
+
+    fn set_style(&mut self, style: BarStyle) -> Result<()> {
+        self.style = style;
+        if self.is_mapped() {
+            self.configure_window()?;
+        }
+        Ok(())
+    }
+
+    fn set_monitor(&mut self, monitor: Monitor) -> Result<()> {
+        self.monitor = monitor;
+        if self.is_mapped() {
+            self.configure_window()?;
+        }
+        Ok(())
+    }
+}
+
+impl Backend for XcbWindow {
+    fn surface(&self) -> &cairo::Surface {
+        &self.surface
+    }
+
+    fn length(&self) -> u16 {
+        let margins = &self.style.margins;
+        let length = if self.style.is_vertical() {
+            f64::from(self.monitor.height) - margins.top - margins.bottom
+        } else {
+            f64::from(self.monitor.width) - margins.left - margins.right
+        };
+        length.max(1.0) as u16
+    }
+
+    fn set_thickness(&mut self, thickness: u16) -> Result<()> {
+        if self.thickness != thickness || !self.is_mapped() {
+            self.thickness = thickness;
+            self.configure_window()?;
         }
 
         Ok(())
     }
 
-    fn update_widget_contents(&mut self, new_contents: Vec<Option<Vec<Text>>>) -> Result<bool> {
+    fn place_embedded_windows(&mut self, embeds: &[(u32, f64, f64, f64, f64)]) {
+        if self.show_embeds {
+            for &(window, x, y, width, height) in embeds {
+                self.place_embedded_window(window, x, y, width, height);
+            }
+        }
+    }
+}
+
+impl Drop for XcbWindow {
+    fn drop(&mut self) {
+        // Don't destroy the windows we've embedded along with our own.
+        self.release_embedded_windows();
+        xcb::destroy_window(&self.conn, self.window_id);
+        if let Some(colormap) = self.colormap {
+            xcb::free_colormap(&self.conn, colormap);
+        }
+    }
+}
+
+/// The contents of a bar, laid out and rendered to a [`Backend`].
+pub(crate) struct Bar<B: Backend> {
+    backend: B,
+    style: BarStyle,
+    regions: Vec<Region>,
+    contents: Vec<Vec<ComputedText>>,
+}
+
+impl<B: Backend> Bar<B> {
+    pub(crate) fn new(backend: B, style: BarStyle) -> Bar<B> {
+        Bar {
+            backend,
+            style,
+            regions: Vec::new(),
+            contents: Vec::new(),
+        }
+    }
+
+    pub(crate) fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub(crate) fn backend_mut(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
+    pub(crate) fn set_style(&mut self, style: BarStyle) {
+        self.style = style;
+    }
+
+    /// Forgets any existing contents and prepares the bar to show widgets in
+    /// the given regions.
+    pub(crate) fn reset_contents(&mut self, regions: &[Region]) {
+        self.regions = regions.to_vec();
+        self.contents = vec![Vec::new(); regions.len()];
+    }
+
+    /// Returns the text at the given position, along with the index of its
+    /// widget and its index within that widget's texts. Only the position
+    /// along the bar is considered, so a text can be clicked anywhere across
+    /// the bar.
+    pub(crate) fn text_at(&self, x: f64, y: f64) -> Option<(usize, usize, &ComputedText)> {
+        let vertical = self.style.is_vertical();
+        self.contents
+            .iter()
+            .enumerate()
+            .flat_map(|(widget_idx, texts)| {
+                texts
+                    .iter()
+                    .enumerate()
+                    .map(move |(index, text)| (widget_idx, index, text))
+            })
+            .find(|&(_, _, text)| {
+                let (start, position) = if vertical { (text.y, y) } else { (text.x, x) };
+                let (length, _) = extents(text, vertical);
+                position >= start && position < start + length
+            })
+    }
+
+    pub(crate) fn update_widget_contents(
+        &mut self,
+        new_contents: Vec<Option<Vec<Text>>>,
+    ) -> Result<bool> {
         // For each widget's texts:
         //  - If they're equal to the previous texts we had for it, do nothing.
         //  - If there are new texts or any non-stretch texts changed size, redraw
@@ -246,7 +1596,11 @@ impl Bar {
 
         // Borrow these here, as otherwise our closures will try to borrow
         // self as both immutable/mutable.
-        let surface = &self.surface;
+        let surface = self.backend.surface();
+        let background = self.style.background();
+        let vertical = self.style.is_vertical();
+        let rotated = vertical && self.style.rotate_text;
+        let arrows = matches!(self.style.separator, Some(Separator::Arrow(_)));
         let contents = &mut self.contents;
 
         let it = new_contents
@@ -259,7 +1613,7 @@ impl Bar {
             // Even if we have actually received an update, it may be identical
             // to the text it gave previously. (If that's the case, we can
             // avoid even calling .compute()).
-            .filter(|&(ref new, ref old)| {
+            .filter(|(new, old)| {
                 let length_different = new.len() != old.len();
                 let all_same = !length_different && new.iter().zip(old.iter()).all(|(n, o)| n == o);
                 !all_same
@@ -269,7 +1623,11 @@ impl Bar {
             // layout information.
             .map(|(new, old)| {
                 new.into_iter()
-                    .map(|text| text.compute(surface))
+                    .map(|text| {
+                        let mut computed = text.compute(surface)?;
+                        computed.rotated = rotated;
+                        Ok(computed)
+                    })
                     .collect::<Result<Vec<ComputedText>>>()
                     .map(|computeds| (computeds, old))
             })
@@ -277,19 +1635,25 @@ impl Bar {
 
         for (mut new_texts, old_texts) in it {
             // Redraw the entire bar if any of widget's non-stretch texts
-            // have changed size, or if the number of texts for this widget
+            // have changed length, or if the number of texts for this widget
             // has changed. (Both of these would affect the size of other
             // stretch texts). Redraw the entire bar if any texts change
-            // height. (It would be better to do this only if this changes
-            // the height of the bar).
+            // thickness. (It would be better to do this only if this changes
+            // the thickness of the bar). Arrow separators are filled with the
+            // background colors of the texts either side of them, so redraw
+            // if those change too.
             let length_different = new_texts.len() != old_texts.len();
             redraw_entire_bar = redraw_entire_bar
                 || length_different
                 || new_texts.iter().zip(old_texts.iter()).any(|(new, old)| {
                     let not_stretch = !new.stretch && !old.stretch;
-                    let diff_width = (new.width - old.width).abs().round() >= 1.0;
-                    let diff_height = (new.height - old.height).abs().round() >= 1.0;
-                    (not_stretch && diff_width) || diff_height
+                    let (new_length, new_thickness) = extents(new, vertical);
+                    let (old_length, old_thickness) = extents(old, vertical);
+                    let diff_length = (new_length - old_length).abs().round() >= 1.0;
+                    let diff_thickness = (new_thickness - old_thickness).abs().round() >= 1.0;
+                    let diff_embed = new.embed != old.embed;
+                    let diff_background = arrows && new.attr.bg_color != old.attr.bg_color;
+                    (not_stretch && diff_length) || diff_thickness || diff_embed || diff_background
                 });
 
             // Where possible, re-use the position of the widget's previous
@@ -317,7 +1681,7 @@ impl Bar {
                     .map(|(n, _)| n);
                 for text in changed {
                     trace!("Redrawing one");
-                    text.render(surface)?;
+                    text.render(surface, &background)?;
                 }
             }
 
@@ -327,176 +1691,252 @@ impl Bar {
         Ok(redraw_entire_bar)
     }
 
-    fn redraw_entire_bar(&mut self) -> Result<()> {
+    pub(crate) fn redraw_entire_bar(&mut self) -> Result<()> {
         trace!("Redraw entire bar");
 
-        // Calculate how much free space we have after laying out all the
-        // non-stretch blocks. Split the remaining space (if any) between the
-        // stretch blocks. If there isn't enough space for the non-stretch blocks
-        // do nothing and allow it to overflow.
-        // While we're at it, we also calculate how
-        let screen_width = f64::from(
-            self.screen()
-                .context("Could not get screen width")?
-                .width_in_pixels(),
-        );
-        let width_per_stretched =
-            {
-                let texts = self.contents.iter().flatten();
-                let (stretched, non_stretched): (Vec<_>, Vec<_>) =
-                    texts.partition(|text| text.stretch);
-                let width = non_stretched.iter().fold(0.0, |acc, text| {
-                    if text.stretch {
-                        0.0
-                    } else {
-                        acc + text.width
-                    }
-                });
-                let remaining_width = (screen_width - width).max(0.0);
-                remaining_width / (stretched.len() as f64)
-            };
+        // Lay out each region's texts within the border, with a separator
+        // between each widget which is showing something. Stretch blocks share
+        // the space left over after laying out the non-stretch blocks. If
+        // there isn't enough space for the non-stretch blocks, they're
+        // squeezed.
+        let border = self.style.border_width;
+        let vertical = self.style.is_vertical();
+        let rotated = vertical && self.style.rotate_text;
+        let bar_length = f64::from(self.backend.length()) - 2.0 * border;
+        let separator_text = match self.style.separator {
+            Some(ref separator) => separator.compute_text(self.backend.surface(), rotated)?,
+            None => None,
+        };
+        let separator_length = self
+            .style
+            .separator
+            .as_ref()
+            .map(|separator| separator.length(separator_text.as_ref(), vertical));
+        let regions = [Region::Left, Region::Center, Region::Right];
+        let widgets: Vec<Vec<usize>> = regions
+            .iter()
+            .map(|region| {
+                (0..self.contents.len())
+                    .filter(|&idx| self.regions[idx] == *region && !self.contents[idx].is_empty())
+                    .collect()
+            })
+            .collect();
+        let spans = |widgets: &[usize]| -> Vec<Span> {
+            let mut spans = Vec::new();
+            for (i, &idx) in widgets.iter().enumerate() {
+                if let (true, Some(length)) = (i > 0, separator_length) {
+                    spans.push(Span {
+                        width: length,
+                        stretch: false,
+                    });
+                }
+                spans.extend(self.contents[idx].iter().map(|text| Span {
+                    width: extents(text, vertical).0,
+                    stretch: text.stretch,
+                }));
+            }
+            spans
+        };
+        let (left, center, right) = (spans(&widgets[0]), spans(&widgets[1]), spans(&widgets[2]));
+        let layouts = layout_regions(bar_length, [&left, &center, &right]);
 
-        // Get the height of the biggest Text and set the bar to be that big.
+        // Get the thickness of the biggest Text and set the bar to be big
+        // enough for it, unless the style says otherwise.
         // TODO: Update all the Layouts so they all render that big too?
-        let height = self
+        let text_thickness = self
             .contents
             .iter()
             .flatten()
-            .fold(f64::NEG_INFINITY, |acc, text| text.height.max(acc));
-        if let Err(e) = self.update_bar_height(height as u16) {
+            .fold(0.0, |acc: f64, text| extents(text, vertical).1.max(acc));
+        let thickness = self.style.thickness(text_thickness);
+        if let Err(e) = self.backend.set_thickness(thickness as u16) {
             // Log and continue - the bar is hopefully still useful.
-            error!("Failed to update bar height to {}: {}", height, e);
+            error!("Failed to update bar thickness to {}: {}", thickness, e);
         }
 
-        // Render each Text in turn. If it's a stretch block, override its width
-        // with the width we've just computed. Regardless of whether it's a stretch
-        // block, override its height - everything should be as big as the biggest item.
-        let texts = self.contents.iter_mut().flatten();
-        let mut x = 0.0;
-        for text in texts {
-            if text.stretch {
-                text.width = width_per_stretched;
+        // Clear the bar, as there may be gaps between the regions which no
+        // text will draw over. We replace rather than paint over what's there,
+        // so that a transparent bar is actually transparent. (Without an alpha
+        // channel, this leaves the bar black).
+        let context = cairo::Context::new(self.backend.surface());
+        context.set_operator(cairo::Operator::Source);
+        Color::transparent().apply_to_context(&context);
+        context.paint();
+        context.set_operator(cairo::Operator::Over);
+        let length = f64::from(self.backend.length());
+        let (width, height) = if vertical {
+            (thickness, length)
+        } else {
+            (length, thickness)
+        };
+        self.style.paint(&context, width, height);
+
+        // Render each Text and separator in turn, at the position we've just
+        // computed for it, centred across the bar within the border.
+        let content_thickness = thickness - 2.0 * border;
+        let background = self.style.background();
+        let mut embeds = Vec::new();
+        for ((region, widgets), layout) in regions.iter().zip(&widgets).zip(layouts.iter()) {
+            let mut layout = layout.iter();
+            for (i, &idx) in widgets.iter().enumerate() {
+                if let (true, Some(separator)) = (i > 0, &self.style.separator) {
+                    let &(position, length) = match layout.next() {
+                        Some(place) => place,
+                        None => break,
+                    };
+                    let place = SeparatorPlace {
+                        start: (border + position, border),
+                        length,
+                        thickness: content_thickness,
+                        vertical,
+                        forwards: *region != Region::Right,
+                        before: bg_color(self.contents[widgets[i - 1]].last(), &background),
+                        after: bg_color(self.contents[idx].first(), &background),
+                        background: &background,
+                    };
+                    separator.render(&context, separator_text.as_ref(), &place)?;
+                }
+                self.render_widget(idx, &mut layout, content_thickness, &mut embeds)?;
             }
-            text.x = x;
-            text.y = 0.0;
-            text.render(&self.surface)?;
-            x += text.width;
         }
 
+        self.backend.place_embedded_windows(&embeds);
+
         Ok(())
     }
 
-    pub fn run_event_loop(
-        mut self,
-        handle: &Handle,
-        widgets: Vec<Box<dyn Widget>>,
-    ) -> Result<Box<dyn Future<Item = (), Error = Error>>> {
-        self.contents = vec![Vec::new(); widgets.len()];
-
-        enum Event {
-            Xcb(<XcbEventStream as Stream>::Item),
-            Widget(<WidgetList as Stream>::Item),
-        };
-
-        let events_stream = XcbEventStream::new(self.conn.clone(), handle)?.map(Event::Xcb);
-        let widget_updates_stream = WidgetList::new(widgets)?.map(Event::Widget);
-        let event_loop = events_stream.select(widget_updates_stream);
-
-        let fut = event_loop.for_each(move |event| {
-            let redraw_entire_bar = match event {
-                Event::Widget(update) => match self.update_widget_contents(update) {
-                    Ok(b) => b,
-                    Err(e) => return future::err(e),
-                },
-                Event::Xcb(event) => event.response_type() & !0x80 == xcb::EXPOSE,
-            };
-
-            if redraw_entire_bar {
-                if let Err(e) = self.redraw_entire_bar() {
-                    error!("Error redrawing bar: {}", e);
-                    return future::err(e);
-                }
+    /// Renders a widget's texts at the positions given by `layout`, noting
+    /// any windows which need to be placed over them.
+    fn render_widget<'a, I: Iterator<Item = &'a (f64, f64)>>(
+        &mut self,
+        idx: usize,
+        layout: I,
+        content_thickness: f64,
+        embeds: &mut Vec<(u32, f64, f64, f64, f64)>,
+    ) -> Result<()> {
+        let border = self.style.border_width;
+        let vertical = self.style.is_vertical();
+        let background = self.style.background();
+        for (text, &(position, length)) in self.contents[idx].iter_mut().zip(layout) {
+            // Vertical texts which aren't rotated are stretched down the
+            // bar, rather than along the text.
+            if vertical && !text.rotated {
+                text.height = length;
+            } else {
+                text.width = length;
             }
-            self.conn.flush();
-
-            future::ok(())
-        });
+            let offset = ((content_thickness - extents(text, vertical).1) / 2.0).round();
+            if vertical {
+                text.x = border + offset;
+                text.y = border + position;
+            } else {
+                text.x = border + position;
+                text.y = border + offset;
+            }
+            text.render(self.backend.surface(), &background)?;
+            if let Some(window) = text.embed {
+                let (width, height) = text.size();
+                embeds.push((window, text.x, text.y, width, height));
+            }
+        }
 
-        Ok(Box::new(fut))
+        Ok(())
     }
 }
 
-struct XcbEvented(Rc<ewmh::Connection>);
+#[cfg(test)]
+mod test {
+    use super::{layout_regions, BarHeight, BarStyle, Position, Separator, Span};
+    use crate::text::Color;
 
-impl XcbEvented {
-    fn fd(&self) -> RawFd {
-        let conn: &xcb::Connection = &self.0;
-        unsafe { xcb::ffi::base::xcb_get_file_descriptor(conn.get_raw_conn()) }
+    fn fixed(width: f64) -> Span {
+        Span {
+            width,
+            stretch: false,
+        }
     }
-}
 
-impl Evented for XcbEvented {
-    fn register(
-        &self,
-        poll: &mio::Poll,
-        token: Token,
-        interest: Ready,
-        opts: PollOpt,
-    ) -> io::Result<()> {
-        EventedFd(&self.fd()).register(poll, token, interest, opts)
+    fn stretch() -> Span {
+        Span {
+            width: 5.0,
+            stretch: true,
+        }
     }
 
-    fn reregister(
-        &self,
-        poll: &mio::Poll,
-        token: Token,
-        interest: Ready,
-        opts: PollOpt,
-    ) -> io::Result<()> {
-        EventedFd(&self.fd()).reregister(poll, token, interest, opts)
+    #[test]
+    fn bar_height_includes_border() {
+        let style = BarStyle::new(Position::Top).with_border(2.0, Color::white());
+        assert_eq!(style.thickness(20.0), 24.0);
+        let style = style.with_height(BarHeight::Minimum(30));
+        assert_eq!(style.thickness(20.0), 30.0);
+        assert_eq!(style.thickness(40.0), 44.0);
+        let style = style.with_height(BarHeight::Fixed(16));
+        assert_eq!(style.thickness(40.0), 16.0);
     }
 
-    fn deregister(&self, poll: &mio::Poll) -> io::Result<()> {
-        EventedFd(&self.fd()).deregister(poll)
+    #[test]
+    fn separators_take_up_space() {
+        let line = Separator::Line {
+            width: 1.0,
+            color: Color::white(),
+            gap: 4.0,
+        };
+        assert_eq!(line.length(None, false), 9.0);
+        assert_eq!(Separator::Arrow(12.0).length(None, true), 12.0);
+        assert_eq!(Separator::Space(6.0).length(None, false), 6.0);
     }
-}
 
-pub(crate) struct XcbEventStream {
-    conn: Rc<ewmh::Connection>,
-    poll: PollEvented<XcbEvented>,
-    would_block: bool,
-}
+    #[test]
+    fn side_bars_are_vertical() {
+        assert!(!BarStyle::new(Position::Bottom).is_vertical());
+        assert!(BarStyle::new(Position::Left).is_vertical());
+        assert!(BarStyle::new(Position::Right).is_vertical());
+    }
 
-impl XcbEventStream {
-    pub fn new(conn: Rc<ewmh::Connection>, handle: &Handle) -> Result<XcbEventStream> {
-        let evented = XcbEvented(conn.clone());
-        Ok(XcbEventStream {
-            conn,
-            poll: PollEvented::new(evented, handle)?,
-            would_block: true,
-        })
+    #[test]
+    fn stretch_fills_free_space() {
+        let left = [fixed(10.0), stretch(), fixed(20.0)];
+        let right = [stretch(), fixed(10.0)];
+        let [left, center, right] = layout_regions(100.0, [&left, &[], &right]);
+        assert_eq!(left, vec![(0.0, 10.0), (10.0, 30.0), (40.0, 20.0)]);
+        assert!(center.is_empty());
+        assert_eq!(right, vec![(60.0, 30.0), (90.0, 10.0)]);
     }
-}
 
-impl Stream for XcbEventStream {
-    type Item = xcb::GenericEvent;
-    type Error = Error;
+    #[test]
+    fn center_stays_centered() {
+        let [left, center, right] =
+            layout_regions(100.0, [&[fixed(10.0)], &[fixed(20.0)], &[fixed(30.0)]]);
+        assert_eq!(left, vec![(0.0, 10.0)]);
+        assert_eq!(center, vec![(40.0, 20.0)]);
+        assert_eq!(right, vec![(70.0, 30.0)]);
+    }
 
-    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
-        if self.would_block {
-            match self.poll.poll_read() {
-                Async::Ready(()) => self.would_block = false,
-                Async::NotReady => return Ok(Async::NotReady),
-            }
-        }
+    #[test]
+    fn center_stretches_equally() {
+        let [_, center, _] = layout_regions(100.0, [&[fixed(10.0)], &[stretch()], &[fixed(30.0)]]);
+        assert_eq!(center, vec![(30.0, 40.0)]);
+    }
 
-        match self.conn.poll_for_event() {
-            Some(event) => Ok(Async::Ready(Some(event))),
-            None => {
-                self.would_block = true;
-                self.poll.need_read();
-                Ok(Async::NotReady)
-            }
-        }
+    #[test]
+    fn center_avoids_other_regions() {
+        let [_, center, _] =
+            layout_regions(100.0, [&[fixed(50.0)], &[fixed(20.0)], &[fixed(10.0)]]);
+        assert_eq!(center, vec![(50.0, 20.0)]);
+    }
+
+    #[test]
+    fn overflow_squeezes_center_then_left() {
+        let [left, center, right] =
+            layout_regions(100.0, [&[fixed(50.0)], &[fixed(40.0)], &[fixed(30.0)]]);
+        assert_eq!(left, vec![(0.0, 50.0)]);
+        assert_eq!(center, vec![(50.0, 20.0)]);
+        assert_eq!(right, vec![(70.0, 30.0)]);
+
+        let [left, center, right] =
+            layout_regions(100.0, [&[fixed(80.0)], &[fixed(40.0)], &[fixed(40.0)]]);
+        assert_eq!(left, vec![(0.0, 60.0)]);
+        assert_eq!(center, vec![(60.0, 0.0)]);
+        assert_eq!(right, vec![(60.0, 40.0)]);
     }
 }
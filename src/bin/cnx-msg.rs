@@ -0,0 +1,97 @@
+#![deny(warnings)]
+
+//! Sends a request to a running instance of Cnx over its IPC socket.
+
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+use failure::format_err;
+
+use cnx::ipc::{self, Request, Response};
+use cnx::Result;
+
+const USAGE: &str = "\
+Usage: cnx-msg [--socket <path>] <command>
+
+Commands:
+    hide                      Hide the bar
+    show                      Show the bar
+    toggle                    Hide the bar if it's shown, or show it if it's hidden
+    refresh <widget>          Restart a widget, so that it shows up-to-date texts
+    send <widget> <message>   Send a message to a widget, e.g. the text for a custom widget
+    query                     Print the texts shown by every widget, as JSON
+
+Widgets are given by name, or by their index (starting from 0).";
+
+/// Parses our arguments into the path of the socket and the request to send.
+fn parse_args(mut args: Vec<String>) -> Result<(PathBuf, Request)> {
+    let mut socket = ipc::default_socket_path();
+    if args.first().map(String::as_str) == Some("--socket") {
+        if args.len() < 2 {
+            return Err(format_err!("Missing path after --socket"));
+        }
+        socket = PathBuf::from(args.remove(1));
+        args.remove(0);
+    }
+
+    let mut args = args.into_iter();
+    let mut next = |what: &str| args.next().ok_or_else(|| format_err!("Missing {}", what));
+    let command = next("command")?;
+    let request = match command.as_str() {
+        "hide" => Request::Hide,
+        "show" => Request::Show,
+        "toggle" => Request::Toggle,
+        "refresh" => Request::Refresh {
+            widget: next("widget")?,
+        },
+        "send" => Request::Send {
+            widget: next("widget")?,
+            message: next("message")?,
+        },
+        "query" => Request::Query,
+        _ => return Err(format_err!("Unknown command: {}", command)),
+    };
+    if let Some(arg) = args.next() {
+        return Err(format_err!("Unexpected argument: {}", arg));
+    }
+
+    Ok((socket, request))
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return;
+    }
+
+    let (socket, request) = match parse_args(args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    match ipc::send_request(&socket, &request) {
+        Ok(Response::Ok) => {}
+        Ok(Response::Widgets(widgets)) => match serde_json::to_string_pretty(&widgets) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("Failed to serialize widgets: {}", e);
+                process::exit(1);
+            }
+        },
+        Ok(Response::Error(e)) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        Err(e) => {
+            // Include the underlying cause, e.g. why we couldn't connect.
+            let causes: Vec<String> = e.iter_chain().map(|cause| cause.to_string()).collect();
+            eprintln!("{}", causes.join(": "));
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,812 @@
+//! The configuration file of the `cnx` binary.
+//!
+//! The file describes the position of the bar, the [`Attributes`] shared by
+//! every widget, the IPC socket to listen on and an ordered list of widgets
+//! with their options. See
+//! `default.toml` (which is used when no configuration file exists) for an
+//! annotated example.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use failure::{bail, format_err};
+use log::*;
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+use toml::{Spanned, Table, Value};
+
+use cnx::text::*;
+use cnx::widgets::*;
+use cnx::*;
+
+/// The configuration used when the configuration file doesn't exist.
+pub const DEFAULT_CONFIG: &str = include_str!("default.toml");
+
+/// Returns where the configuration file is expected to be:
+/// `$XDG_CONFIG_HOME/cnx/config.toml`, or `~/.config/cnx/config.toml` if
+/// `$XDG_CONFIG_HOME` isn't set.
+pub fn default_path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_home.join("cnx").join("config.toml"))
+}
+
+/// Loads the configuration file at `path`, falling back to the default
+/// configuration if it doesn't exist.
+pub fn load(path: &Path) -> Result<Config> {
+    match fs::read_to_string(path) {
+        Ok(source) => parse(&source).map_err(|e| format_err!("{}: {}", path.display(), e)),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+            info!(
+                "No configuration file at {}, using the default configuration",
+                path.display()
+            );
+            parse(DEFAULT_CONFIG)
+        }
+        Err(e) => Err(format_err!("Failed to read {}: {}", path.display(), e)),
+    }
+}
+
+/// A parsed configuration file.
+#[derive(Debug)]
+pub struct Config {
+    style: BarStyle,
+    outputs: Option<Vec<String>>,
+    transparent: bool,
+    socket: Option<PathBuf>,
+    attr: Attributes,
+    widgets: Vec<WidgetConfig>,
+}
+
+impl Config {
+    /// Creates a `Cnx` instance with the configured widgets.
+    pub fn build(&self) -> Result<Cnx> {
+        let mut cnx = Cnx::new(self.style.clone())?;
+        self.apply_to(&mut cnx)?;
+        Ok(cnx)
+    }
+
+    /// Applies the configured settings to a `Cnx` instance and adds the
+    /// configured widgets to it.
+    pub fn apply_to(&self, cnx: &mut Cnx) -> Result<()> {
+        cnx.set_style(self.style.clone());
+        if let Some(ref outputs) = self.outputs {
+            cnx.set_outputs(outputs.clone());
+        }
+        cnx.set_transparent(self.transparent);
+        if let Some(ref socket) = self.socket {
+            cnx.set_ipc_socket(socket.clone());
+        }
+        self.add_widgets(cnx)
+    }
+
+    /// Adds the configured widgets to a `Cnx` instance.
+    fn add_widgets(&self, cnx: &mut Cnx) -> Result<()> {
+        for widget in &self.widgets {
+            let attr = widget.attr.apply_to(&self.attr);
+            let region = widget.region;
+            // Widgets are only given a name if they have one, so that they
+            // can still be referred to by index otherwise.
+            macro_rules! add {
+                ($widget:expr) => {{
+                    let w = $widget;
+                    match widget.name {
+                        Some(ref name) => cnx.add_named_widget_to(region, name.as_str(), w),
+                        None => cnx.add_widget_to(region, w),
+                    }
+                }};
+            }
+            match widget.kind {
+                WidgetKind::ActiveWindowTitle { icon } => {
+                    add!(ActiveWindowTitle::new(cnx, attr).with_icon(icon));
+                }
+                WidgetKind::Pager {
+                    ref active,
+                    wrap_around,
+                } => {
+                    let active_attr = active.apply_to(&attr);
+                    add!(Pager::new(cnx, active_attr, attr).with_wrap_around(wrap_around));
+                }
+                WidgetKind::Sensors {
+                    ref sensors,
+                    ref source,
+                    ref sysfs_root,
+                    ref kinds,
+                    ref formats,
+                } => {
+                    let source = match source {
+                        SensorSourceConfig::Sysfs => match sysfs_root {
+                            Some(root) => SensorSource::Sysfs(root.clone()),
+                            None => SensorSource::default(),
+                        },
+                        SensorSourceConfig::LmSensors => SensorSource::LmSensors,
+                        SensorSourceConfig::LmSensorsJson => SensorSource::LmSensorsJson,
+                    };
+                    let mut widget = Sensors::new(cnx, attr, sensors.clone()).with_source(source);
+                    if let Some(kinds) = kinds {
+                        widget = widget.with_kinds(kinds.iter().map(|kind| kind.kind()));
+                    }
+                    for (kind, format) in formats {
+                        widget = widget.with_format(kind.kind(), format.clone());
+                    }
+                    add!(widget);
+                }
+                #[cfg(feature = "volume-widget")]
+                WidgetKind::Volume {
+                    ref icon,
+                    ref muted_icon,
+                } => {
+                    let mut widget = Volume::new(cnx, attr);
+                    if let Some(icon) = load_icon(icon) {
+                        widget = widget.with_icon(icon);
+                    }
+                    if let Some(icon) = load_icon(muted_icon) {
+                        widget = widget.with_muted_icon(icon);
+                    }
+                    add!(widget);
+                }
+                #[cfg(not(feature = "volume-widget"))]
+                WidgetKind::Volume { .. } => {}
+                WidgetKind::Battery {
+                    ref battery,
+                    ref warning_color,
+                    ref icon,
+                } => {
+                    let warning_color = warning_color.clone().map_or_else(Color::red, |c| c.0);
+                    let mut widget = Battery::new(cnx, attr, warning_color);
+                    if let Some(battery) = battery {
+                        widget = widget.with_battery(battery.as_str());
+                    }
+                    if let Some(icon) = load_icon(icon) {
+                        widget = widget.with_icon(icon);
+                    }
+                    add!(widget);
+                }
+                WidgetKind::Clock {
+                    ref format,
+                    ref formats,
+                    ref timezone,
+                    calendar,
+                } => {
+                    let mut widget = Clock::new(cnx, attr).with_calendar(calendar);
+                    if let Some(format) = format {
+                        widget = widget.with_format(format.as_str())?;
+                    }
+                    if let Some(formats) = formats {
+                        widget = widget.with_formats(formats.iter().map(String::as_str))?;
+                    }
+                    if let Some(timezone) = timezone {
+                        widget = widget.with_timezone(timezone)?;
+                    }
+                    add!(widget);
+                }
+                WidgetKind::Timer {
+                    ref mode,
+                    ref minutes,
+                    ref break_minutes,
+                    ref warning_color,
+                    ref command,
+                } => {
+                    let warning_color = warning_color.clone().map_or_else(Color::red, |c| c.0);
+                    let length = Duration::from_secs(minutes.as_ref().map_or(25 * 60, |m| m.0));
+                    let rest = Duration::from_secs(break_minutes.as_ref().map_or(5 * 60, |m| m.0));
+                    let mut widget = match mode {
+                        TimerModeConfig::Stopwatch => Timer::stopwatch(cnx, attr),
+                        TimerModeConfig::Countdown => {
+                            Timer::countdown(cnx, attr, length, warning_color)
+                        }
+                        TimerModeConfig::Pomodoro => {
+                            Timer::pomodoro(cnx, attr, length, rest, warning_color)
+                        }
+                    };
+                    if let Some(command) = command {
+                        widget = widget.with_command(command.as_str());
+                    }
+                    add!(widget);
+                }
+                WidgetKind::Tray {} => {
+                    add!(Tray::new(cnx, attr));
+                }
+                WidgetKind::Custom { ref text, markup } => {
+                    let mut widget = Custom::new(cnx, attr).with_markup(markup);
+                    if let Some(text) = text {
+                        widget = widget.with_text(text.as_str());
+                    }
+                    add!(widget);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Loads an icon given in the configuration file. If it can't be loaded, the
+/// widget is shown without it rather than not at all.
+fn load_icon(path: &Option<PathBuf>) -> Option<Icon> {
+    let path = path.as_ref()?;
+    Icon::load(path)
+        .map_err(|e| warn!("Showing widget without its icon: {}", e))
+        .ok()
+}
+
+/// Parses the source of a configuration file.
+pub fn parse(source: &str) -> Result<Config> {
+    // TOML's own errors already say which line they're on. They end with a
+    // newline, which we don't want when they're wrapped in our own errors.
+    let file: ConfigFile =
+        toml::from_str(source).map_err(|e| format_err!("{}", e.to_string().trim_end()))?;
+
+    let default_attr = Attributes {
+        font: Font::new("SourceCodePro 21"),
+        fg_color: Color::white(),
+        bg_color: None,
+        padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    };
+    // The line each widget name was first used on, as `cnx-msg` can only
+    // refer to one widget by each name.
+    let mut names = HashMap::new();
+    let widgets = file
+        .widgets
+        .into_iter()
+        .map(|table| {
+            let line = line_number(source, table.span().start);
+            let widget = parse_widget(table.into_inner()).map_err(|e| {
+                let e = e.to_string();
+                format_err!("Invalid widget at line {}: {}", line, e.trim_end())
+            })?;
+            let name = widget.as_ref().and_then(|widget| widget.name.clone());
+            if let Some(name) = name {
+                if let Some(first) = names.insert(name.clone(), line) {
+                    bail!(
+                        "Invalid widget at line {}: name {:?} is already used by the widget at line {}",
+                        line,
+                        name,
+                        first
+                    );
+                }
+            }
+            Ok(widget)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let position = match file.position {
+        PositionConfig::Top => Position::Top,
+        PositionConfig::Bottom => Position::Bottom,
+        PositionConfig::Left => Position::Left,
+        PositionConfig::Right => Position::Right,
+    };
+
+    let attr = file.attributes.apply_to(&default_attr);
+    Ok(Config {
+        style: file.bar.apply_to(BarStyle::new(position), &attr),
+        outputs: file.outputs,
+        transparent: file.transparent,
+        socket: match file.socket {
+            SocketConfig::Enabled(true) => Some(ipc::default_socket_path()),
+            SocketConfig::Enabled(false) => None,
+            SocketConfig::Path(path) => Some(path),
+        },
+        attr,
+        widgets: widgets.into_iter().flatten().collect(),
+    })
+}
+
+/// Parses one `[[widget]]` table, returning `None` if the widget isn't
+/// available in this build of Cnx.
+fn parse_widget(mut table: Table) -> Result<Option<WidgetConfig>> {
+    // Every widget accepts a region and attributes, alongside the options
+    // specific to its type. Split them up so that each can reject any fields
+    // it doesn't know about.
+    let mut common = Table::new();
+    for key in &["name", "region", "font", "fg_color", "bg_color", "padding"] {
+        if let Some(value) = table.remove(*key) {
+            common.insert((*key).to_owned(), value);
+        }
+    }
+    let region = common
+        .remove("region")
+        .map(|region| region.try_into::<RegionConfig>())
+        .transpose()?
+        .unwrap_or(RegionConfig::Left);
+    let name = common
+        .remove("name")
+        .map(|name| name.try_into::<String>())
+        .transpose()?;
+    // `cnx-msg` takes either a widget's name or its index.
+    if let Some(ref name) = name {
+        if name.parse::<usize>().is_ok() {
+            bail!("name {:?} would be mistaken for a widget index", name);
+        }
+    }
+    let attr: AttributesConfig = Value::Table(common).try_into()?;
+    let kind: WidgetKind = Value::Table(table).try_into()?;
+
+    // Check the formats and time zone now, so that a bad one is reported
+    // with its line rather than stopping the clock from starting.
+    if let WidgetKind::Clock {
+        ref format,
+        ref formats,
+        ref timezone,
+        ..
+    } = kind
+    {
+        if format.is_some() && formats.is_some() {
+            bail!("only one of `format` and `formats` may be given");
+        }
+        for format in format.iter().chain(formats.iter().flatten()) {
+            Clock::check_format(format)?;
+        }
+        if let Some(timezone) = timezone {
+            Clock::check_timezone(timezone)?;
+        }
+    }
+
+    if let WidgetKind::Volume { .. } = kind {
+        if cfg!(not(feature = "volume-widget")) {
+            warn!("Cnx was built without the volume widget, so it won't be shown");
+            return Ok(None);
+        }
+    }
+
+    Ok(Some(WidgetConfig {
+        name,
+        region: match region {
+            RegionConfig::Left => Region::Left,
+            RegionConfig::Center => Region::Center,
+            RegionConfig::Right => Region::Right,
+        },
+        attr,
+        kind,
+    }))
+}
+
+/// Returns the 1-based line number of the byte at `offset`.
+fn line_number(source: &str, offset: usize) -> usize {
+    source[..offset].matches('\n').count() + 1
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    position: PositionConfig,
+    outputs: Option<Vec<String>>,
+    #[serde(default)]
+    transparent: bool,
+    #[serde(default)]
+    socket: SocketConfig,
+    #[serde(default)]
+    bar: BarConfig,
+    #[serde(default)]
+    attributes: AttributesConfig,
+    #[serde(default, rename = "widget")]
+    widgets: Vec<Spanned<Table>>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum PositionConfig {
+    Top,
+    #[default]
+    Bottom,
+    Left,
+    Right,
+}
+
+/// Either `false` to not listen on an IPC socket, `true` to listen on the
+/// default socket, or the path of the socket to listen on.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SocketConfig {
+    Enabled(bool),
+    Path(PathBuf),
+}
+
+impl Default for SocketConfig {
+    fn default() -> SocketConfig {
+        SocketConfig::Enabled(true)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RegionConfig {
+    Left,
+    Center,
+    Right,
+}
+
+/// The style of the bar as a whole.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BarConfig {
+    background: Option<ColorConfig>,
+    margins: Option<[f64; 4]>,
+    border_width: Option<f64>,
+    border_color: Option<ColorConfig>,
+    corner_radius: Option<f64>,
+    height: Option<u16>,
+    min_height: Option<u16>,
+    rotate_text: Option<bool>,
+    separator: Option<SeparatorConfig>,
+}
+
+impl BarConfig {
+    /// Applies the options to `style`. Text separators are drawn with the
+    /// global `attr`, overridden by their own attributes.
+    fn apply_to(self, mut style: BarStyle, attr: &Attributes) -> BarStyle {
+        if let Some(background) = self.background {
+            style = style.with_background(background.0);
+        }
+        if let Some([left, right, top, bottom]) = self.margins {
+            style = style.with_margins(Padding::new(left, right, top, bottom));
+        }
+        if self.border_width.is_some() || self.border_color.is_some() {
+            let color = self.border_color.map_or_else(Color::white, |c| c.0);
+            style = style.with_border(self.border_width.unwrap_or(1.0), color);
+        }
+        if let Some(radius) = self.corner_radius {
+            style = style.with_corner_radius(radius);
+        }
+        // A fixed height takes precedence over a minimum one.
+        if let Some(height) = self.height {
+            style = style.with_height(BarHeight::Fixed(height));
+        } else if let Some(height) = self.min_height {
+            style = style.with_height(BarHeight::Minimum(height));
+        }
+        if let Some(rotate_text) = self.rotate_text {
+            style = style.with_rotated_text(rotate_text);
+        }
+        if let Some(separator) = self.separator {
+            style = style.with_separator(separator.into_separator(attr));
+        }
+        style
+    }
+}
+
+/// What's drawn between adjacent widgets.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum SeparatorConfig {
+    Space {
+        width: f64,
+    },
+    Text {
+        text: String,
+        #[serde(default)]
+        attributes: AttributesConfig,
+    },
+    Line {
+        width: f64,
+        color: ColorConfig,
+        #[serde(default)]
+        gap: f64,
+    },
+    Arrow {
+        width: f64,
+    },
+}
+
+impl SeparatorConfig {
+    fn into_separator(self, attr: &Attributes) -> Separator {
+        match self {
+            SeparatorConfig::Space { width } => Separator::Space(width),
+            SeparatorConfig::Text { text, attributes } => {
+                Separator::Text(text, attributes.apply_to(attr))
+            }
+            SeparatorConfig::Line { width, color, gap } => Separator::Line {
+                width,
+                color: color.0,
+                gap,
+            },
+            SeparatorConfig::Arrow { width } => Separator::Arrow(width),
+        }
+    }
+}
+
+/// Attributes which override those of the enclosing scope.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AttributesConfig {
+    font: Option<String>,
+    fg_color: Option<ColorConfig>,
+    bg_color: Option<ColorConfig>,
+    padding: Option<[f64; 4]>,
+}
+
+impl AttributesConfig {
+    fn apply_to(&self, attr: &Attributes) -> Attributes {
+        let mut attr = attr.clone();
+        if let Some(ref font) = self.font {
+            attr.font = Font::new(font);
+        }
+        if let Some(ref fg_color) = self.fg_color {
+            attr.fg_color = fg_color.0.clone();
+        }
+        if let Some(ref bg_color) = self.bg_color {
+            attr.bg_color = Some(bg_color.0.clone());
+        }
+        if let Some([left, right, top, bottom]) = self.padding {
+            attr.padding = Padding::new(left, right, top, bottom);
+        }
+        attr
+    }
+}
+
+#[derive(Clone, Debug)]
+struct ColorConfig(Color);
+
+impl<'de> Deserialize<'de> for ColorConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let color = String::deserialize(deserializer)?;
+        color.parse().map(ColorConfig).map_err(|e| {
+            de::Error::custom(format!(
+                "{}, expected a hex color (e.g. `#rrggbb` or `#rrggbbaa`) or a CSS color name",
+                e
+            ))
+        })
+    }
+}
+
+/// A number of minutes, which may be fractional, converted to whole seconds.
+/// A timer phase must last at least a second, or it would end as soon as it
+/// starts, over and over.
+#[derive(Clone, Debug)]
+struct MinutesConfig(u64);
+
+impl<'de> Deserialize<'de> for MinutesConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let minutes = f64::deserialize(deserializer)?;
+        let seconds = (minutes * 60.0).round();
+        if !seconds.is_finite() || seconds < 1.0 {
+            return Err(de::Error::custom(format!(
+                "invalid number of minutes {}, expected a positive number",
+                minutes
+            )));
+        }
+        Ok(MinutesConfig(seconds as u64))
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SensorSourceConfig {
+    #[default]
+    Sysfs,
+    LmSensors,
+    LmSensorsJson,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum SensorKindConfig {
+    Temperature,
+    Fan,
+    Voltage,
+    Power,
+    Current,
+}
+
+impl SensorKindConfig {
+    fn kind(self) -> SensorKind {
+        match self {
+            SensorKindConfig::Temperature => SensorKind::Temperature,
+            SensorKindConfig::Fan => SensorKind::Fan,
+            SensorKindConfig::Voltage => SensorKind::Voltage,
+            SensorKindConfig::Power => SensorKind::Power,
+            SensorKindConfig::Current => SensorKind::Current,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum TimerModeConfig {
+    #[default]
+    Stopwatch,
+    Countdown,
+    Pomodoro,
+}
+
+#[derive(Debug)]
+struct WidgetConfig {
+    name: Option<String>,
+    region: Region,
+    attr: AttributesConfig,
+    kind: WidgetKind,
+}
+
+/// The type of a widget, along with the options specific to that type.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum WidgetKind {
+    ActiveWindowTitle {
+        #[serde(default)]
+        icon: bool,
+    },
+    Pager {
+        #[serde(default)]
+        active: AttributesConfig,
+        #[serde(default)]
+        wrap_around: bool,
+    },
+    Sensors {
+        sensors: Vec<String>,
+        #[serde(default)]
+        source: SensorSourceConfig,
+        sysfs_root: Option<PathBuf>,
+        kinds: Option<Vec<SensorKindConfig>>,
+        #[serde(default)]
+        formats: HashMap<SensorKindConfig, String>,
+    },
+    // The options are still accepted without the `volume-widget` feature, but
+    // the widget isn't shown.
+    #[cfg_attr(not(feature = "volume-widget"), allow(dead_code))]
+    Volume {
+        icon: Option<PathBuf>,
+        muted_icon: Option<PathBuf>,
+    },
+    Battery {
+        battery: Option<String>,
+        warning_color: Option<ColorConfig>,
+        icon: Option<PathBuf>,
+    },
+    Clock {
+        format: Option<String>,
+        formats: Option<Vec<String>>,
+        timezone: Option<String>,
+        #[serde(default)]
+        calendar: bool,
+    },
+    Timer {
+        #[serde(default)]
+        mode: TimerModeConfig,
+        minutes: Option<MinutesConfig>,
+        break_minutes: Option<MinutesConfig>,
+        warning_color: Option<ColorConfig>,
+        command: Option<String>,
+    },
+    Tray {},
+    Custom {
+        text: Option<String>,
+        #[serde(default)]
+        markup: bool,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn default_config_parses() {
+        let config = parse(DEFAULT_CONFIG).unwrap();
+        assert!(!config.widgets.is_empty());
+    }
+
+    #[test]
+    fn widget_attributes_override_global_attributes() {
+        let source = r#"
+            [attributes]
+            fg_color = "white"
+            padding = [1.0, 2.0, 3.0, 4.0]
+
+            [[widget]]
+            type = "clock"
+            region = "right"
+            fg_color = "red"
+        "#;
+        let config = parse(source).unwrap();
+        let widget = &config.widgets[0];
+        assert_eq!(widget.region, Region::Right);
+        let attr = widget.attr.apply_to(&config.attr);
+        assert_eq!(attr.fg_color, Color::red());
+        assert_eq!(attr.padding, Padding::new(1.0, 2.0, 3.0, 4.0));
+    }
+
+    #[test]
+    fn separator_text_uses_global_attributes() {
+        let source = r#"
+            [attributes]
+            fg_color = "white"
+
+            [bar.separator]
+            type = "text"
+            text = "|"
+            attributes = { bg_color = "black" }
+        "#;
+        let config = parse(source).unwrap();
+        let style = BarStyle::new(Position::Bottom).with_separator(Separator::Text(
+            "|".to_owned(),
+            Attributes {
+                bg_color: Some(Color::black()),
+                ..config.attr.clone()
+            },
+        ));
+        assert_eq!(config.style, style);
+    }
+
+    #[test]
+    fn invalid_widget_option_reports_line() {
+        let source = "position = \"top\"\n\n[[widget]]\ntype = \"clock\"\n\n[[widget]]\ntype = \"battery\"\nbatery = \"BAT1\"\n";
+        let error = parse(source).unwrap_err().to_string();
+        assert!(error.starts_with("Invalid widget at line 6:"), "{}", error);
+        assert!(error.contains("batery"), "{}", error);
+    }
+
+    #[test]
+    fn unknown_color_is_rejected() {
+        let source = "[[widget]]\ntype = \"clock\"\nfg_color = \"mauve\"\n";
+        let error = parse(source).unwrap_err().to_string();
+        assert!(error.contains("Unknown color: \"mauve\""), "{}", error);
+    }
+
+    #[test]
+    fn clock_formats_are_validated() {
+        let source = "[[widget]]\ntype = \"clock\"\n\n[[widget]]\ntype = \"clock\"\nformats = [\"%H:%M\", \"%Q\"]\n";
+        let error = parse(source).unwrap_err().to_string();
+        assert!(error.starts_with("Invalid widget at line 4:"), "{}", error);
+        assert!(error.contains("Invalid time format \"%Q\""), "{}", error);
+
+        let source = "[[widget]]\ntype = \"clock\"\nformat = \"%H:%M\"\nformats = [\"%H:%M\"]\n";
+        let error = parse(source).unwrap_err().to_string();
+        assert!(error.contains("`format` and `formats`"), "{}", error);
+        let source = "[[widget]]\ntype = \"clock\"\ntimezone = \"America/NewYork\"\n";
+        let error = parse(source).unwrap_err().to_string();
+        assert!(
+            error.starts_with("Invalid widget at line 1: Unknown time zone \"America/NewYork\""),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn widget_names_must_be_unique() {
+        let source = "[[widget]]\ntype = \"clock\"\nname = \"clock\"\n\n[[widget]]\ntype = \"timer\"\n\n[[widget]]\ntype = \"clock\"\nname = \"clock\"\n";
+        let error = parse(source).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "Invalid widget at line 8: name \"clock\" is already used by the widget at line 1"
+        );
+
+        let source = "[[widget]]\ntype = \"timer\"\nname = \"1\"\n";
+        let error = parse(source).unwrap_err().to_string();
+        assert!(error.starts_with("Invalid widget at line 1:"), "{}", error);
+        assert!(error.contains("mistaken for a widget index"), "{}", error);
+    }
+
+    #[test]
+    fn timer_minutes_are_validated() {
+        let source = "[[widget]]\ntype = \"timer\"\nmode = \"countdown\"\nminutes = 2.5\n";
+        match parse(source).unwrap().widgets[0].kind {
+            WidgetKind::Timer {
+                minutes: Some(MinutesConfig(seconds)),
+                ..
+            } => assert_eq!(seconds, 150),
+            ref kind => panic!("Unexpected widget {:?}", kind),
+        }
+
+        let source = "[[widget]]\ntype = \"timer\"\nminutes = -1\n";
+        let error = parse(source).unwrap_err().to_string();
+        assert!(error.contains("invalid number of minutes -1"), "{}", error);
+
+        let source = "[[widget]]\ntype = \"timer\"\nmode = \"pomodoro\"\nbreak_minutes = 0\n";
+        let error = parse(source).unwrap_err().to_string();
+        assert!(error.starts_with("Invalid widget at line 1:"), "{}", error);
+        assert!(error.contains("invalid number of minutes 0"), "{}", error);
+
+        let source = "[[widget]]\ntype = \"timer\"\nminutes = 0.001\n";
+        let error = parse(source).unwrap_err().to_string();
+        assert!(
+            error.contains("invalid number of minutes 0.001"),
+            "{}",
+            error
+        );
+    }
+}
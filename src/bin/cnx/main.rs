@@ -0,0 +1,51 @@
+#![deny(warnings)]
+
+mod config;
+mod reload;
+
+use std::env;
+use std::path::PathBuf;
+
+use env_logger::Builder;
+use failure::format_err;
+use log::LevelFilter;
+
+use cnx::*;
+
+fn init_log() -> Result<()> {
+    let mut builder = Builder::new();
+    builder.filter(Some("cnx"), LevelFilter::Trace);
+    if let Ok(rust_log) = env::var("RUST_LOG") {
+        builder.parse_filters(&rust_log);
+    }
+    builder.try_init()?;
+    Ok(())
+}
+
+/// Returns the path of the configuration file, which can be given with
+/// `--config <path>`.
+fn config_path() -> Result<PathBuf> {
+    let mut args = env::args().skip(1);
+    match args.next().as_deref() {
+        Some("-c") | Some("--config") => args
+            .next()
+            .map(PathBuf::from)
+            .ok_or_else(|| format_err!("Missing path after --config")),
+        Some(arg) => Err(format_err!("Unexpected argument: {}", arg)),
+        None => config::default_path()
+            .ok_or_else(|| format_err!("Neither $XDG_CONFIG_HOME nor $HOME is set")),
+    }
+}
+
+fn main() -> Result<()> {
+    init_log()?;
+
+    let path = config_path()?;
+    let mut cnx = config::load(&path)?.build()?;
+    cnx.set_reloader(reload::triggers(&path), move |cnx| {
+        config::load(&path)?.apply_to(cnx)
+    });
+    cnx.run()?;
+
+    Ok(())
+}
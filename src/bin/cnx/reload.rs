@@ -0,0 +1,76 @@
+//! Noticing when the configuration file should be reloaded.
+
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use futures::{future, stream, Stream, StreamExt};
+use inotify::{EventStream, Inotify, WatchMask};
+use log::*;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio_stream::wrappers::SignalStream;
+
+/// Returns a stream which produces an item whenever the configuration file at
+/// `path` is written to, or the process receives `SIGHUP`.
+///
+/// Neither is set up until the stream is first polled, as both need the Tokio
+/// runtime. If either can't be set up, a warning is logged and the bar carries
+/// on without it.
+pub fn triggers(path: &Path) -> impl Stream<Item = ()> {
+    stream::select(sighups(), changes(path))
+}
+
+fn sighups() -> impl Stream<Item = ()> {
+    stream::once(async { signal(SignalKind::hangup()) })
+        .filter_map(|signal| {
+            if let Err(ref e) = signal {
+                warn!("Failed to listen for SIGHUP: {}", e);
+            }
+            future::ready(signal.ok())
+        })
+        .flat_map(SignalStream::new)
+        .inspect(|()| info!("Received SIGHUP, reloading configuration"))
+}
+
+fn changes(path: &Path) -> impl Stream<Item = ()> {
+    // If the configuration file is a symlink (e.g. into a dotfiles
+    // repository), it's the file it points to that will be edited.
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+    // Editors often save by writing a new file and renaming it over the old
+    // one, so we watch the directory rather than the file itself.
+    let dir = match path.parent() {
+        Some(dir) if dir != Path::new("") => dir.to_owned(),
+        _ => PathBuf::from("."),
+    };
+    let name = path.file_name().map(OsString::from);
+
+    stream::once(async move {
+        let events = watch(&dir);
+        if let Err(ref e) = events {
+            warn!("Failed to watch {} for changes: {}", dir.display(), e);
+        }
+        events.ok()
+    })
+    .filter_map(future::ready)
+    .flatten()
+    .take_while(|event| {
+        if let Err(ref e) = event {
+            warn!("Stopped watching for configuration changes: {}", e);
+        }
+        future::ready(event.is_ok())
+    })
+    .filter_map(move |event| {
+        let changed = event.ok().filter(|event| event.name == name).map(|_| ());
+        future::ready(changed)
+    })
+    .inspect(move |()| info!("{} changed, reloading configuration", path.display()))
+}
+
+fn watch(dir: &Path) -> io::Result<EventStream<[u8; 1024]>> {
+    let inotify = Inotify::init()?;
+    inotify
+        .watches()
+        .add(dir, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO)?;
+    inotify.into_event_stream([0; 1024])
+}
@@ -0,0 +1,438 @@
+//! Rendering the bar without an X server.
+//!
+//! [`HeadlessBar`] lays out and renders widget texts exactly as a bar on
+//! screen does, but to an in-memory image rather than an X window. This makes
+//! it possible to regression-test layout and rendering by comparing the result
+//! against golden PNG images.
+//!
+//! When a golden image doesn't exist yet, or after an intentional change to
+//! how the bar looks, run the tests with `CNX_UPDATE_GOLDEN=1` set to write the
+//! current output as the new golden images. When an image doesn't match, the
+//! actual output is written alongside the golden image with an `.actual.png`
+//! extension, so that it can be inspected.
+//!
+//! Text is rendered using the fonts installed on the machine, so golden images
+//! are only comparable between machines with the same fonts installed.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use cnx::headless::HeadlessBar;
+//! # use cnx::text::{Attributes, Color, Font, Padding, Text};
+//! # use cnx::Region;
+//! # fn run() -> ::cnx::Result<()> {
+//! let attr = Attributes {
+//!     font: Font::new("SourceCodePro 21"),
+//!     fg_color: Color::white(),
+//!     bg_color: None,
+//!     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+//! };
+//! let text = |text: &str| Text::new(attr.clone(), text);
+//!
+//! let mut bar = HeadlessBar::new(800, &[Region::Left, Region::Right])?;
+//! bar.update(vec![Some(vec![text("left")]), Some(vec![text("right")])])?;
+//! bar.compare_to_golden("tests/golden/left-right.png", 0)?;
+//! # Ok(())
+//! # }
+//! # fn main() { run().unwrap(); }
+//! ```
+
+use std::env;
+use std::fs::{self, File};
+use std::path::Path;
+
+use cairo::{Format, ImageSurface};
+use failure::{bail, format_err, ResultExt};
+
+use crate::bar::{Backend, Bar};
+use crate::text::Text;
+use crate::{BarStyle, Position, Region, Result};
+
+fn create_image_surface(width: u16, height: u16) -> Result<ImageSurface> {
+    ImageSurface::create(Format::ARgb32, i32::from(width), i32::from(height))
+        .map_err(|status| format_err!("Failed to create image surface: {:?}", status))
+}
+
+/// Renders a bar to an image surface, rather than to an X window.
+struct ImageBackend {
+    surface: ImageSurface,
+    length: u16,
+    vertical: bool,
+}
+
+impl Backend for ImageBackend {
+    fn surface(&self) -> &cairo::Surface {
+        &self.surface
+    }
+
+    fn length(&self) -> u16 {
+        self.length
+    }
+
+    fn set_thickness(&mut self, thickness: u16) -> Result<()> {
+        let (width, height) = if self.vertical {
+            (thickness, self.length)
+        } else {
+            (self.length, thickness)
+        };
+        // Image surfaces can't be resized, but we're about to redraw the
+        // entire bar, so we can replace it with an empty one.
+        let size = (i32::from(width), i32::from(height));
+        if size != (self.surface.get_width(), self.surface.get_height()) {
+            self.surface = create_image_surface(width, height)?;
+        }
+        Ok(())
+    }
+}
+
+/// A bar which renders to an in-memory image.
+///
+/// See the [module documentation](index.html) for an example.
+pub struct HeadlessBar {
+    bar: Bar<ImageBackend>,
+}
+
+impl HeadlessBar {
+    /// Creates a bar which is `length` pixels long, with one widget in each of
+    /// the given `regions`. The bar is `length` pixels wide, or tall if it's
+    /// later given a vertical style with [`set_style()`].
+    ///
+    /// The bar's height is determined by its tallest text, just as it is for a
+    /// bar shown on screen.
+    ///
+    /// [`set_style()`]: #method.set_style
+    pub fn new(length: u16, regions: &[Region]) -> Result<HeadlessBar> {
+        let backend = ImageBackend {
+            surface: create_image_surface(length, 1)?,
+            length,
+            vertical: false,
+        };
+        let mut bar = Bar::new(backend, BarStyle::new(Position::Top));
+        bar.reset_contents(regions);
+        Ok(HeadlessBar { bar })
+    }
+
+    /// Updates the texts shown by each widget, as though each widget's stream
+    /// had just produced them. Widgets given `None` keep their existing texts.
+    ///
+    /// Just like a bar shown on screen, this only redraws the entire bar if
+    /// the update changes the layout. Otherwise only the texts which have
+    /// changed are redrawn.
+    pub fn update(&mut self, contents: Vec<Option<Vec<Text>>>) -> Result<()> {
+        if self.bar.update_widget_contents(contents)? {
+            self.bar.redraw_entire_bar()?;
+        }
+        Ok(())
+    }
+
+    /// Sets the style of the bar, which is used the next time the entire bar
+    /// is redrawn. The style's position and margins are ignored, as the
+    /// image is only as big as the bar itself.
+    pub fn set_style(&mut self, style: BarStyle) {
+        self.bar.backend_mut().vertical = style.is_vertical();
+        self.bar.set_style(style);
+    }
+
+    /// Lays out and redraws the entire bar.
+    pub fn redraw(&mut self) -> Result<()> {
+        self.bar.redraw_entire_bar()
+    }
+
+    /// Returns the image the bar has been rendered to.
+    pub fn surface(&self) -> &ImageSurface {
+        &self.bar.backend().surface
+    }
+
+    /// Writes the bar's current image to a PNG file.
+    pub fn write_png<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        write_png(self.surface(), path.as_ref())
+    }
+
+    /// Compares the bar's current image against the golden PNG image at
+    /// `path`, returning an error if they differ.
+    ///
+    /// Pixels are considered equal if none of their channels differ by more
+    /// than `tolerance`. This allows for small differences in anti-aliasing.
+    ///
+    /// If the `CNX_UPDATE_GOLDEN` environment variable is set, the golden
+    /// image is overwritten with the current image instead.
+    pub fn compare_to_golden<P: AsRef<Path>>(&self, path: P, tolerance: u8) -> Result<()> {
+        let path = path.as_ref();
+        let surface = self.surface();
+        if env::var_os("CNX_UPDATE_GOLDEN").is_some() {
+            return write_png(surface, path);
+        }
+
+        let mut file = File::open(path)
+            .with_context(|_| format!("Failed to open golden image {}", path.display()))?;
+        let golden = ImageSurface::create_from_png(&mut file)
+            .with_context(|_| format!("Failed to read golden image {}", path.display()))?;
+
+        let actual_path = path.with_extension("actual.png");
+        let (width, height) = (surface.get_width(), surface.get_height());
+        if (width, height) != (golden.get_width(), golden.get_height()) {
+            write_png(surface, &actual_path)?;
+            bail!(
+                "Image is {}x{}, but golden image {} is {}x{}",
+                width,
+                height,
+                path.display(),
+                golden.get_width(),
+                golden.get_height()
+            );
+        }
+
+        let differing = count_differing_pixels(&pixels(surface)?, &pixels(&golden)?, tolerance);
+        if differing > 0 {
+            write_png(surface, &actual_path)?;
+            bail!(
+                "{} pixels differ from golden image {} (actual image written to {})",
+                differing,
+                path.display(),
+                actual_path.display()
+            );
+        }
+
+        Ok(())
+    }
+}
+
+fn write_png(surface: &ImageSurface, path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|_| format!("Failed to create {}", dir.display()))?;
+    }
+    let mut file =
+        File::create(path).with_context(|_| format!("Failed to create {}", path.display()))?;
+    surface
+        .write_to_png(&mut file)
+        .with_context(|_| format!("Failed to write PNG to {}", path.display()))?;
+    Ok(())
+}
+
+/// Returns the ARGB pixel data of an image, without any padding at the end of
+/// each row.
+fn pixels(surface: &ImageSurface) -> Result<Vec<u8>> {
+    let (width, height) = (surface.get_width(), surface.get_height());
+
+    // We can only borrow the data of a surface nobody else holds a reference
+    // to, so paint a copy. This also makes sure both images we're comparing
+    // have the same format.
+    let mut copy = ImageSurface::create(Format::ARgb32, width, height)
+        .map_err(|status| format_err!("Failed to create image surface: {:?}", status))?;
+    {
+        let context = cairo::Context::new(&copy);
+        context.set_source_surface(surface, 0.0, 0.0);
+        context.paint();
+    }
+
+    let stride = copy.get_stride() as usize;
+    let row_length = width as usize * 4;
+    let data = copy.get_data()?;
+    Ok(data
+        .chunks(stride)
+        .flat_map(|row| &row[..row_length])
+        .cloned()
+        .collect())
+}
+
+/// Returns the number of 4-byte pixels which have any channel that differs by
+/// more than `tolerance`.
+fn count_differing_pixels(a: &[u8], b: &[u8], tolerance: u8) -> usize {
+    a.chunks(4)
+        .zip(b.chunks(4))
+        .filter(|(a, b)| {
+            a.iter()
+                .zip(b.iter())
+                .any(|(a, b)| (i16::from(*a) - i16::from(*b)).abs() > i16::from(tolerance))
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::text::{Attributes, Color, Font, Padding};
+    use crate::Separator;
+
+    const RED: u32 = 0xffff_0000;
+    const GREEN: u32 = 0xff00_ff00;
+    const BLUE: u32 = 0xff00_00ff;
+    const BLACK: u32 = 0xff00_0000;
+    const WHITE: u32 = 0xffff_ffff;
+
+    fn text(text: &str, bg_color: Option<Color>, padding: f64, stretch: bool) -> Text {
+        let attr = Attributes {
+            font: Font::new("monospace 12"),
+            fg_color: Color::white(),
+            bg_color,
+            padding: Padding::new(padding, padding, 0.0, 0.0),
+        };
+        Text::new(attr, text).with_stretch(stretch)
+    }
+
+    /// Returns each row of the bar's image, with each pixel as `0xAARRGGBB`.
+    fn rows(bar: &HeadlessBar) -> Vec<Vec<u32>> {
+        let width = bar.surface().get_width() as usize;
+        let pixels: Vec<u32> = pixels(bar.surface())
+            .unwrap()
+            .chunks(4)
+            .map(|pixel| u32::from_ne_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]))
+            .collect();
+        pixels.chunks(width).map(<[u32]>::to_vec).collect()
+    }
+
+    fn middle_row(bar: &HeadlessBar) -> Vec<u32> {
+        let rows = rows(bar);
+        rows[rows.len() / 2].clone()
+    }
+
+    #[test]
+    fn draws_background_and_text_colors() {
+        let mut bar = HeadlessBar::new(120, &[Region::Left]).unwrap();
+        bar.set_style(BarStyle::new(Position::Top).with_background(Color::black()));
+        let block = text("\u{2588}\u{2588}", Some(Color::red()), 4.0, false);
+        bar.update(vec![Some(vec![block])]).unwrap();
+
+        let row = middle_row(&bar);
+        assert_eq!(row[0], RED, "padding is drawn in the text's background");
+        assert_eq!(row[119], BLACK, "the rest of the bar is its background");
+        let text_end = row.iter().rposition(|&pixel| pixel == RED).unwrap();
+        assert!(
+            row[..text_end].contains(&WHITE),
+            "text is drawn in its foreground"
+        );
+        assert!(row[text_end + 1..].iter().all(|&pixel| pixel == BLACK));
+    }
+
+    #[test]
+    fn stretch_texts_share_free_space() {
+        let mut bar = HeadlessBar::new(200, &[Region::Left, Region::Right]).unwrap();
+        bar.update(vec![
+            Some(vec![
+                text("a", Some(Color::red()), 2.0, false),
+                text("", Some(Color::green()), 0.0, true),
+            ]),
+            Some(vec![
+                text("", Some(Color::blue()), 0.0, true),
+                text("b", Some(Color::red()), 2.0, false),
+            ]),
+        ])
+        .unwrap();
+
+        let row = middle_row(&bar);
+        assert_eq!(row[0], RED);
+        assert_eq!(row[199], RED);
+        // The stretch texts fill all of the space between the fixed texts,
+        // leaving none of the (transparent) bar showing.
+        assert!(row.iter().all(|&pixel| pixel >> 24 == 0xff));
+        let green = row.iter().filter(|&&pixel| pixel == GREEN).count();
+        let blue = row.iter().filter(|&&pixel| pixel == BLUE).count();
+        assert!(green > 0);
+        assert!(
+            (green as i64 - blue as i64).abs() <= 1,
+            "{} != {}",
+            green,
+            blue
+        );
+        let last_green = row.iter().rposition(|&pixel| pixel == GREEN).unwrap();
+        let first_blue = row.iter().position(|&pixel| pixel == BLUE).unwrap();
+        assert_eq!(first_blue, last_green + 1);
+    }
+
+    #[test]
+    fn squeezed_texts_are_ellipsized() {
+        let mut bar = HeadlessBar::new(80, &[Region::Left, Region::Right]).unwrap();
+        bar.update(vec![
+            Some(vec![text(
+                "WWWWWWWWWWWWWWWWWWWW",
+                Some(Color::black()),
+                0.0,
+                false,
+            )]),
+            Some(vec![text("", Some(Color::blue()), 10.0, false)]),
+        ])
+        .unwrap();
+
+        let rows = rows(&bar);
+        // The right text keeps its width and isn't drawn over.
+        assert!(rows
+            .iter()
+            .all(|row| row[60..].iter().all(|&pixel| pixel == BLUE)));
+
+        // The left text is cut short with an ellipsis, whose dots only reach
+        // the bottom half of the text, rather than with part of a "W".
+        let inked = |pixel: u32| pixel != BLACK;
+        let last_ink = rows
+            .iter()
+            .filter_map(|row| row[..60].iter().rposition(|&pixel| inked(pixel)))
+            .max()
+            .unwrap();
+        let (top, bottom) = rows.split_at(rows.len() / 2);
+        assert!(top.iter().all(|row| !inked(row[last_ink])));
+        assert!(bottom.iter().any(|row| inked(row[last_ink])));
+        assert!(top
+            .iter()
+            .any(|row| row[..last_ink].iter().any(|&pixel| inked(pixel))));
+    }
+
+    /// Draws a bar on the left of the screen in the given style, with empty
+    /// stretch texts in each region. Their lengths come from the stretching,
+    /// and the bar's width from their padding, so unlike a text with glyphs,
+    /// the image doesn't depend on the installed fonts and can be compared
+    /// with a golden image.
+    fn draw_shapes(style: BarStyle) -> HeadlessBar {
+        let regions = [Region::Left, Region::Left, Region::Right];
+        let mut bar = HeadlessBar::new(120, &regions).unwrap();
+        bar.set_style(style);
+        bar.update(vec![
+            Some(vec![text("", Some(Color::red()), 8.0, true)]),
+            Some(vec![text("", Some(Color::green()), 6.0, true)]),
+            Some(vec![
+                text("", Some(Color::blue()), 8.0, true),
+                text("", Some(Color::red()), 8.0, true),
+            ]),
+        ])
+        .unwrap();
+        bar
+    }
+
+    fn golden(name: &str) -> String {
+        format!("{}/tests/golden/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    #[test]
+    fn matches_golden_image() {
+        let style = BarStyle::new(Position::Left)
+            .with_background(Color::black())
+            .with_border(2.0, Color::white())
+            .with_corner_radius(6.0)
+            .with_separator(Separator::Line {
+                width: 2.0,
+                color: Color::white(),
+                gap: 3.0,
+            });
+        let bar = draw_shapes(style);
+        bar.compare_to_golden(golden("shapes.png"), 2).unwrap();
+    }
+
+    #[test]
+    fn identical_pixels_match() {
+        let pixels = [0, 10, 20, 255, 255, 255, 255, 255];
+        assert_eq!(count_differing_pixels(&pixels, &pixels, 0), 0);
+    }
+
+    #[test]
+    fn counts_pixels_not_channels() {
+        let a = [0, 0, 0, 255, 0, 0, 0, 255, 0, 0, 0, 255];
+        let b = [9, 9, 9, 255, 0, 0, 0, 255, 0, 0, 1, 255];
+        assert_eq!(count_differing_pixels(&a, &b, 0), 2);
+    }
+
+    #[test]
+    fn differences_within_tolerance_match() {
+        let a = [100, 100, 100, 255];
+        let b = [102, 98, 100, 255];
+        assert_eq!(count_differing_pixels(&a, &b, 2), 0);
+        assert_eq!(count_differing_pixels(&a, &b, 1), 1);
+    }
+}
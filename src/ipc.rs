@@ -0,0 +1,353 @@
+//! Controlling a running bar from other programs.
+//!
+//! If [`Cnx::set_ipc_socket()`] is used, Cnx listens on a Unix domain socket
+//! for requests, such as hiding the bar or sending text to a widget. The
+//! `cnx-msg` program bundled with Cnx sends these requests from the command
+//! line, and scripts may also use [`send_request()`] or speak the protocol
+//! themselves.
+//!
+//! The protocol is simple: each connection sends one [`Request`] as a line of
+//! JSON, and receives one [`Response`] as a line of JSON.
+//!
+//! Widgets are identified by the name they were given with
+//! [`Cnx::add_named_widget_to()`] or, if they weren't given a name, by their
+//! index in the order they were added (starting from `0`).
+//!
+//! [`Cnx::set_ipc_socket()`]: ../struct.Cnx.html#method.set_ipc_socket
+//! [`Cnx::add_named_widget_to()`]: ../struct.Cnx.html#method.add_named_widget_to
+//! [`send_request()`]: fn.send_request.html
+//! [`Request`]: enum.Request.html
+//! [`Response`]: enum.Response.html
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use cnx::ipc::{self, Request, Response};
+//! # fn run() -> ::cnx::Result<()> {
+//! let request = Request::Send {
+//!     widget: "status".to_owned(),
+//!     message: "Building...".to_owned(),
+//! };
+//! match ipc::send_request(ipc::default_socket_path(), &request)? {
+//!     Response::Error(e) => eprintln!("{}", e),
+//!     _ => {}
+//! }
+//! # Ok(())
+//! # }
+//! # fn main() { run().unwrap(); }
+//! ```
+
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+
+use failure::{bail, ResultExt};
+use futures::channel::{mpsc, oneshot};
+use futures::Stream;
+use log::*;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::task;
+
+use crate::bar::Region;
+use crate::text::Text;
+use crate::widgets::{Event, WidgetList};
+use crate::Result;
+
+/// A request sent to a running Cnx instance.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    /// Hides the bar.
+    Hide,
+    /// Shows the bar, if it was hidden.
+    Show,
+    /// Hides the bar if it's shown, or shows it if it's hidden.
+    Toggle,
+    /// Restarts a widget, so that it shows up-to-date texts immediately.
+    Refresh {
+        /// The name or index of the widget.
+        widget: String,
+    },
+    /// Sends a message to a widget, such as the text for a [`Custom`] widget
+    /// to show. The widget receives it as an [`Event::Message`].
+    ///
+    /// [`Custom`]: ../widgets/struct.Custom.html
+    /// [`Event::Message`]: ../widgets/enum.Event.html#variant.Message
+    Send {
+        /// The name or index of the widget.
+        widget: String,
+        /// The message to send.
+        message: String,
+    },
+    /// Asks for the texts currently shown by every widget.
+    Query,
+}
+
+/// The response to a [`Request`].
+///
+/// [`Request`]: enum.Request.html
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+    /// The request succeeded.
+    Ok,
+    /// The request failed, for the given reason.
+    Error(String),
+    /// The texts currently shown by every widget, in response to
+    /// [`Request::Query`].
+    ///
+    /// [`Request::Query`]: enum.Request.html#variant.Query
+    Widgets(Vec<WidgetInfo>),
+}
+
+/// The texts currently shown by a widget.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct WidgetInfo {
+    /// The index of the widget, in the order widgets were added.
+    pub index: usize,
+    /// The name of the widget, if it was given one.
+    pub name: Option<String>,
+    /// The region of the bar the widget is shown in.
+    pub region: Region,
+    /// The widget's texts, without any markup.
+    pub texts: Vec<String>,
+}
+
+/// Returns the socket path used by the `cnx` binary and `cnx-msg`:
+/// `$XDG_RUNTIME_DIR/cnx.sock`, or a per-user socket in the temporary directory
+/// if `$XDG_RUNTIME_DIR` isn't set.
+pub fn default_socket_path() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(ref dir) if !dir.is_empty() => Path::new(dir).join("cnx.sock"),
+        _ => {
+            let uid = unsafe { libc::getuid() };
+            env::temp_dir().join(format!("cnx-{}.sock", uid))
+        }
+    }
+}
+
+/// Sends a request to the Cnx instance listening on the socket at `path`, and
+/// waits for its response.
+pub fn send_request<P: AsRef<Path>>(path: P, request: &Request) -> Result<Response> {
+    let path = path.as_ref();
+    let mut stream = net::UnixStream::connect(path)
+        .with_context(|_| format!("Failed to connect to {}", path.display()))?;
+    let mut json = serde_json::to_string(request).context("Failed to serialize request")?;
+    json.push('\n');
+    stream
+        .write_all(json.as_bytes())
+        .context("Failed to send request")?;
+
+    let mut line = String::new();
+    BufReader::new(stream)
+        .read_line(&mut line)
+        .context("Failed to read response")?;
+    let response = serde_json::from_str(&line).context("Invalid response")?;
+    Ok(response)
+}
+
+/// A request received over the socket, and where to send its response.
+pub(crate) type Requests = Pin<Box<dyn Stream<Item = (Request, oneshot::Sender<Response>)>>>;
+
+/// Starts listening for requests on the socket at `path`.
+///
+/// This must be called from within a `tokio::task::LocalSet`, as connections
+/// are accepted by a task spawned on the current thread. The socket is
+/// removed once that task is dropped, along with the runtime.
+pub(crate) fn listen(path: PathBuf) -> Result<Requests> {
+    let listener = bind(&path)?;
+    // Created outside the task, so that the socket is removed even if the
+    // task is dropped before it's first polled.
+    let socket = SocketFile(path);
+    let (sender, receiver) = mpsc::unbounded();
+    task::spawn_local(async move {
+        let _socket = socket;
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    task::spawn_local(respond(stream, sender.clone()));
+                }
+                Err(e) => warn!("Failed to accept IPC connection: {}", e),
+            }
+        }
+    });
+    Ok(Box::pin(receiver))
+}
+
+fn bind(path: &Path) -> Result<UnixListener> {
+    // A Cnx instance which didn't exit cleanly will have left its socket
+    // behind, which would stop us from binding. Remove it, but only if nobody
+    // is listening on it.
+    if path.exists() {
+        if net::UnixStream::connect(path).is_ok() {
+            bail!(
+                "Another instance of Cnx is already listening on {}",
+                path.display()
+            );
+        }
+        fs::remove_file(path)
+            .with_context(|_| format!("Failed to remove stale socket {}", path.display()))?;
+    }
+    let listener = UnixListener::bind(path)
+        .with_context(|_| format!("Failed to listen on {}", path.display()))?;
+    Ok(listener)
+}
+
+/// Removes the socket file when dropped.
+struct SocketFile(PathBuf);
+
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+async fn respond(
+    stream: UnixStream,
+    requests: mpsc::UnboundedSender<(Request, oneshot::Sender<Response>)>,
+) {
+    let (reader, mut writer) = stream.into_split();
+    let mut line = String::new();
+    if let Err(e) = tokio::io::BufReader::new(reader).read_line(&mut line).await {
+        warn!("Failed to read IPC request: {}", e);
+        return;
+    }
+
+    let response = match serde_json::from_str::<Request>(&line) {
+        Ok(request) => {
+            debug!("Received IPC request: {:?}", request);
+            let (sender, receiver) = oneshot::channel();
+            let _ = requests.unbounded_send((request, sender));
+            receiver
+                .await
+                .unwrap_or_else(|_| Response::Error("Cnx is shutting down".to_owned()))
+        }
+        Err(e) => Response::Error(format!("Invalid request: {}", e)),
+    };
+
+    let mut json = match serde_json::to_string(&response) {
+        Ok(json) => json,
+        Err(e) => {
+            warn!("Failed to serialize IPC response: {}", e);
+            return;
+        }
+    };
+    json.push('\n');
+    if let Err(e) = writer.write_all(json.as_bytes()).await {
+        warn!("Failed to send IPC response: {}", e);
+    }
+}
+
+/// The parts of a bar which requests can control.
+pub(crate) trait Control {
+    fn is_hidden(&self) -> bool;
+
+    fn set_hidden(&mut self, hidden: bool) -> Result<()>;
+
+    /// Returns the region of each widget, and the texts it's showing.
+    fn contents(&self) -> (&[Region], &[Vec<Text>]);
+}
+
+/// Carries out a request, returning the response to send back.
+pub(crate) fn handle_request<C: Control>(
+    bar: &mut C,
+    widget_list: &mut WidgetList,
+    request: Request,
+) -> Result<Response> {
+    let unknown = |widget: &str| Response::Error(format!("No widget named {:?}", widget));
+
+    let response = match request {
+        Request::Hide => {
+            bar.set_hidden(true)?;
+            Response::Ok
+        }
+        Request::Show => {
+            bar.set_hidden(false)?;
+            Response::Ok
+        }
+        Request::Toggle => {
+            let hidden = !bar.is_hidden();
+            bar.set_hidden(hidden)?;
+            Response::Ok
+        }
+        Request::Refresh { widget } => match widget_list.find(&widget) {
+            Some(idx) => {
+                widget_list.restart(idx);
+                Response::Ok
+            }
+            None => unknown(&widget),
+        },
+        Request::Send { widget, message } => match widget_list.find(&widget) {
+            Some(idx) => {
+                widget_list
+                    .event_senders()
+                    .send(idx, Event::Message(message));
+                Response::Ok
+            }
+            None => unknown(&widget),
+        },
+        Request::Query => {
+            let (regions, contents) = bar.contents();
+            let widgets = regions
+                .iter()
+                .zip(contents)
+                .enumerate()
+                .map(|(index, (&region, texts))| WidgetInfo {
+                    index,
+                    name: widget_list.name(index).map(str::to_owned),
+                    region,
+                    texts: texts
+                        .iter()
+                        .map(|text| text.plain_text().into_owned())
+                        .collect(),
+                })
+                .collect();
+            Response::Widgets(widgets)
+        }
+    };
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn requests_round_trip() {
+        let request = Request::Send {
+            widget: "status".to_owned(),
+            message: "hello".to_owned(),
+        };
+        let json = serde_json::to_string(&request).unwrap();
+        assert_eq!(
+            json,
+            r#"{"command":"send","widget":"status","message":"hello"}"#
+        );
+        assert_eq!(serde_json::from_str::<Request>(&json).unwrap(), request);
+        assert_eq!(
+            serde_json::from_str::<Request>(r#"{"command":"toggle"}"#).unwrap(),
+            Request::Toggle
+        );
+    }
+
+    #[test]
+    fn responses_serialize() {
+        let response = Response::Widgets(vec![WidgetInfo {
+            index: 0,
+            name: None,
+            region: Region::Right,
+            texts: vec!["12:00".to_owned()],
+        }]);
+        assert_eq!(
+            serde_json::to_string(&response).unwrap(),
+            r#"{"widgets":[{"index":0,"name":null,"region":"right","texts":["12:00"]}]}"#
+        );
+        assert_eq!(serde_json::to_string(&Response::Ok).unwrap(), r#""ok""#);
+    }
+}
@@ -4,7 +4,7 @@
 //!
 //! Cnx is written to be customisable, simple and fast. Where possible, it
 //! prefers to asynchronously wait for changes in the underlying data sources
-//! (and uses [`mio`]/[`tokio`] to achieve this), rather than periodically
+//! (and uses [`tokio`] to achieve this), rather than periodically
 //! calling out to external programs.
 //!
 //! # How to use
@@ -42,15 +42,23 @@
 //! }
 //! ```
 //!
-//! A more complex example is given in [`src/bin/cnx.rs`] alongside the project.
-//! (This is the default `[bin]` target for the crate, so you _could_ use it by
-//! either executing `cargo run` from the crate root, or even running `cargo
-//! install cnx; cnx`. However, neither of these are recommended as options for
-//! customizing Cnx are then limited).
+//! A more complex example is given in [`src/bin/cnx/main.rs`] alongside the
+//! project. (This is the default `[bin]` target for the crate, so you can also
+//! use it directly by running `cargo install cnx; cnx`. It reads the position
+//! of the bar and its widgets from a configuration file, which is described in
+//! the [`README`][readme-config]).
 //!
 //! Before running Cnx, you'll need to make sure your system has the required
 //! dependencies, which are described in the [`README`][readme-deps].
 //!
+//! Cnx's widgets can also be shown by another status bar, such as `lemonbar` or
+//! `i3bar`, by choosing a different [`OutputMode`] with
+//! [`Cnx::set_output_mode()`].
+//!
+//! Scripts can control a running bar (e.g. to hide it, or to show some text
+//! in a [`Custom`] widget) over a Unix domain socket, using the `cnx-msg`
+//! program. See the [`ipc`] module for more details.
+//!
 //! # Built-in widgets
 //!
 //! There are currently these widgets available:
@@ -58,16 +66,25 @@
 //! - [`Active Window Title`] — Shows the title ([`EWMH`]'s `_NET_WM_NAME`) for
 //!   the currently focused window ([`EWMH`]'s `_NEW_ACTIVE_WINDOW`).
 //! - [`Pager`] — Shows the WM's workspaces/groups, highlighting whichever is
-//!   currently active. (Uses [`EWMH`]'s `_NET_DESKTOP_NAMES`,
+//!   currently active. Click a workspace or scroll over the widget to switch
+//!   workspace. (Uses [`EWMH`]'s `_NET_DESKTOP_NAMES`,
 //!   `_NET_NUMBER_OF_DESKTOPS` and `_NET_CURRENT_DESKTOP`).
-//! - [`Sensors`] — Periodically parses and displays the output of the
-//!   [`lm_sensors`] utility, allowing CPU temperature to be displayed.
+//! - [`Sensors`] — Periodically reads the kernel's hwmon and thermal zone
+//!   sensors from `/sys`, allowing CPU temperature, fan speeds, voltages, power
+//!   and current to be displayed. (The output of the [`lm_sensors`]
+//!   utility can be used instead.)
 //! - [`Volume`] — Uses `alsa-lib` to show the current volume/mute status of the
-//!   default output device. (Disable by removing default feature
-//!   `volume-control`).
+//!   default output device. Scroll over it to change the volume, or click it to
+//!   toggle mute. (Disable by removing default feature `volume-control`).
 //! - [`Battery`] — Uses `/sys/class/power_supply/` to show details on the
 //!   remaining battery and charge status.
-//! - [`Clock`] — Shows the time.
+//! - [`Clock`] — Shows the time. Click it to switch between formats, or to
+//!   pop up a calendar.
+//! - [`Timer`] — A stopwatch, countdown or pomodoro timer, which is started,
+//!   paused and reset by clicking it or with `cnx-msg`.
+//! - [`Tray`] — Hosts system tray icons, using the freedesktop.org [`System
+//!   Tray`] protocol.
+//! - [`Custom`] — Shows text sent to it by scripts, using `cnx-msg`.
 //!
 //! # Dependencies
 //!
@@ -83,7 +100,8 @@
 //! Some widgets have additional dependencies:
 //!
 //!  - [`Volume`] widget relies on `alsa-lib`
-//!  - [`Sensors`] widget relies on [`lm_sensors`] being installed.
+//!  - [`Sensors`] widget relies on [`lm_sensors`] being installed, if it's
+//!    told to use it.
 //!
 //! # Creating new widgets
 //!
@@ -95,13 +113,15 @@
 //! documentation of the [`Widget`] trait. The built-in [`widgets`] should give you
 //! some examples on which to base your work.
 //!
-//! [`mio`]: https://docs.rs/mio
 //! [`tokio`]: https://tokio.rs/
 //! [`Cnx`]: struct.Cnx.html
+//! [`OutputMode`]: enum.OutputMode.html
+//! [`Cnx::set_output_mode()`]: struct.Cnx.html#method.set_output_mode
 //! [`QTile`]: http://www.qtile.org/
 //! [`dwm`]: http://dwm.suckless.org/
 //! [readme-deps]: https://github.com/mjkillough/cnx/blob/master/README.md#dependencies
-//! [`src/bin/cnx.rs`]: https://github.com/mjkillough/cnx/blob/master/src/bin/cnx.rs
+//! [`src/bin/cnx/main.rs`]: https://github.com/mjkillough/cnx/blob/master/src/bin/cnx/main.rs
+//! [readme-config]: https://github.com/mjkillough/cnx/blob/master/README.md#configuring-the-cnx-binary
 //! [`Active Window Title`]: widgets/struct.ActiveWindowTitle.html
 //! [`EWMH`]: https://specifications.freedesktop.org/wm-spec/wm-spec-latest.html
 //! [`Pager`]: widgets/struct.Pager.html
@@ -110,6 +130,11 @@
 //! [`Volume`]: widgets/struct.Volume.html
 //! [`Battery`]: widgets/struct.Battery.html
 //! [`Clock`]: widgets/struct.Clock.html
+//! [`Timer`]: widgets/struct.Timer.html
+//! [`Tray`]: widgets/struct.Tray.html
+//! [`Custom`]: widgets/struct.Custom.html
+//! [`ipc`]: ipc/index.html
+//! [`System Tray`]: https://specifications.freedesktop.org/systemtray-spec/systemtray-spec-latest.html
 //! [`Widget`]: widgets/trait.Widget.html
 //! [`widgets`]: widgets/index.html
 
@@ -117,16 +142,28 @@
 #![allow(clippy::new_ret_no_self)]
 
 mod bar;
+pub mod headless;
+pub mod ipc;
+mod stdout;
 pub mod text;
 pub mod widgets;
+mod x11;
+
+use std::path::PathBuf;
+use std::pin::Pin;
 
 use failure::ResultExt;
-use tokio_core::reactor::{Core, Handle};
-use tokio_timer::Timer;
+use futures::{future, stream, Stream, StreamExt};
+use log::*;
+use tokio::runtime;
+use tokio::task::LocalSet;
 
-use crate::bar::Bar;
+use crate::bar::Bars;
+use crate::widgets::{ErrorPlaceholder, WidgetFactory, WidgetList};
+use crate::x11::LazyConnection;
 
-pub use crate::bar::Position;
+pub use crate::bar::{BarHeight, BarStyle, Position, Region, Separator};
+pub use crate::stdout::OutputMode;
 pub use crate::widgets::Widget;
 
 pub type Result<T> = std::result::Result<T, failure::Error>;
@@ -167,19 +204,38 @@ pub type Result<T> = std::result::Result<T, failure::Error>;
 /// # fn main() { run().unwrap(); }
 /// ```
 pub struct Cnx {
-    core: Core,
-    timer: Timer,
-    bar: Bar,
-    widgets: Vec<Box<dyn Widget>>,
+    style: BarStyle,
+    outputs: Option<Vec<String>>,
+    output_mode: OutputMode,
+    transparent: bool,
+    error_placeholder: ErrorPlaceholder,
+    widgets: Vec<(Region, Option<String>, WidgetFactory)>,
+    x_connection: LazyConnection,
+    reloader: Option<(Triggers, Reload)>,
+    ipc_socket: Option<PathBuf>,
 }
 
+type Triggers = Pin<Box<dyn Stream<Item = ()>>>;
+type Reload = Box<dyn FnMut(&mut Cnx) -> Result<()>>;
+
+/// The style of the bar after a reload, the widgets to show and the regions to
+/// show them in.
+pub(crate) type Reloads = Pin<Box<dyn Stream<Item = (BarStyle, Vec<Region>, WidgetList)>>>;
+
 impl Cnx {
     /// Creates a new `Cnx` instance.
     ///
-    /// This creates a new `Cnx` instance at either the top or bottom of the
-    /// screen, depending on the value of the [`Position`] enum.
+    /// This creates a new `Cnx` instance docked to one edge of the screen,
+    /// depending on the value of the [`Position`] enum. A
+    /// [`BarStyle`] may be given instead, to also choose the bar's background,
+    /// margins, border, corner radius and height.
+    ///
+    /// By default, a bar is shown on every monitor that RandR reports. Use
+    /// [`set_outputs()`] to choose which monitors the bar is shown on.
     ///
     /// [`Position`]: enum.Position.html
+    /// [`BarStyle`]: struct.BarStyle.html
+    /// [`set_outputs()`]: #method.set_outputs
     ///
     /// # Examples
     ///
@@ -191,39 +247,287 @@ impl Cnx {
     /// # use cnx::{Cnx, Position};
     /// let mut cnx = Cnx::new(Position::Bottom);
     /// ```
-    pub fn new(position: Position) -> Result<Cnx> {
-        Ok(Cnx {
-            core: Core::new().context("Could not create Tokio Core")?,
-            timer: Timer::default(),
-            bar: Bar::new(position)?,
+    pub fn new<S: Into<BarStyle>>(style: S) -> Result<Cnx> {
+        Ok(Cnx::with_x_connection(
+            style.into(),
+            LazyConnection::default(),
+        ))
+    }
+
+    fn with_x_connection(style: BarStyle, x_connection: LazyConnection) -> Cnx {
+        Cnx {
+            style,
+            outputs: None,
+            output_mode: OutputMode::X11,
+            transparent: false,
+            error_placeholder: ErrorPlaceholder::default(),
             widgets: Vec::new(),
-        })
+            x_connection,
+            reloader: None,
+            ipc_socket: None,
+        }
+    }
+
+    /// Changes the position and [`BarStyle`] of the bar, as given to
+    /// [`new()`].
+    ///
+    /// [`BarStyle`]: struct.BarStyle.html
+    /// [`new()`]: #method.new
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::{BarStyle, Cnx, Position};
+    /// # use cnx::text::Color;
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx.set_style(BarStyle::new(Position::Bottom).with_background(Color::black()));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_style<S: Into<BarStyle>>(&mut self, style: S) {
+        self.style = style.into();
     }
 
-    fn handle(&self) -> Handle {
-        self.core.handle()
+    /// Restricts the bar to the given RandR outputs.
+    ///
+    /// By default, Cnx shows one bar on each connected monitor. This method
+    /// takes a list of output names (e.g. `DP-1` or `HDMI-0`, as shown by
+    /// `xrandr`) and shows a bar on only those outputs, in the given order.
+    ///
+    /// Bars are created, resized and destroyed as monitors are plugged in,
+    /// reconfigured or removed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::{Cnx, Position};
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx.set_outputs(vec!["DP-1", "HDMI-0"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_outputs<S: Into<String>>(&mut self, outputs: Vec<S>) {
+        self.outputs = Some(outputs.into_iter().map(Into::into).collect());
     }
 
-    fn timer(&self) -> Timer {
-        self.timer.clone()
+    /// Chooses how the widgets are shown.
+    ///
+    /// By default, Cnx draws its own bar. Other [`OutputMode`]s write the
+    /// widgets' texts to stdout instead, in a format that another status bar
+    /// (such as `lemonbar` or `i3bar`) can show.
+    ///
+    /// [`OutputMode`]: enum.OutputMode.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::{Cnx, OutputMode, Position};
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx.set_output_mode(OutputMode::Lemonbar);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_output_mode(&mut self, output_mode: OutputMode) {
+        self.output_mode = output_mode;
+    }
+
+    /// Chooses whether the bar can be transparent.
+    ///
+    /// By default, the bar's window is opaque and parts of it without a
+    /// background color are black. If `transparent` is set, the window is
+    /// created with a 32-bit ARGB visual so that, when a compositor (such as
+    /// `picom`) is running, texts without a background color are drawn over
+    /// whatever is behind the bar, and semi-transparent colors (such as
+    /// `#00000080`) are blended with it. Without a compositor, the bar looks
+    /// the same as an opaque one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::{Cnx, Position};
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx.set_transparent(true);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_transparent(&mut self, transparent: bool) {
+        self.transparent = transparent;
+    }
+
+    /// Chooses what is shown in place of a widget that has failed.
+    ///
+    /// If a widget returns an error, it is shown as an [`ErrorPlaceholder`]
+    /// (by default, a red `!`) and restarted after a delay, while the rest of
+    /// the bar carries on as normal. The error itself is logged.
+    ///
+    /// [`ErrorPlaceholder`]: widgets/enum.ErrorPlaceholder.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::{Cnx, Position};
+    /// # use cnx::text::Color;
+    /// # use cnx::widgets::ErrorPlaceholder;
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx.set_error_placeholder(ErrorPlaceholder::Text("?".to_owned(), Color::white()));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_error_placeholder(&mut self, placeholder: ErrorPlaceholder) {
+        self.error_placeholder = placeholder;
     }
 
     /// Adds a widget to the Cnx instance.
     ///
-    /// This method takes a [`Widget`] and adds it to the current Cnx instance,
-    /// to the right of any existing widgets.
+    /// This method takes a [`Widget`] and adds it to the left region of the
+    /// current Cnx instance, to the right of any existing widgets in that
+    /// region.
     ///
     /// It is recommended that you instead use the [`cnx_add_widget!()`] macro,
     /// as this will eventually grow to have a more flexible syntax for
     /// configuring widget attributes.
     ///
+    /// The widget is cloned each time it needs to be restarted after failing,
+    /// which is why it must implement `Clone`.
+    ///
     /// [`Widget`]: widgets/trait.Widget.html
     /// [`cnx_add_widget!()`]: macro.cnx_add_widget.html
     pub fn add_widget<W>(&mut self, widget: W)
     where
-        W: Widget + 'static,
+        W: Widget + Clone + 'static,
+    {
+        self.add_widget_to(Region::Left, widget);
+    }
+
+    /// Adds a widget to a region of the Cnx instance.
+    ///
+    /// This method takes a [`Widget`] and adds it to the given [`Region`] of
+    /// the current Cnx instance, to the right of any existing widgets in that
+    /// region.
+    ///
+    /// It is recommended that you instead use the [`cnx_add_widget!()`] macro.
+    ///
+    /// [`Widget`]: widgets/trait.Widget.html
+    /// [`Region`]: enum.Region.html
+    /// [`cnx_add_widget!()`]: macro.cnx_add_widget.html
+    pub fn add_widget_to<W>(&mut self, region: Region, widget: W)
+    where
+        W: Widget + Clone + 'static,
+    {
+        self.push_widget(region, None, widget);
+    }
+
+    /// Adds a named widget to a region of the Cnx instance.
+    ///
+    /// This is the same as [`add_widget_to()`], except that the widget can be
+    /// referred to by `name` in requests sent over the IPC socket (e.g. with
+    /// `cnx-msg refresh <name>`). See the [`ipc`] module for more details.
+    ///
+    /// [`add_widget_to()`]: #method.add_widget_to
+    /// [`ipc`]: ipc/index.html
+    pub fn add_named_widget_to<S, W>(&mut self, region: Region, name: S, widget: W)
+    where
+        S: Into<String>,
+        W: Widget + Clone + 'static,
     {
-        self.widgets.push(Box::new(widget) as Box<dyn Widget>);
+        self.push_widget(region, Some(name.into()), widget);
+    }
+
+    fn push_widget<W>(&mut self, region: Region, name: Option<String>, widget: W)
+    where
+        W: Widget + Clone + 'static,
+    {
+        let factory = move || Box::new(widget.clone()) as Box<dyn Widget>;
+        self.widgets.push((region, name, Box::new(factory)));
+    }
+
+    /// Replaces the widgets whenever `triggers` produces an item.
+    ///
+    /// Each time `triggers` produces an item, `reload` is called with a new
+    /// `Cnx` instance to add widgets to. If it succeeds, the running widgets
+    /// are stopped and replaced with the new widgets, without re-creating the
+    /// bar. If it returns an error, the error is logged and the current
+    /// widgets are kept.
+    ///
+    /// The new instance starts out with this instance's settings, but no
+    /// widgets. Its widgets, [`ErrorPlaceholder`] and style are used, so the
+    /// bar is moved, resized and redrawn if its position or [`BarStyle`] are
+    /// changed. If its outputs, output mode, transparency or IPC socket are
+    /// changed, a warning is logged that Cnx must be restarted for the changes
+    /// to apply.
+    ///
+    /// `triggers` is first polled once the bar is running, so it may wait on
+    /// signals or files using Tokio.
+    ///
+    /// [`ErrorPlaceholder`]: widgets/enum.ErrorPlaceholder.html
+    /// [`BarStyle`]: struct.BarStyle.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate cnx;
+    /// #
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// # let attr = Attributes {
+    /// #     font: Font::new("SourceCodePro 21"),
+    /// #     fg_color: Color::white(),
+    /// #     bg_color: None,
+    /// #     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    /// # };
+    /// # let triggers = futures::stream::pending();
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx_add_widget!(cnx, Clock::new(&cnx, attr.clone()));
+    /// cnx.set_reloader(triggers, move |cnx| {
+    ///     cnx_add_widget!(cnx, Clock::new(cnx, attr.clone()));
+    ///     Ok(())
+    /// });
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn set_reloader<S, F>(&mut self, triggers: S, reload: F)
+    where
+        S: Stream<Item = ()> + 'static,
+        F: FnMut(&mut Cnx) -> Result<()> + 'static,
+    {
+        self.reloader = Some((Box::pin(triggers), Box::new(reload)));
+    }
+
+    /// Listens for requests on a Unix domain socket at `path`.
+    ///
+    /// This lets other programs, such as `cnx-msg`, hide or show the bar,
+    /// refresh widgets, send messages to widgets and query what each widget
+    /// is showing. See the [`ipc`] module for the protocol.
+    ///
+    /// If a socket already exists at `path`, it's replaced, unless another
+    /// instance of Cnx is still listening on it, in which case [`run()`]
+    /// returns an error. The socket is removed when the bar stops.
+    ///
+    /// [`ipc`]: ipc/index.html
+    /// [`run()`]: #method.run
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::{ipc, Cnx, Position};
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx.set_ipc_socket(ipc::default_socket_path());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_ipc_socket<P: Into<PathBuf>>(&mut self, path: P) {
+        self.ipc_socket = Some(path.into());
     }
 
     /// Runs the Cnx instance.
@@ -231,17 +535,160 @@ impl Cnx {
     /// This method takes ownership of the Cnx instance and runs it until either
     /// the process is terminated, or an internal error is returned.
     pub fn run(mut self) -> Result<()> {
-        let handle = self.handle();
-        self.core
-            .run(self.bar.run_event_loop(&handle, self.widgets)?)
+        let runtime = runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("Could not create Tokio runtime")?;
+
+        let reloads = self.reloads();
+        let Cnx {
+            style,
+            outputs,
+            output_mode,
+            transparent,
+            error_placeholder,
+            widgets,
+            x_connection,
+            ipc_socket,
+            ..
+        } = self;
+        let (regions, widget_list) = widget_list(widgets, error_placeholder, output_mode);
+
+        // Events from the shared X connection are dispatched by a task that
+        // lives on this thread, alongside the bar and the widgets. The same
+        // goes for connections to the IPC socket.
+        LocalSet::new().block_on(&runtime, async move {
+            let requests = match ipc_socket {
+                Some(path) => ipc::listen(path)?,
+                None => Box::pin(stream::pending()),
+            };
+            match output_mode {
+                OutputMode::X11 => {
+                    let bars = Bars::new(x_connection.get()?, style, outputs, transparent)?;
+                    bars.run_event_loop(regions, widget_list, reloads, requests)
+                        .await
+                }
+                mode => stdout::run_event_loop(mode, regions, widget_list, reloads, requests).await,
+            }
+        })
+    }
+
+    /// Returns a stream of the new widgets to show each time the reloader
+    /// succeeds.
+    fn reloads(&mut self) -> Reloads {
+        let (triggers, mut reload) = match self.reloader.take() {
+            Some(reloader) => reloader,
+            None => return Box::pin(stream::pending()),
+        };
+        let mut running = self.settings();
+        Box::pin(triggers.filter_map(move |()| {
+            let mut cnx = running.settings();
+            let reloaded = match reload(&mut cnx) {
+                Ok(()) => {
+                    info!("Reloaded {} widgets", cnx.widgets.len());
+                    let ignored = running.changed_settings(&cnx);
+                    if !ignored.is_empty() {
+                        warn!(
+                            "Restart Cnx to apply the changes to its {}",
+                            ignored.join(", ")
+                        );
+                    }
+                    // Later reloads are compared with the style now in use.
+                    running.style = cnx.style.clone();
+                    let (regions, widget_list) =
+                        widget_list(cnx.widgets, cnx.error_placeholder, cnx.output_mode);
+                    Some((cnx.style, regions, widget_list))
+                }
+                Err(e) => {
+                    error!("Failed to reload, keeping the current widgets: {}", e);
+                    None
+                }
+            };
+            future::ready(reloaded)
+        }))
+    }
+
+    /// Returns a new instance with the same settings as this one, but none of
+    /// its widgets.
+    fn settings(&self) -> Cnx {
+        let mut cnx = Cnx::with_x_connection(self.style.clone(), self.x_connection.clone());
+        cnx.outputs = self.outputs.clone();
+        cnx.output_mode = self.output_mode;
+        cnx.transparent = self.transparent;
+        cnx.error_placeholder = self.error_placeholder.clone();
+        cnx.ipc_socket = self.ipc_socket.clone();
+        cnx
+    }
+
+    /// Returns the names of the settings which differ in `other`, other than
+    /// those which can be changed while the bar is running.
+    fn changed_settings(&self, other: &Cnx) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        if self.outputs != other.outputs {
+            changed.push("outputs");
+        }
+        if self.output_mode != other.output_mode {
+            changed.push("output mode");
+        }
+        if self.transparent != other.transparent {
+            changed.push("transparency");
+        }
+        if self.ipc_socket != other.ipc_socket {
+            changed.push("IPC socket");
+        }
+        changed
+    }
+
+    /// Returns a handle to the X connection shared by the bar and widgets.
+    pub(crate) fn x_connection(&self) -> LazyConnection {
+        self.x_connection.clone()
     }
 }
 
+/// Splits the widgets added to a `Cnx` instance into their regions and a
+/// `WidgetList` to run them.
+///
+/// Widgets which embed windows in the bar are left out, with a warning, unless
+/// Cnx draws its own bar.
+fn widget_list(
+    widgets: Vec<(Region, Option<String>, WidgetFactory)>,
+    placeholder: ErrorPlaceholder,
+    output_mode: OutputMode,
+) -> (Vec<Region>, WidgetList) {
+    let (regions, widgets) = widgets
+        .into_iter()
+        .enumerate()
+        .filter(|(idx, (_, name, factory))| {
+            if output_mode == OutputMode::X11 || !factory().embeds_windows() {
+                return true;
+            }
+            match name {
+                Some(name) => warn!(
+                    "Skipping widget {} ({:?}): it needs Cnx's own bar",
+                    idx, name
+                ),
+                None => warn!("Skipping widget {}: it needs Cnx's own bar", idx),
+            }
+            false
+        })
+        .map(|(_, widget)| widget)
+        .map(|(region, name, factory)| (region, (name, factory)))
+        .unzip();
+    (regions, WidgetList::new(widgets, placeholder))
+}
+
 /// Adds a `Widget` to a `Cnx` instance.
 ///
 /// This macro adds a [`Widget`] to a [`Cnx`] instance, placing it to the right
-/// of any existing widgets. (Internally, this macro uses
-/// [`Cnx::add_widget()`]).
+/// of any existing widgets. An optional [`Region`] can be given before the
+/// widget to choose which region of the bar it is shown in; by default it is
+/// added to the left region. (Internally, this macro uses
+/// [`Cnx::add_widget_to()`]).
+///
+/// ```ignore
+/// cnx_add_widget!(cnx, DummyWidget::new(&cnx));
+/// cnx_add_widget!(cnx, Region::Right, DummyWidget::new(&cnx));
+/// ```
 ///
 /// This macro serves two purposes:
 ///
@@ -266,11 +713,107 @@ impl Cnx {
 ///
 /// [`Widget`]: widgets/trait.Widget.html
 /// [`Cnx`]: struct.Cnx.html
-/// [`Cnx::add_widget()`]: struct.Cnx.html#method.add_widget
+/// [`Region`]: enum.Region.html
+/// [`Cnx::add_widget_to()`]: struct.Cnx.html#method.add_widget_to
 #[macro_export]
 macro_rules! cnx_add_widget {
     ($cnx:ident, $widget:expr) => {
+        $crate::cnx_add_widget!($cnx, $crate::Region::Left, $widget);
+    };
+    ($cnx:ident, $region:expr, $widget:expr) => {
         let widget = $widget;
-        $cnx.add_widget(widget);
+        $cnx.add_widget_to($region, widget);
     };
 }
+
+#[cfg(test)]
+mod test {
+    use failure::bail;
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::widgets::WidgetStream;
+
+    #[derive(Clone)]
+    struct Empty;
+
+    impl Widget for Empty {
+        fn stream(self: Box<Self>) -> Result<WidgetStream> {
+            Ok(Box::pin(stream::pending()))
+        }
+    }
+
+    #[derive(Clone)]
+    struct Docked;
+
+    impl Widget for Docked {
+        fn stream(self: Box<Self>) -> Result<WidgetStream> {
+            Ok(Box::pin(stream::pending()))
+        }
+
+        fn embeds_windows(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn embedding_widgets_need_own_bar() {
+        let add_widgets = |mode| {
+            let mut cnx = Cnx::new(Position::Top).unwrap();
+            cnx.add_named_widget_to(Region::Left, "title", Empty);
+            cnx.add_named_widget_to(Region::Right, "tray", Docked);
+            widget_list(cnx.widgets, cnx.error_placeholder, mode)
+        };
+
+        let (regions, widgets) = add_widgets(OutputMode::X11);
+        assert_eq!(regions, [Region::Left, Region::Right]);
+        assert_eq!(widgets.name(1), Some("tray"));
+
+        let (regions, widgets) = add_widgets(OutputMode::Lemonbar);
+        assert_eq!(regions, [Region::Left]);
+        assert_eq!(widgets.name(0), Some("title"));
+        assert_eq!(widgets.name(1), None);
+    }
+
+    #[test]
+    fn failed_reload_keeps_widgets() {
+        let mut cnx = Cnx::new(Position::Top).unwrap();
+        cnx.add_named_widget_to(Region::Left, "old", Empty);
+        let mut attempts = 0;
+        cnx.set_reloader(stream::iter(vec![(), ()]), move |cnx| {
+            attempts += 1;
+            cnx.add_named_widget_to(Region::Right, "new", Empty);
+            if attempts == 1 {
+                bail!("Invalid widget at line 3: unknown field `batery`");
+            }
+            Ok(())
+        });
+
+        // The first reload fails, so produces no widgets to replace the
+        // running ones with. Only the second replaces them.
+        let reloads: Vec<_> = block_on(cnx.reloads().collect());
+        assert_eq!(reloads.len(), 1);
+        let (_, ref regions, ref widgets) = reloads[0];
+        assert_eq!(regions, &[Region::Right]);
+        assert_eq!(widgets.name(0), Some("new"));
+
+        let (regions, widgets) = widget_list(cnx.widgets, cnx.error_placeholder, cnx.output_mode);
+        assert_eq!(regions, [Region::Left]);
+        assert_eq!(widgets.name(0), Some("old"));
+    }
+
+    #[test]
+    fn reload_notes_settings_needing_restart() {
+        let cnx = Cnx::new(Position::Top).unwrap();
+        let mut reloaded = cnx.settings();
+        assert!(cnx.changed_settings(&reloaded).is_empty());
+
+        // The style is applied to the running bar.
+        reloaded.set_style(BarStyle::new(Position::Left).with_corner_radius(4.0));
+        assert!(cnx.changed_settings(&reloaded).is_empty());
+
+        reloaded.set_outputs(vec!["DP-1"]);
+        reloaded.set_transparent(true);
+        assert_eq!(cnx.changed_settings(&reloaded), ["outputs", "transparency"]);
+    }
+}
@@ -0,0 +1,460 @@
+use std::io::{self, BufRead, Write};
+use std::thread;
+
+use cairo::{Format, ImageSurface};
+use failure::{format_err, ResultExt};
+use futures::channel::mpsc;
+use futures::{stream, Stream, StreamExt};
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use crate::bar::Region;
+use crate::ipc::{self, Requests};
+use crate::text::{Color, Text};
+use crate::widgets::{Button, Click, Event, EventSenders, Modifiers, WidgetList};
+use crate::{Reloads, Result};
+
+/// An enum specifying how Cnx shows its widgets.
+///
+/// Passed to [`Cnx::set_output_mode()`]. By default, Cnx draws its own bar in
+/// an X window. The other modes don't create a window, and instead write a
+/// line to stdout each time a widget updates, so that Cnx's widgets can be
+/// shown by another status bar.
+///
+/// Widgets that show X windows in place of their text (such as the [`Tray`])
+/// can only be shown by Cnx's own bar, and are left out of the other modes.
+///
+/// [`Cnx::set_output_mode()`]: struct.Cnx.html#method.set_output_mode
+/// [`Tray`]: widgets/struct.Tray.html
+///
+/// # Examples
+///
+/// ```
+/// # use cnx::{Cnx, OutputMode, Position};
+/// # fn run() -> ::cnx::Result<()> {
+/// let mut cnx = Cnx::new(Position::Top)?;
+/// cnx.set_output_mode(OutputMode::I3bar);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OutputMode {
+    /// Draw the bar in an X window on each monitor.
+    X11,
+    /// Write each region's texts as plain text, separated by spaces. Regions
+    /// are separated by ` | `.
+    Plain,
+    /// Write lines for [`lemonbar`], using its formatting tags to align each
+    /// region and to apply the colors and padding from each text's
+    /// `Attributes`.
+    ///
+    /// [`lemonbar`]: https://github.com/LemonBoy/bar
+    Lemonbar,
+    /// Speak the [`i3bar` protocol], as used by `i3bar` and `swaybar`.
+    ///
+    /// Each text is written as a block, named after the index of its widget,
+    /// with the index of the text as its instance. Click events that `i3bar`
+    /// writes to our stdin are passed on to the widget that was clicked.
+    ///
+    /// [`i3bar` protocol]: https://i3wm.org/docs/i3bar-protocol.html
+    I3bar,
+}
+
+/// A block in the `i3bar` protocol.
+#[derive(Serialize)]
+struct Block<'a> {
+    full_text: &'a str,
+    color: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    background: Option<String>,
+    separator: bool,
+    min_width: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    markup: Option<&'static str>,
+    name: String,
+    instance: String,
+}
+
+/// A click event in the `i3bar` protocol.
+#[derive(Debug, Deserialize)]
+struct ClickEvent {
+    name: String,
+    instance: String,
+    button: u8,
+    #[serde(default)]
+    modifiers: Vec<String>,
+    #[serde(default)]
+    relative_x: f64,
+    #[serde(default)]
+    relative_y: f64,
+}
+
+impl ClickEvent {
+    /// Returns the index of the widget that was clicked, and the `Click` to
+    /// send it.
+    fn to_click(&self) -> Result<(usize, Click)> {
+        let widget_idx = self
+            .name
+            .parse::<usize>()
+            .with_context(|_| format!("Invalid block name: {}", self.name))?;
+        let index = self
+            .instance
+            .parse::<usize>()
+            .with_context(|_| format!("Invalid block instance: {}", self.instance))?;
+        let has_modifier = |name: &str| self.modifiers.iter().any(|m| m == name);
+        let click = Click {
+            button: Button::from_x11(self.button),
+            modifiers: Modifiers {
+                shift: has_modifier("Shift"),
+                control: has_modifier("Control"),
+                alt: has_modifier("Mod1"),
+                logo: has_modifier("Mod4"),
+            },
+            index,
+            x: self.relative_x,
+            y: self.relative_y,
+        };
+        Ok((widget_idx, click))
+    }
+}
+
+/// Parses a line of the infinite JSON array of click events that `i3bar`
+/// writes to our stdin.
+fn parse_click_event(line: &str) -> Result<Option<ClickEvent>> {
+    let line = line.trim().trim_start_matches(['[', ',']);
+    if line.is_empty() {
+        return Ok(None);
+    }
+    let event = serde_json::from_str(line).context("Invalid click event")?;
+    Ok(Some(event))
+}
+
+/// Reads click events from stdin on a separate thread, as stdin can't be
+/// polled asynchronously.
+fn click_events() -> impl Stream<Item = ClickEvent> {
+    let (sender, receiver) = mpsc::unbounded();
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    error!("Error reading click events: {}", e);
+                    return;
+                }
+            };
+            match parse_click_event(&line) {
+                Ok(Some(event)) => {
+                    if sender.unbounded_send(event).is_err() {
+                        return;
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Ignoring click event {:?}: {}", line, e),
+            }
+        }
+    });
+    receiver
+}
+
+/// Escapes text so that `lemonbar` doesn't interpret it as formatting tags.
+fn escape_lemonbar(text: &str) -> String {
+    text.replace('%', "%%")
+}
+
+/// Returns a color in the `#rrggbb` or `#aarrggbb` form `lemonbar` expects.
+fn lemonbar_color(color: &Color) -> String {
+    let [red, green, blue, alpha] = color.to_bytes();
+    if alpha == 255 {
+        format!("#{:02x}{:02x}{:02x}", red, green, blue)
+    } else {
+        format!("#{:02x}{:02x}{:02x}{:02x}", alpha, red, green, blue)
+    }
+}
+
+/// Returns a `lemonbar` tag which leaves a gap of the given number of pixels.
+fn lemonbar_offset(pixels: f64) -> String {
+    if pixels >= 1.0 {
+        format!("%{{O{}}}", pixels.round())
+    } else {
+        String::new()
+    }
+}
+
+/// Writes widget texts to stdout in one of the formats that other status bars
+/// understand.
+struct StdoutBar {
+    mode: OutputMode,
+    regions: Vec<Region>,
+    contents: Vec<Vec<Text>>,
+    // Used to measure texts, so that we can tell i3bar how wide they are.
+    surface: ImageSurface,
+    event_senders: EventSenders,
+    hidden: bool,
+}
+
+impl StdoutBar {
+    fn new(mode: OutputMode) -> Result<StdoutBar> {
+        let surface = ImageSurface::create(Format::ARgb32, 1, 1)
+            .map_err(|status| format_err!("Failed to create image surface: {:?}", status))?;
+        Ok(StdoutBar {
+            mode,
+            regions: Vec::new(),
+            contents: Vec::new(),
+            surface,
+            event_senders: EventSenders::default(),
+            hidden: false,
+        })
+    }
+
+    /// Replaces the widgets whose texts are written. Nothing is written until
+    /// the new widgets produce their texts.
+    fn set_widgets(&mut self, regions: Vec<Region>, widget_list: &WidgetList) {
+        self.contents = vec![Vec::new(); regions.len()];
+        self.regions = regions;
+        self.event_senders = widget_list.event_senders();
+    }
+
+    /// Returns the texts in each region, along with the index of their widget
+    /// and their index within the widget's texts.
+    fn region_texts(&self, region: Region) -> Vec<(usize, usize, &Text)> {
+        self.contents
+            .iter()
+            .zip(&self.regions)
+            .enumerate()
+            .filter(|&(_, (_, r))| *r == region)
+            .flat_map(|(widget_idx, (texts, _))| {
+                texts
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, text)| text.embed.is_none())
+                    .map(move |(index, text)| (widget_idx, index, text))
+            })
+            .collect()
+    }
+
+    fn format_plain(&self) -> String {
+        [Region::Left, Region::Center, Region::Right]
+            .iter()
+            .map(|&region| {
+                self.region_texts(region)
+                    .into_iter()
+                    .map(|(_, _, text)| text.plain_text())
+                    .filter(|text| !text.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .filter(|region| !region.is_empty())
+            .collect::<Vec<_>>()
+            .join(" | ")
+    }
+
+    fn format_lemonbar(&self) -> String {
+        let mut line = String::new();
+        let regions = [
+            (Region::Left, "%{l}"),
+            (Region::Center, "%{c}"),
+            (Region::Right, "%{r}"),
+        ];
+        for &(region, tag) in &regions {
+            line.push_str(tag);
+            for (_, _, text) in self.region_texts(region) {
+                let attr = &text.attr;
+                line.push_str(&format!("%{{F{}}}", lemonbar_color(&attr.fg_color)));
+                if let Some(ref bg_color) = attr.bg_color {
+                    line.push_str(&format!("%{{B{}}}", lemonbar_color(bg_color)));
+                }
+                line.push_str(&lemonbar_offset(attr.padding.left));
+                line.push_str(&escape_lemonbar(&text.plain_text()));
+                line.push_str(&lemonbar_offset(attr.padding.right));
+                if attr.bg_color.is_some() {
+                    line.push_str("%{B-}");
+                }
+                line.push_str("%{F-}");
+            }
+        }
+        line
+    }
+
+    fn format_i3bar(&self) -> Result<String> {
+        let mut blocks = Vec::new();
+        for &region in &[Region::Left, Region::Center, Region::Right] {
+            for (widget_idx, index, text) in self.region_texts(region) {
+                let width = text.clone().compute(&self.surface)?.width;
+                // Only separate widgets from each other, not the texts within
+                // a widget (such as each desktop in the pager).
+                let separator = index + 1 == self.contents[widget_idx].len();
+                blocks.push(Block {
+                    full_text: &text.text,
+                    color: text.attr.fg_color.to_hex(),
+                    background: text.attr.bg_color.as_ref().map(|c| c.to_hex()),
+                    separator,
+                    min_width: width.round() as u32,
+                    // Invalid markup is shown as it is, just as on our own bar.
+                    markup: text.valid_markup().map(|_| "pango"),
+                    name: widget_idx.to_string(),
+                    instance: index.to_string(),
+                });
+            }
+        }
+        let json = serde_json::to_string(&blocks).context("Failed to serialize blocks")?;
+        Ok(format!("{},", json))
+    }
+
+    fn write_header(&self) -> Result<()> {
+        if self.mode == OutputMode::I3bar {
+            // The header is followed by the opening of an infinite array, of
+            // which each status line is an element.
+            self.write_line(r#"{"version":1,"click_events":true}"#)?;
+            self.write_line("[")?;
+        }
+        Ok(())
+    }
+
+    fn write_line(&self, line: &str) -> Result<()> {
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        writeln!(stdout, "{}", line).context("Failed to write to stdout")?;
+        stdout.flush().context("Failed to flush stdout")?;
+        Ok(())
+    }
+
+    fn update_widget_contents(&mut self, new_contents: Vec<Option<Vec<Text>>>) -> Result<()> {
+        for (new, old) in new_contents.into_iter().zip(self.contents.iter_mut()) {
+            if let Some(new) = new {
+                *old = new;
+            }
+        }
+        self.write_contents()
+    }
+
+    /// Writes a line with the current texts, or an empty line if we're hidden.
+    fn write_contents(&self) -> Result<()> {
+        let line = match (self.mode, self.hidden) {
+            (OutputMode::I3bar, true) => "[],".to_owned(),
+            (_, true) => String::new(),
+            (OutputMode::Plain, false) => self.format_plain(),
+            (OutputMode::Lemonbar, false) => self.format_lemonbar(),
+            (OutputMode::I3bar, false) => self.format_i3bar()?,
+            (OutputMode::X11, false) => unreachable!("X11 output isn't written to stdout"),
+        };
+        self.write_line(&line)
+    }
+
+    fn handle_click_event(&self, event: &ClickEvent) {
+        match event.to_click() {
+            Ok((widget_idx, click)) => {
+                self.event_senders
+                    .send(widget_idx, Event::ButtonPress(click));
+            }
+            Err(e) => warn!("Ignoring click event {:?}: {}", event, e),
+        }
+    }
+}
+
+impl ipc::Control for StdoutBar {
+    fn is_hidden(&self) -> bool {
+        self.hidden
+    }
+
+    fn set_hidden(&mut self, hidden: bool) -> Result<()> {
+        if self.hidden == hidden {
+            return Ok(());
+        }
+        self.hidden = hidden;
+        self.write_contents()
+    }
+
+    fn contents(&self) -> (&[Region], &[Vec<Text>]) {
+        (&self.regions, &self.contents)
+    }
+}
+
+/// Runs the widgets, writing their texts to stdout rather than drawing a bar.
+pub(crate) async fn run_event_loop(
+    mode: OutputMode,
+    regions: Vec<Region>,
+    mut widget_list: WidgetList,
+    mut reloads: Reloads,
+    mut requests: Requests,
+) -> Result<()> {
+    let mut bar = StdoutBar::new(mode)?;
+    bar.set_widgets(regions, &widget_list);
+    bar.write_header()?;
+
+    let mut click_events = if mode == OutputMode::I3bar {
+        click_events().boxed_local()
+    } else {
+        stream::pending().boxed_local()
+    };
+
+    loop {
+        let result = tokio::select! {
+            Some(update) = widget_list.next() => bar.update_widget_contents(update),
+            Some(event) = click_events.next() => {
+                bar.handle_click_event(&event);
+                Ok(())
+            }
+            Some((_, regions, new_widget_list)) = reloads.next() => {
+                widget_list = new_widget_list;
+                bar.set_widgets(regions, &widget_list);
+                Ok(())
+            }
+            Some((request, responder)) = requests.next() => {
+                ipc::handle_request(&mut bar, &mut widget_list, request).map(|response| {
+                    let _ = responder.send(response);
+                })
+            }
+            else => break,
+        };
+        if let Err(e) = result {
+            error!("Error writing to stdout: {}", e);
+            return Err(e);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_click_events() {
+        assert!(parse_click_event("[").unwrap().is_none());
+
+        let line = r#",{"name":"2","instance":"1","button":4,"modifiers":["Shift","Mod4"],"relative_x":5,"relative_y":3}"#;
+        let event = parse_click_event(line).unwrap().unwrap();
+        let (widget_idx, click) = event.to_click().unwrap();
+        assert_eq!(widget_idx, 2);
+        assert_eq!(
+            click,
+            Click {
+                button: Button::ScrollUp,
+                modifiers: Modifiers {
+                    shift: true,
+                    logo: true,
+                    ..Modifiers::default()
+                },
+                index: 1,
+                x: 5.0,
+                y: 3.0,
+            }
+        );
+    }
+
+    #[test]
+    fn escapes_lemonbar_tags() {
+        assert_eq!(escape_lemonbar("100% %{F#fff}"), "100%% %%{F#fff}");
+    }
+
+    #[test]
+    fn lemonbar_colors_put_alpha_first() {
+        assert_eq!(lemonbar_color(&Color::red()), "#ff0000");
+        assert_eq!(
+            lemonbar_color(&Color::from_hex("#11223380").unwrap()),
+            "#80112233"
+        );
+    }
+}
@@ -1,50 +1,344 @@
+use std::borrow::Cow;
+use std::f64;
 use std::fmt;
+use std::str::FromStr;
 
-use cairo::{Context, Surface};
+use cairo::{Context, Operator, Surface};
 use failure::format_err;
+use log::*;
 use pango::{EllipsizeMode, FontDescription, LayoutExt};
 use pangocairo;
 
 use crate::Result;
 
+mod icon;
+
+pub(crate) use self::icon::pick_wm_icon;
+pub use self::icon::Icon;
+
+/// A color, with an alpha channel.
+///
+/// As well as the constructors below, colors can be parsed from strings with
+/// [`FromStr`], which accepts hex colors (`#rgb`, `#rgba`, `#rrggbb` or
+/// `#rrggbbaa`) and [CSS color names] (such as `"tomato"`).
+///
+/// [`FromStr`]: #impl-FromStr
+/// [CSS color names]: https://www.w3.org/TR/css-color-4/#named-colors
+///
+/// # Examples
+///
+/// ```
+/// # use cnx::text::Color;
+/// # fn run() -> ::cnx::Result<()> {
+/// let base00: Color = "#181818".parse()?;
+/// let translucent = Color::from_hex("#18181880")?;
+/// let tomato: Color = "tomato".parse()?;
+/// assert_eq!(tomato, Color::from_hex("#ff6347")?);
+/// # Ok(())
+/// # }
+/// # fn main() { run().unwrap(); }
+/// ```
 #[derive(Clone, Debug, PartialEq)]
 pub struct Color {
     red: f64,
     green: f64,
     blue: f64,
+    alpha: f64,
 }
 
 macro_rules! color {
     ($name:ident,($r:expr, $g:expr, $b:expr)) => {
         #[allow(dead_code)]
         pub fn $name() -> Color {
-            Color {
-                red: $r,
-                green: $g,
-                blue: $b,
-            }
+            Color::rgb($r, $g, $b)
         }
     };
 }
 
 impl Color {
     color!(red, (1.0, 0.0, 0.0));
+    // Note that this is brighter than CSS's `green`, which is `#008000`.
     color!(green, (0.0, 1.0, 0.0));
     color!(blue, (0.0, 0.0, 1.0));
     color!(white, (1.0, 1.0, 1.0));
     color!(black, (0.0, 0.0, 0.0));
 
+    /// A fully transparent color.
+    pub fn transparent() -> Color {
+        Color::rgba(0.0, 0.0, 0.0, 0.0)
+    }
+
+    /// Creates an opaque color from its red, green and blue components, each
+    /// between `0.0` and `1.0`.
+    pub fn rgb(red: f64, green: f64, blue: f64) -> Color {
+        Color::rgba(red, green, blue, 1.0)
+    }
+
+    /// Creates a color from its red, green, blue and alpha components, each
+    /// between `0.0` and `1.0`. An alpha of `0.0` is fully transparent.
+    pub fn rgba(red: f64, green: f64, blue: f64, alpha: f64) -> Color {
+        Color {
+            red,
+            green,
+            blue,
+            alpha,
+        }
+    }
+
+    /// Parses a color in `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa` form. The
+    /// leading `#` is optional.
+    pub fn from_hex(hex: &str) -> Result<Color> {
+        let digits = hex.strip_prefix('#').unwrap_or(hex);
+        let invalid = || format_err!("Invalid hex color: {:?}", hex);
+        if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+
+        // Each component is either one or two hex digits. A single digit is
+        // repeated, so that `f` is the same as `ff`.
+        let component_len = match digits.len() {
+            3 | 4 => 1,
+            6 | 8 => 2,
+            _ => return Err(invalid()),
+        };
+        let components: Vec<f64> = digits
+            .as_bytes()
+            .chunks(component_len)
+            .map(|chunk| {
+                let chunk = std::str::from_utf8(chunk).expect("hex digits are ASCII");
+                let value = u8::from_str_radix(chunk, 16).expect("checked for hex digits");
+                let value = if component_len == 1 {
+                    value * 17
+                } else {
+                    value
+                };
+                f64::from(value) / 255.0
+            })
+            .collect();
+        let alpha = components.get(3).cloned().unwrap_or(1.0);
+        Ok(Color::rgba(
+            components[0],
+            components[1],
+            components[2],
+            alpha,
+        ))
+    }
+
+    /// Returns the alpha component of the color, between `0.0` (transparent)
+    /// and `1.0` (opaque).
+    pub fn alpha(&self) -> f64 {
+        self.alpha
+    }
+
     pub fn apply_to_context(&self, cr: &Context) {
-        cr.set_source_rgb(self.red, self.green, self.blue);
+        cr.set_source_rgba(self.red, self.green, self.blue, self.alpha);
+    }
+
+    /// Returns the red, green, blue and alpha components as bytes.
+    pub(crate) fn to_bytes(&self) -> [u8; 4] {
+        let byte = |c: f64| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+        [
+            byte(self.red),
+            byte(self.green),
+            byte(self.blue),
+            byte(self.alpha),
+        ]
+    }
+
+    /// Returns the color in `#rrggbb` form, or `#rrggbbaa` form if it isn't
+    /// opaque.
+    pub fn to_hex(&self) -> String {
+        let [red, green, blue, alpha] = self.to_bytes();
+        if alpha == 255 {
+            format!("#{:02x}{:02x}{:02x}", red, green, blue)
+        } else {
+            format!("#{:02x}{:02x}{:02x}{:02x}", red, green, blue, alpha)
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = failure::Error;
+
+    /// Parses a hex color (see [`from_hex()`]) or a CSS color name. Names
+    /// are case-insensitive.
+    ///
+    /// [`from_hex()`]: #method.from_hex
+    fn from_str(s: &str) -> Result<Color> {
+        if s.starts_with('#') {
+            return Color::from_hex(s);
+        }
+        let name = s.to_ascii_lowercase();
+        if name == "transparent" {
+            return Ok(Color::transparent());
+        }
+        let &(_, rgb) = CSS_COLORS
+            .iter()
+            .find(|&&(css_name, _)| css_name == name)
+            .ok_or_else(|| format_err!("Unknown color: {:?}", s))?;
+        let component = |shift: u32| f64::from((rgb >> shift) as u8) / 255.0;
+        Ok(Color::rgb(component(16), component(8), component(0)))
     }
 }
 
+/// The named colors of CSS Color Module Level 4.
+const CSS_COLORS: &[(&str, u32)] = &[
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Padding {
-    left: f64,
-    right: f64,
-    top: f64,
-    bottom: f64,
+    pub(crate) left: f64,
+    pub(crate) right: f64,
+    pub(crate) top: f64,
+    pub(crate) bottom: f64,
 }
 
 impl Padding {
@@ -91,45 +385,169 @@ fn show_pango_layout(cairo_context: &cairo::Context, layout: &pango::Layout) {
     pangocairo::functions::show_layout(cairo_context, layout);
 }
 
+/// Parses Pango markup, returning the text without its tags.
+fn parse_markup(markup: &str) -> Result<String> {
+    // We don't use accelerator markers, so pass a character that can't appear.
+    let (_, text, _) = pango::parse_markup(markup, '\0')
+        .map_err(|e| format_err!("Invalid markup {:?}: {}", markup, e))?;
+    Ok(text)
+}
+
+/// Sets the text of a Pango layout, interpreting it as markup if `markup` is
+/// set. Invalid markup is shown as plain text, tags and all, rather than
+/// showing nothing.
+fn set_layout_text(layout: &pango::Layout, text: &str, markup: bool) -> Result<()> {
+    if markup {
+        parse_markup(text)?;
+        layout.set_markup(text);
+    } else {
+        layout.set_text(text);
+    }
+    Ok(())
+}
+
+/// Returns the space between an icon of the given height and its text, if it
+/// has any.
+fn icon_spacing(text: &str, icon_height: f64) -> f64 {
+    if text.is_empty() {
+        0.0
+    } else {
+        (icon_height / 4.0).round()
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Text {
     pub attr: Attributes,
     pub text: String,
     pub stretch: bool,
+    /// An X window to show in place of the text, such as the container for the
+    /// system tray's icons. The bar moves and resizes the window to cover the
+    /// space taken up by the text.
+    pub embed: Option<u32>,
+    /// Whether the text is [Pango markup], such as `<b>bold</b>` or
+    /// `<span foreground="red">red</span>`, rather than plain text. This
+    /// allows parts of the text to be styled differently from its
+    /// `Attributes`. If the markup is invalid, the text is shown as it is.
+    ///
+    /// [Pango markup]: https://docs.gtk.org/Pango/pango_markup.html
+    pub markup: bool,
+    /// An icon to show before the text. The text may be empty to show just
+    /// the icon.
+    pub icon: Option<Icon>,
 }
 
 impl Text {
+    /// Creates a plain text with the given attributes, which doesn't stretch
+    /// and has no icon or embedded window.
+    pub fn new<S: Into<String>>(attr: Attributes, text: S) -> Text {
+        Text {
+            attr,
+            text: text.into(),
+            stretch: false,
+            embed: None,
+            markup: false,
+            icon: None,
+        }
+    }
+
+    /// Sets whether the text takes up a share of any space left over in its
+    /// region of the bar.
+    pub fn with_stretch(mut self, stretch: bool) -> Text {
+        self.stretch = stretch;
+        self
+    }
+
+    /// Sets whether the text is Pango markup. See [`markup`].
+    ///
+    /// [`markup`]: #structfield.markup
+    pub fn with_markup(mut self, markup: bool) -> Text {
+        self.markup = markup;
+        self
+    }
+
+    /// Sets the icon shown before the text, if any.
+    pub fn with_icon<I: Into<Option<Icon>>>(mut self, icon: I) -> Text {
+        self.icon = icon.into();
+        self
+    }
+
+    /// Shows an X window in place of the text. See [`embed`].
+    ///
+    /// [`embed`]: #structfield.embed
+    pub fn with_embed(mut self, window: u32) -> Text {
+        self.embed = Some(window);
+        self
+    }
+
     pub(crate) fn compute(self, surface: &Surface) -> Result<ComputedText> {
-        let (width, height) = {
+        let (width, height, icon_size) = {
             let context = Context::new(&surface);
             let layout = create_pango_layout(&context)?;
-            layout.set_text(&self.text);
+            if let Err(e) = set_layout_text(&layout, &self.text, self.markup) {
+                warn!("Showing text as plain text: {}", e);
+                layout.set_text(&self.text);
+            }
             layout.set_font_description(Some(&self.attr.font.0));
 
             let padding = &self.attr.padding;
             let (text_width, text_height) = layout.get_pixel_size();
-            let width = f64::from(text_width) + padding.left + padding.right;
-            let height = f64::from(text_height) + padding.top + padding.bottom;
-            (width, height)
+            let (text_width, text_height) = (f64::from(text_width), f64::from(text_height));
+            // Even an empty layout is one line tall, so icons are scaled to
+            // the height of the font.
+            let icon_size = self.icon.as_ref().map(|icon| icon.size(text_height));
+            let (icon_width, icon_height) = icon_size.map_or((0.0, 0.0), |(width, height)| {
+                (width + icon_spacing(&self.text, height), height)
+            });
+            let width = icon_width + text_width + padding.left + padding.right;
+            let height = text_height.max(icon_height) + padding.top + padding.bottom;
+            (width, height, icon_size)
         };
 
         Ok(ComputedText {
             attr: self.attr,
             text: self.text,
             stretch: self.stretch,
+            embed: self.embed,
+            markup: self.markup,
+            icon: self.icon.map(|icon| (icon, icon_size.unwrap_or_default())),
+            rotated: false,
             x: 0.0,
             y: 0.0,
             width,
             height,
         })
     }
+
+    /// Returns the text without any markup. Invalid markup is returned as it
+    /// is, just as it's shown on the bar.
+    pub(crate) fn plain_text(&self) -> Cow<'_, str> {
+        match self.valid_markup() {
+            Some(text) => Cow::Owned(text),
+            None => Cow::Borrowed(&self.text),
+        }
+    }
+
+    /// Returns the text without its markup, if the text is valid markup.
+    pub(crate) fn valid_markup(&self) -> Option<String> {
+        if self.markup {
+            parse_markup(&self.text).ok()
+        } else {
+            None
+        }
+    }
 }
 
 // This impl allows us to see whether a widget's text has changed without
 // having to call the (relatively) expensive .compute().
 impl PartialEq<ComputedText> for Text {
     fn eq(&self, other: &ComputedText) -> bool {
-        self.attr == other.attr && self.text == other.text && self.stretch == other.stretch
+        self.attr == other.attr
+            && self.text == other.text
+            && self.stretch == other.stretch
+            && self.embed == other.embed
+            && self.markup == other.markup
+            && self.icon.as_ref() == other.icon.as_ref().map(|(icon, _)| icon)
     }
 }
 
@@ -138,6 +556,14 @@ pub(crate) struct ComputedText {
     pub attr: Attributes,
     pub text: String,
     pub stretch: bool,
+    pub embed: Option<u32>,
+    pub markup: bool,
+    /// The text's icon, and the size it's shown at.
+    pub icon: Option<(Icon, (f64, f64))>,
+    /// Whether the text is turned a quarter turn clockwise, to read down a
+    /// vertical bar. `width` and `height` are the size of the text before it's
+    /// rotated.
+    pub rotated: bool,
 
     pub x: f64,
     pub y: f64,
@@ -146,13 +572,34 @@ pub(crate) struct ComputedText {
 }
 
 impl ComputedText {
-    pub fn render(&self, surface: &Surface) -> Result<()> {
+    /// Returns the width and height of the space the text takes up in the bar,
+    /// taking into account whether it's rotated.
+    pub fn size(&self) -> (f64, f64) {
+        if self.rotated {
+            (self.height, self.width)
+        } else {
+            (self.width, self.height)
+        }
+    }
+
+    /// Renders the text onto `surface`, over the bar's `background`.
+    pub fn render(&self, surface: &Surface, background: &Color) -> Result<()> {
         let context = Context::new(&surface);
         let layout = create_pango_layout(&context)?;
-        layout.set_text(&self.text);
+        // We've already warned about invalid markup when computing the text.
+        if set_layout_text(&layout, &self.text, self.markup).is_err() {
+            layout.set_text(&self.text);
+        }
         layout.set_font_description(Some(&self.attr.font.0));
 
-        context.translate(self.x, self.y);
+        if self.rotated {
+            // Rotate about our top-left corner, then move right by our height
+            // so that we're drawn within the space we take up.
+            context.translate(self.x + self.height, self.y);
+            context.rotate(f64::consts::FRAC_PI_2);
+        } else {
+            context.translate(self.x, self.y);
+        }
 
         // Set the width/height on the Pango layout so that it word-wraps/ellipises.
         let padding = &self.attr.padding;
@@ -162,19 +609,87 @@ impl ComputedText {
         layout.set_width(text_width as i32 * pango::SCALE);
         layout.set_height(text_height as i32 * pango::SCALE);
 
-        let bg_color = &self.attr.bg_color.clone().unwrap_or_else(Color::black);
-        bg_color.apply_to_context(&context);
+        // Replace whatever was drawn here before, as it would otherwise show
+        // through a transparent background. Without a background color, the
+        // text is drawn straight onto the bar's background.
         // FIXME: The use of `height` isnt' right here: we want to do the
         // full height of the bar, not the full height of the text. It
         // would be useful if we could do Surface.get_height(), but that
         // doesn't seem to be available in cairo-rs for some reason?
         context.rectangle(0.0, 0.0, self.width, self.height);
+        context.set_operator(Operator::Source);
+        self.attr
+            .bg_color
+            .as_ref()
+            .unwrap_or(background)
+            .apply_to_context(&context);
         context.fill();
+        context.set_operator(Operator::Over);
 
         self.attr.fg_color.apply_to_context(&context);
         context.translate(padding.left, padding.top);
+        if let Some((ref icon, (width, height))) = self.icon {
+            // Centre the icon against the text, then move the text after it.
+            let offset = ((text_height - height) / 2.0).round();
+            context.translate(0.0, offset);
+            icon.render(&context, width, height);
+            let icon_width = width + icon_spacing(&self.text, height);
+            context.translate(icon_width, -offset);
+            layout.set_width((text_width - icon_width).max(0.0) as i32 * pango::SCALE);
+        }
         show_pango_layout(&context, &layout);
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn text(text: &str, markup: bool) -> Text {
+        let attr = Attributes {
+            font: Font::new("monospace"),
+            fg_color: Color::white(),
+            bg_color: None,
+            padding: Padding::new(0.0, 0.0, 0.0, 0.0),
+        };
+        Text::new(attr, text).with_markup(markup)
+    }
+
+    #[test]
+    fn parses_hex_colors() {
+        assert_eq!(Color::from_hex("#ff0000").unwrap(), Color::red());
+        assert_eq!(Color::from_hex("fff").unwrap(), Color::white());
+        assert_eq!(
+            Color::from_hex("#00000080").unwrap(),
+            Color::rgba(0.0, 0.0, 0.0, 128.0 / 255.0)
+        );
+        assert_eq!(Color::from_hex("#0f08").unwrap().to_hex(), "#00ff0088");
+        assert!(Color::from_hex("#12345").is_err());
+        assert!(Color::from_hex("#gggggg").is_err());
+    }
+
+    #[test]
+    fn parses_color_names() {
+        assert_eq!("Tomato".parse::<Color>().unwrap().to_hex(), "#ff6347");
+        assert_eq!("green".parse::<Color>().unwrap().to_hex(), "#008000");
+        assert_eq!("#abc".parse::<Color>().unwrap().to_hex(), "#aabbcc");
+        assert_eq!("transparent".parse::<Color>().unwrap().alpha(), 0.0);
+        assert!("mauve".parse::<Color>().is_err());
+    }
+
+    #[test]
+    fn plain_text_strips_markup() {
+        let markup = r#"<b>50%</b> <span foreground="red">low</span>"#;
+        assert_eq!(text(markup, true).plain_text(), "50% low");
+        assert_eq!(text(markup, false).plain_text(), markup);
+    }
+
+    #[test]
+    fn invalid_markup_is_shown_as_is() {
+        let invalid = "<b>unclosed & broken";
+        assert_eq!(text(invalid, true).valid_markup(), None);
+        assert_eq!(text(invalid, true).plain_text(), invalid);
+    }
+}
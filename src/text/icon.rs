@@ -0,0 +1,374 @@
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+use std::rc::Rc;
+
+use cairo::{Context, Format, ImageSurface};
+use failure::{bail, format_err, ResultExt};
+
+use crate::Result;
+
+/// An image shown alongside a [`Text`], such as an application's icon.
+///
+/// Icons are scaled to the height of the text's font, so that they fit in the
+/// bar, unless they're given a size with [`with_size()`]. PNG images can always
+/// be loaded. SVG images can be loaded if Cnx is built with the `svg-icons`
+/// feature, which uses [resvg].
+///
+/// Icons are cheap to clone, and are only equal to clones of themselves. A
+/// widget which shows the same icon each time it updates should load it once
+/// and clone it, so that the bar can tell that it hasn't changed.
+///
+/// [`Text`]: struct.Text.html
+/// [`with_size()`]: #method.with_size
+/// [resvg]: https://github.com/linebender/resvg
+///
+/// # Examples
+///
+/// ```no_run
+/// # use cnx::text::*;
+/// # fn run() -> ::cnx::Result<()> {
+/// # let attr = Attributes {
+/// #     font: Font::new("SourceCodePro 21"),
+/// #     fg_color: Color::white(),
+/// #     bg_color: None,
+/// #     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+/// # };
+/// let icon = Icon::load("/usr/share/icons/hicolor/32x32/apps/firefox.png")?;
+/// let text = Text::new(attr.clone(), "Firefox").with_icon(icon);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Icon {
+    image: Rc<Image>,
+    size: Option<f64>,
+}
+
+enum Image {
+    Raster(ImageSurface),
+    #[cfg(feature = "svg-icons")]
+    Svg(Box<svg::Handle>),
+}
+
+impl Icon {
+    /// Loads an icon from a PNG or SVG file, depending on its extension.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Icon> {
+        let path = path.as_ref();
+        let is_svg = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("svg"));
+        let image = if is_svg {
+            load_svg(path)?
+        } else {
+            let mut file =
+                File::open(path).with_context(|_| format!("Failed to open {}", path.display()))?;
+            let surface = ImageSurface::create_from_png(&mut file)
+                .map_err(|e| format_err!("Failed to read PNG {}: {:?}", path.display(), e))?;
+            Image::Raster(surface)
+        };
+        Ok(Icon {
+            image: Rc::new(image),
+            size: None,
+        })
+    }
+
+    /// Creates an icon from `width * height` pixels of non-premultiplied ARGB
+    /// data, row by row, as found in the `_NET_WM_ICON` property of windows.
+    pub fn from_argb(width: u32, height: u32, pixels: &[u32]) -> Result<Icon> {
+        let len = width as usize * height as usize;
+        if width == 0 || height == 0 || pixels.len() < len {
+            bail!(
+                "Expected {}x{} icon, but got {} pixels",
+                width,
+                height,
+                pixels.len()
+            );
+        }
+
+        let mut data = Vec::with_capacity(len * 4);
+        for &pixel in &pixels[..len] {
+            data.extend_from_slice(&premultiply(pixel).to_ne_bytes());
+        }
+        let surface = ImageSurface::create_for_data(
+            data,
+            Format::ARgb32,
+            width as i32,
+            height as i32,
+            width as i32 * 4,
+        )
+        .map_err(|status| format_err!("Failed to create icon: {:?}", status))?;
+        Ok(Icon {
+            image: Rc::new(Image::Raster(surface)),
+            size: None,
+        })
+    }
+
+    /// Shows the icon this many pixels tall, rather than as tall as the text's
+    /// font.
+    pub fn with_size(mut self, size: f64) -> Icon {
+        self.size = Some(size);
+        self
+    }
+
+    /// Returns the natural width and height of the icon, in pixels.
+    fn dimensions(&self) -> (f64, f64) {
+        match *self.image {
+            Image::Raster(ref surface) => (
+                f64::from(surface.get_width()),
+                f64::from(surface.get_height()),
+            ),
+            #[cfg(feature = "svg-icons")]
+            Image::Svg(ref handle) => handle.dimensions(),
+        }
+    }
+
+    /// Returns the width and height to show the icon at, given the height of
+    /// the text's font.
+    pub(crate) fn size(&self, font_height: f64) -> (f64, f64) {
+        let (width, height) = self.dimensions();
+        let scaled_height = self.size.unwrap_or(font_height);
+        if height <= 0.0 {
+            return (0.0, 0.0);
+        }
+        (width * scaled_height / height, scaled_height)
+    }
+
+    /// Draws the icon with its top-left corner at the context's origin, scaled
+    /// to the given size.
+    pub(crate) fn render(&self, context: &Context, width: f64, height: f64) {
+        let (natural_width, natural_height) = self.dimensions();
+        if natural_width <= 0.0 || natural_height <= 0.0 {
+            return;
+        }
+        match *self.image {
+            Image::Raster(ref surface) => {
+                context.save();
+                context.scale(width / natural_width, height / natural_height);
+                context.set_source_surface(surface, 0.0, 0.0);
+                context.paint();
+                context.restore();
+            }
+            // SVG images are drawn at the given size, rather than scaled up or
+            // down from their natural size, so that they stay sharp.
+            #[cfg(feature = "svg-icons")]
+            Image::Svg(ref handle) => handle.render(context, width, height),
+        }
+    }
+}
+
+impl PartialEq for Icon {
+    fn eq(&self, other: &Icon) -> bool {
+        Rc::ptr_eq(&self.image, &other.image) && self.size == other.size
+    }
+}
+
+impl fmt::Debug for Icon {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (width, height) = self.dimensions();
+        write!(f, "Icon({}x{}", width, height)?;
+        if let Some(size) = self.size {
+            write!(f, ", size {}", size)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Picks the icon to show from the contents of a `_NET_WM_ICON` property,
+/// which holds any number of icons, each given as its width, height and then
+/// its pixels. The smallest icon at least `size` pixels tall is picked, or
+/// the biggest icon if they're all smaller.
+pub(crate) fn pick_wm_icon(data: &[u32], size: u32) -> Option<(u32, u32, &[u32])> {
+    let mut icons = Vec::new();
+    let mut rest = data;
+    while rest.len() >= 2 {
+        let (width, height) = (rest[0], rest[1]);
+        let len = width as usize * height as usize;
+        if width == 0 || height == 0 || rest.len() - 2 < len {
+            break;
+        }
+        icons.push((width, height, &rest[2..2 + len]));
+        rest = &rest[2 + len..];
+    }
+
+    let big_enough = icons
+        .iter()
+        .filter(|&&(_, height, _)| height >= size)
+        .min_by_key(|&&(_, height, _)| height);
+    big_enough
+        .or_else(|| icons.iter().max_by_key(|&&(_, height, _)| height))
+        .cloned()
+}
+
+/// Converts a pixel from non-premultiplied ARGB to the premultiplied ARGB
+/// that Cairo expects.
+fn premultiply(pixel: u32) -> u32 {
+    let alpha = pixel >> 24;
+    let channel = |shift: u32| {
+        let value = (pixel >> shift) & 0xff;
+        // Divide by 255, rounding to the nearest value.
+        ((value * alpha + 127) / 255) << shift
+    };
+    (alpha << 24) | channel(16) | channel(8) | channel(0)
+}
+
+#[cfg(feature = "svg-icons")]
+fn load_svg(path: &Path) -> Result<Image> {
+    Ok(Image::Svg(Box::new(svg::Handle::load(path)?)))
+}
+
+#[cfg(not(feature = "svg-icons"))]
+fn load_svg(path: &Path) -> Result<Image> {
+    bail!(
+        "Can't load {}: Cnx was built without SVG support (the `svg-icons` feature)",
+        path.display()
+    )
+}
+
+/// SVG images, drawn with resvg.
+#[cfg(feature = "svg-icons")]
+mod svg {
+    use std::cell::RefCell;
+    use std::fs;
+    use std::path::Path;
+
+    use cairo::{Context, Format, ImageSurface};
+    use failure::{format_err, ResultExt};
+    use resvg::tiny_skia::{Pixmap, Transform};
+    use resvg::usvg::{Options, Tree};
+
+    use crate::Result;
+
+    /// A loaded SVG image, along with the last size it was drawn at.
+    pub(super) struct Handle {
+        tree: Tree,
+        rendered: RefCell<Option<ImageSurface>>,
+    }
+
+    impl Handle {
+        pub(super) fn load(path: &Path) -> Result<Handle> {
+            let data =
+                fs::read(path).with_context(|_| format!("Failed to open {}", path.display()))?;
+            Handle::from_data(&data)
+                .map_err(|e| format_err!("Failed to read SVG {}: {}", path.display(), e))
+        }
+
+        pub(super) fn from_data(data: &[u8]) -> Result<Handle> {
+            let tree = Tree::from_data(data, &Options::default())?;
+            Ok(Handle {
+                tree,
+                rendered: RefCell::new(None),
+            })
+        }
+
+        pub(super) fn dimensions(&self) -> (f64, f64) {
+            let size = self.tree.size();
+            (f64::from(size.width()), f64::from(size.height()))
+        }
+
+        /// Draws the image with its top-left corner at the context's origin,
+        /// scaled to the given size.
+        pub(super) fn render(&self, context: &Context, width: f64, height: f64) {
+            let (pixel_width, pixel_height) = (width.ceil() as i32, height.ceil() as i32);
+            let mut rendered = self.rendered.borrow_mut();
+            let is_current = rendered.as_ref().is_some_and(|surface| {
+                surface.get_width() == pixel_width && surface.get_height() == pixel_height
+            });
+            if !is_current {
+                *rendered = self.rasterize(pixel_width, pixel_height);
+            }
+            if let Some(ref surface) = *rendered {
+                context.save();
+                context.scale(
+                    width / f64::from(pixel_width),
+                    height / f64::from(pixel_height),
+                );
+                context.set_source_surface(surface, 0.0, 0.0);
+                context.paint();
+                context.restore();
+            }
+        }
+
+        /// Renders the image to a new surface of the given size.
+        fn rasterize(&self, width: i32, height: i32) -> Option<ImageSurface> {
+            let mut pixmap = Pixmap::new(width as u32, height as u32)?;
+            let (natural_width, natural_height) = self.dimensions();
+            let transform = Transform::from_scale(
+                (f64::from(width) / natural_width) as f32,
+                (f64::from(height) / natural_height) as f32,
+            );
+            resvg::render(&self.tree, transform, &mut pixmap.as_mut());
+
+            // Both use premultiplied alpha, but resvg's pixels are RGBA bytes
+            // whereas Cairo's are native-endian ARGB words.
+            let mut data = Vec::with_capacity(pixmap.data().len());
+            for pixel in pixmap.pixels() {
+                let argb = u32::from(pixel.alpha()) << 24
+                    | u32::from(pixel.red()) << 16
+                    | u32::from(pixel.green()) << 8
+                    | u32::from(pixel.blue());
+                data.extend_from_slice(&argb.to_ne_bytes());
+            }
+            ImageSurface::create_for_data(data, Format::ARgb32, width, height, width * 4).ok()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn premultiplies_pixels() {
+        assert_eq!(premultiply(0xffff_8000), 0xffff_8000);
+        assert_eq!(premultiply(0x80ff_ffff), 0x8080_8080);
+        assert_eq!(premultiply(0x00ff_ffff), 0x0000_0000);
+    }
+
+    #[test]
+    fn picks_smallest_big_enough_wm_icon() {
+        let mut data = vec![1, 1, 0xffff_ffff];
+        data.extend(&[2, 2, 1, 2, 3, 4]);
+        data.extend(&[3, 3]);
+        data.extend(vec![0; 9]);
+        assert_eq!(pick_wm_icon(&data, 2), Some((2, 2, &[1, 2, 3, 4][..])));
+        assert_eq!(pick_wm_icon(&data, 8).map(|(w, h, _)| (w, h)), Some((3, 3)));
+        // Truncated icons are ignored.
+        assert_eq!(pick_wm_icon(&[4, 4, 0, 0], 1), None);
+    }
+
+    #[cfg(feature = "svg-icons")]
+    #[test]
+    fn renders_svg() {
+        let data = br##"<svg xmlns="http://www.w3.org/2000/svg" width="4" height="2">
+            <rect width="2" height="2" fill="#ff0000"/>
+            <rect x="2" width="2" height="2" fill="#0000ff" fill-opacity="0.5"/>
+        </svg>"##;
+        let icon = Icon {
+            image: Rc::new(Image::Svg(Box::new(svg::Handle::from_data(data).unwrap()))),
+            size: None,
+        };
+        assert_eq!(icon.size(6.0), (12.0, 6.0));
+
+        let mut surface = ImageSurface::create(Format::ARgb32, 12, 6).unwrap();
+        icon.render(&Context::new(&surface), 12.0, 6.0);
+        let stride = surface.get_stride() as usize;
+        let data = surface.get_data().unwrap();
+        let pixel = |x: usize, y: usize| {
+            let offset = y * stride + x * 4;
+            u32::from_ne_bytes([
+                data[offset],
+                data[offset + 1],
+                data[offset + 2],
+                data[offset + 3],
+            ])
+        };
+        assert_eq!(pixel(0, 0), 0xffff_0000);
+        assert_eq!(pixel(5, 5), 0xffff_0000);
+        // Half-transparent blue, premultiplied.
+        assert_eq!(pixel(6, 0), 0x8000_0080);
+        assert_eq!(pixel(11, 5), 0x8000_0080);
+
+        assert!(svg::Handle::from_data(b"<svg").is_err());
+    }
+}
@@ -1,8 +1,11 @@
-use tokio_core::reactor::Handle;
+use std::cell::Cell;
+
+use log::*;
 use xcb;
 use xcb_util::ewmh;
 
-use crate::text::{Attributes, Text};
+use crate::text::{self, Attributes, Icon, Text};
+use crate::x11::{Connection, LazyConnection};
 use crate::{Cnx, Result};
 
 /// Shows the title of the currently focused window.
@@ -14,10 +17,18 @@ use crate::{Cnx, Result};
 /// The widgets content stretches to fill all available space. If the title is
 /// too large for the available space, it will be truncated.
 ///
+/// Use [`with_icon()`] to also show the window's icon (its `_NET_WM_ICON`
+/// property).
+///
 /// [`EWMH`]: https://specifications.freedesktop.org/wm-spec/wm-spec-latest.html
+/// [`with_icon()`]: #method.with_icon
+#[derive(Clone)]
 pub struct ActiveWindowTitle {
-    tokio_handle: Handle,
+    conn: LazyConnection,
     attr: Attributes,
+    show_icon: bool,
+    /// The window whose property changes we've selected, if any.
+    watched: Cell<Option<xcb::Window>>,
 }
 
 impl ActiveWindowTitle {
@@ -26,10 +37,9 @@ impl ActiveWindowTitle {
     /// Creates a new `ActiveWindowTitle` widget, whose text will be displayed
     /// with the given [`Attributes`].
     ///
-    /// The [`Cnx`] instance is borrowed during construction in order to get
-    /// access to handles of its event loop. However, it is not borrowed for the
-    /// lifetime of the widget. See the [`cnx_add_widget!()`] for more discussion
-    /// about the lifetime of the borrow.
+    /// The [`Cnx`] instance is borrowed during construction. However, it is not
+    /// borrowed for the lifetime of the widget. See the [`cnx_add_widget!()`]
+    /// for more discussion about the lifetime of the borrow.
     ///
     /// [`Attributes`]: ../text/struct.Attributes.html
     /// [`Cnx`]: ../struct.Cnx.html
@@ -61,39 +71,80 @@ impl ActiveWindowTitle {
     /// ```
     pub fn new(cnx: &Cnx, attr: Attributes) -> ActiveWindowTitle {
         ActiveWindowTitle {
-            tokio_handle: cnx.handle(),
+            conn: cnx.x_connection(),
             attr,
+            show_icon: false,
+            watched: Cell::new(None),
         }
     }
 
-    fn on_change(&self, conn: &ewmh::Connection, screen_idx: i32) -> Result<Vec<Text>> {
-        let title = ewmh::get_active_window(conn, screen_idx)
-            .get_reply()
-            .and_then(|active_window| {
-                // x_properties_widget!() will only register for notifications on the
-                // root window, so will only receive notifications when the active window
-                // changes. So, for each active window we see, register for property
-                // change notifications, so that we can see when the currently active
-                // window changes title. (We'll continue to receive notifications after
-                // it is no longer the active window, but this isn't a big deal).
-                let attributes = [(xcb::CW_EVENT_MASK, xcb::EVENT_MASK_PROPERTY_CHANGE)];
-                xcb::change_window_attributes(conn, active_window, &attributes);
-                conn.flush();
+    /// Sets whether the focused window's icon is shown before its title.
+    /// Windows without an icon just show their title.
+    pub fn with_icon(mut self, show_icon: bool) -> ActiveWindowTitle {
+        self.show_icon = show_icon;
+        self
+    }
+
+    fn on_change(&self, conn: &Connection, screen_idx: i32) -> Result<Vec<Text>> {
+        let active_window = ewmh::get_active_window(conn, screen_idx).get_reply().ok();
+        // x_properties_widget!() will only register for notifications on the
+        // root window, so will only receive notifications when the active window
+        // changes. So, for each active window we see, register for property
+        // change notifications, so that we can see when the currently active
+        // window changes title. We stop watching the previous active window,
+        // which may since have been destroyed.
+        let previous = self.watched.replace(active_window);
+        if previous != active_window {
+            if let Some(previous) = previous {
+                conn.deselect_input(previous, xcb::EVENT_MASK_PROPERTY_CHANGE);
+            }
+            if let Some(active_window) = active_window {
+                conn.select_input(active_window, xcb::EVENT_MASK_PROPERTY_CHANGE);
+            }
+            conn.flush();
+        }
 
-                ewmh::get_wm_name(conn, active_window).get_reply()
-            })
+        let title = active_window
+            .and_then(|active_window| ewmh::get_wm_name(conn, active_window).get_reply().ok())
             .map(|reply| reply.string().to_owned())
-            .unwrap_or_else(|_| "".to_owned());
+            .unwrap_or_default();
+        let icon = match active_window {
+            Some(active_window) if self.show_icon => window_icon(conn, active_window),
+            _ => None,
+        };
 
-        Ok(vec![Text {
-            attr: self.attr.clone(),
-            text: title,
-            stretch: true,
-        }])
+        Ok(vec![Text::new(self.attr.clone(), title)
+            .with_stretch(true)
+            .with_icon(icon)])
     }
 }
 
-x_properties_widget!(ActiveWindowTitle, tokio_handle, on_change; [
+/// The size of the window icon we'd like, in pixels. Icons are scaled to fit
+/// the bar, so this only needs to be roughly the height of the bar.
+const ICON_SIZE: u32 = 32;
+
+/// Returns the icon of a window, if it has one.
+fn window_icon(conn: &Connection, window: xcb::Window) -> Option<Icon> {
+    // The property can hold several large icons, so ask for all of it.
+    let reply = xcb::get_property(
+        conn,
+        false,
+        window,
+        conn.WM_ICON(),
+        xcb::ATOM_CARDINAL,
+        0,
+        u32::MAX,
+    )
+    .get_reply()
+    .ok()?;
+    let (width, height, pixels) = text::pick_wm_icon(reply.value::<u32>(), ICON_SIZE)?;
+    Icon::from_argb(width, height, pixels)
+        .map_err(|e| warn!("Failed to read icon of window {}: {}", window, e))
+        .ok()
+}
+
+x_properties_widget!(ActiveWindowTitle, conn, on_change; [
     ACTIVE_WINDOW,
-    WM_NAME
+    WM_NAME,
+    WM_ICON
 ]);
@@ -6,9 +6,8 @@ use std::str::FromStr;
 use std::time::Duration;
 
 use failure::{format_err, Error, ResultExt};
-use tokio_timer::Timer;
 
-use crate::text::{Attributes, Color, Text};
+use crate::text::{Attributes, Color, Icon, Text};
 use crate::{Cnx, Result};
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -43,14 +42,19 @@ impl FromStr for Status {
 /// change to the specified `warning_color`.
 ///
 /// Battery charge information is read from [`/sys/class/power_supply/BAT0/`].
+/// Use [`with_battery()`] to show a different battery, and [`with_icon()`] to
+/// show a battery icon before the text.
 ///
+/// [`with_battery()`]: #method.with_battery
+/// [`with_icon()`]: #method.with_icon
 /// [`/sys/class/power_supply/BAT0/`]: https://www.kernel.org/doc/Documentation/power/power_supply_class.txt
+#[derive(Clone)]
 pub struct Battery {
-    timer: Timer,
     update_interval: Duration,
     battery: String,
     attr: Attributes,
     warning_color: Color,
+    icon: Option<Icon>,
 }
 
 impl Battery {
@@ -61,10 +65,10 @@ impl Battery {
     ///  argument, to control the [`Color`] of the text once the battery has
     ///  less than 10% charge remaining.
     ///
-    ///  The [`Cnx`] instance is borrowed during construction in order to get
-    ///  access to handles of its event loop. However, it is not borrowed for
-    ///  the lifetime of the widget. See the [`cnx_add_widget!()`] for more
-    ///  discussion about the lifetime of the borrow.
+    ///  The [`Cnx`] instance is borrowed during construction. However, it is
+    ///  not borrowed for the lifetime of the widget. See the
+    ///  [`cnx_add_widget!()`] for more discussion about the lifetime of the
+    ///  borrow.
     ///
     /// [`Attributes`]: ../text/struct.Attributes.html
     /// [`Color`]: ../text/struct.Color.html
@@ -95,16 +99,55 @@ impl Battery {
     /// # }
     /// # fn main() { run().unwrap(); }
     /// ```
-    pub fn new(cnx: &Cnx, attr: Attributes, warning_color: Color) -> Battery {
+    pub fn new(_cnx: &Cnx, attr: Attributes, warning_color: Color) -> Battery {
         Battery {
-            timer: cnx.timer(),
             update_interval: Duration::from_secs(60),
             battery: "BAT0".to_owned(),
             attr,
             warning_color,
+            icon: None,
         }
     }
 
+    /// Sets which battery to show, by its name in `/sys/class/power_supply/`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate cnx;
+    /// #
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// # let attr = Attributes {
+    /// #     font: Font::new("SourceCodePro 21"),
+    /// #     fg_color: Color::white(),
+    /// #     bg_color: None,
+    /// #     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    /// # };
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx_add_widget!(
+    ///     cnx,
+    ///     Battery::new(&cnx, attr.clone(), Color::red()).with_battery("BAT1")
+    /// );
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn with_battery<S: Into<String>>(mut self, battery: S) -> Battery {
+        self.battery = battery.into();
+        self
+    }
+
+    /// Shows an icon, such as a battery, before the charge percentage.
+    pub fn with_icon(mut self, icon: Icon) -> Battery {
+        self.icon = Some(icon);
+        self
+    }
+
     fn load_value_inner<T>(&self, file: &str) -> Result<T>
     where
         T: FromStr,
@@ -162,12 +205,8 @@ impl Battery {
             attr.fg_color = self.warning_color.clone()
         }
 
-        Ok(vec![Text {
-            attr,
-            text,
-            stretch: false,
-        }])
+        Ok(vec![Text::new(attr, text).with_icon(self.icon.clone())])
     }
 }
 
-timer_widget!(Battery, timer, update_interval, tick);
+timer_widget!(Battery, update_interval, tick);
@@ -1,21 +1,44 @@
+use std::pin::Pin;
 use std::time::Duration;
 
+use chrono::format::{Item, StrftimeItems};
 use chrono::prelude::*;
-use failure::ResultExt;
-use futures::{stream, Future, Stream};
-use tokio_timer::Timer;
+use chrono_tz::Tz;
+use failure::{bail, format_err};
+use futures::{future, stream, StreamExt};
+use log::*;
+use tokio::time::{self, Instant, Sleep};
 
-use super::{Widget, WidgetStream};
+use self::calendar::Calendar;
+use super::{Button, Event, EventStream, Widget, WidgetStream};
 use crate::text::{Attributes, Text};
+use crate::x11::LazyConnection;
 use crate::{Cnx, Result};
 
+mod calendar;
+
 /// Shows the current time and date.
 ///
-/// This widget shows the current time and date, in the form `%Y-%m-%d %a %I:%M
-/// %p`, e.g. `2017-09-01 Fri 12:51 PM`.
+/// This widget shows the current time and date, by default in the form
+/// `%Y-%m-%d %a %I:%M %p`, e.g. `2017-09-01 Fri 12:51 PM`. Use
+/// [`with_format()`] to choose a different format, and [`with_timezone()`] to
+/// show the time somewhere other than the local time zone.
+///
+/// Given several formats with [`with_formats()`], left-clicking the clock
+/// switches to the next one. With [`with_calendar()`], right-clicking it pops
+/// up a calendar of the current month.
+///
+/// [`with_format()`]: #method.with_format
+/// [`with_timezone()`]: #method.with_timezone
+/// [`with_formats()`]: #method.with_formats
+/// [`with_calendar()`]: #method.with_calendar
+#[derive(Clone)]
 pub struct Clock {
-    timer: Timer,
+    conn: LazyConnection,
     attr: Attributes,
+    formats: Vec<String>,
+    timezone: Option<Tz>,
+    calendar: bool,
 }
 
 impl Clock {
@@ -24,10 +47,9 @@ impl Clock {
     /// Creates a new `Clock` widget, whose text will be displayed with the
     /// given [`Attributes`].
     ///
-    /// The [`Cnx`] instance is borrowed during construction in order to get
-    /// access to handles of its event loop. However, it is not borrowed for the
-    /// lifetime of the widget. See the [`cnx_add_widget!()`] for more
-    /// discussion about the lifetime of the borrow.
+    /// The [`Cnx`] instance is borrowed during construction. However, it is not
+    /// borrowed for the lifetime of the widget. See the [`cnx_add_widget!()`]
+    /// for more discussion about the lifetime of the borrow.
     ///
     /// [`Attributes`]: ../text/struct.Attributes.html
     /// [`Cnx`]: ../struct.Cnx.html
@@ -59,37 +81,412 @@ impl Clock {
     /// ```
     pub fn new(cnx: &Cnx, attr: Attributes) -> Clock {
         Clock {
-            timer: cnx.timer(),
+            conn: cnx.x_connection(),
             attr,
+            formats: vec!["%Y-%m-%d %a %I:%M %p".to_owned()],
+            timezone: None,
+            calendar: false,
+        }
+    }
+
+    /// Sets the format the time is shown in.
+    ///
+    /// The format uses the [`strftime`]-like syntax of `chrono`. The clock is
+    /// updated once a minute, or once a second if the format shows seconds
+    /// (e.g. with `%S` or `%T`). An error is returned if the format is
+    /// invalid, as described for [`check_format()`].
+    ///
+    /// [`check_format()`]: #method.check_format
+    /// [`strftime`]: https://docs.rs/chrono/0.4/chrono/format/strftime/index.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate cnx;
+    /// #
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// # let attr = Attributes {
+    /// #     font: Font::new("SourceCodePro 21"),
+    /// #     fg_color: Color::white(),
+    /// #     bg_color: None,
+    /// #     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    /// # };
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx_add_widget!(cnx, Clock::new(&cnx, attr.clone()).with_format("%H:%M")?);
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn with_format<S: Into<String>>(self, format: S) -> Result<Clock> {
+        self.with_formats(vec![format])
+    }
+
+    /// Sets the formats the time can be shown in. The first is shown
+    /// initially, and left-clicking the clock switches to the next.
+    ///
+    /// Each format is as described for [`with_format()`], and an error is
+    /// returned if any is invalid. The widget fails to start if no formats are
+    /// given.
+    ///
+    /// [`with_format()`]: #method.with_format
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate cnx;
+    /// #
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// # let attr = Attributes {
+    /// #     font: Font::new("SourceCodePro 21"),
+    /// #     fg_color: Color::white(),
+    /// #     bg_color: None,
+    /// #     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    /// # };
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// let clock = Clock::new(&cnx, attr.clone()).with_formats(vec![
+    ///     "%H:%M",
+    ///     "%A %-d %B %Y",
+    ///     "Week %V",
+    /// ])?;
+    /// cnx_add_widget!(cnx, clock);
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn with_formats<I, S>(mut self, formats: I) -> Result<Clock>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let formats = formats.into_iter().map(Into::into).collect::<Vec<_>>();
+        for format in &formats {
+            Clock::check_format(format)?;
         }
+        self.formats = formats;
+        Ok(self)
+    }
+
+    /// Returns an error if `format` isn't a valid [`strftime`]-like format,
+    /// such as one with an unknown specifier like `%Q`. Chrono can't show the
+    /// time in an invalid format.
+    ///
+    /// [`strftime`]: https://docs.rs/chrono/0.4/chrono/format/strftime/index.html
+    pub fn check_format(format: &str) -> Result<()> {
+        if StrftimeItems::new(format).any(|item| item == Item::Error) {
+            bail!("Invalid time format {:?}", format);
+        }
+        Ok(())
+    }
+
+    /// Sets whether right-clicking the clock pops up a calendar.
+    ///
+    /// The calendar shows the current month, with today highlighted, in the
+    /// clock's font and colors. Scrolling over it shows the previous or next
+    /// month, and clicking it (or right-clicking the clock again) closes it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate cnx;
+    /// #
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// # let attr = Attributes {
+    /// #     font: Font::new("SourceCodePro 21"),
+    /// #     fg_color: Color::white(),
+    /// #     bg_color: None,
+    /// #     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    /// # };
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx_add_widget!(cnx, Clock::new(&cnx, attr.clone()).with_calendar(true));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn with_calendar(mut self, calendar: bool) -> Clock {
+        self.calendar = calendar;
+        self
     }
+
+    /// Shows the time in the given time zone, rather than the local time zone.
+    ///
+    /// The zone is given by its name in the [IANA time zone database] (e.g.
+    /// `UTC` or `America/New_York`), as used for `$TZ`. An error is returned
+    /// if the zone isn't known, as described for [`check_timezone()`].
+    ///
+    /// `%Z` in the format shows the zone's abbreviation, such as `EDT`.
+    ///
+    /// [IANA time zone database]: https://www.iana.org/time-zones
+    /// [`check_timezone()`]: #method.check_timezone
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate cnx;
+    /// #
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// # let attr = Attributes {
+    /// #     font: Font::new("SourceCodePro 21"),
+    /// #     fg_color: Color::white(),
+    /// #     bg_color: None,
+    /// #     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    /// # };
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// for zone in &["UTC", "America/New_York"] {
+    ///     let clock = Clock::new(&cnx, attr.clone())
+    ///         .with_format("%H:%M %Z")?
+    ///         .with_timezone(zone)?;
+    ///     cnx_add_widget!(cnx, clock);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn with_timezone<S: AsRef<str>>(mut self, timezone: S) -> Result<Clock> {
+        self.timezone = Some(parse_timezone(timezone.as_ref())?);
+        Ok(self)
+    }
+
+    /// Returns an error if `timezone` isn't the name of a zone in the IANA
+    /// time zone database, such as `America/NewYork` rather than
+    /// `America/New_York`.
+    pub fn check_timezone(timezone: &str) -> Result<()> {
+        parse_timezone(timezone).map(|_| ())
+    }
+}
+
+fn parse_timezone(timezone: &str) -> Result<Tz> {
+    timezone
+        .parse::<Tz>()
+        .map_err(|e| format_err!("Unknown time zone {:?}: {}", timezone, e))
+}
+
+/// Returns the time `now` in the given zone, or the local time zone.
+fn local_time(now: DateTime<Utc>, zone: Option<&Tz>) -> DateTime<FixedOffset> {
+    match zone {
+        Some(zone) => now.with_timezone(zone).fixed_offset(),
+        None => now.with_timezone(&Local).fixed_offset(),
+    }
+}
+
+/// Formats the time `now` in the given zone, or the local time zone.
+fn format_time(now: DateTime<Utc>, zone: Option<&Tz>, format: &str) -> String {
+    match zone {
+        Some(zone) => now.with_timezone(zone).format(format).to_string(),
+        None => now.with_timezone(&Local).format(format).to_string(),
+    }
+}
+
+/// Returns whether a format shows seconds, and so needs updating every second
+/// rather than every minute.
+fn shows_seconds(format: &str) -> bool {
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            continue;
+        }
+        // Skip any padding flags or widths, such as `%-S` or `%.3f`.
+        let specifier = chars
+            .by_ref()
+            .find(|c| !matches!(c, '-' | '_' | '0'..='9' | '.' | ':' | '#'));
+        if let Some('S' | 'T' | 'X' | 'r' | 's' | 'f' | 'c' | '+') = specifier {
+            return true;
+        }
+    }
+    false
 }
 
 impl Widget for Clock {
     fn stream(self: Box<Self>) -> Result<WidgetStream> {
-        // As we're not showing seconds, we can sleep for however long it takes
-        // until the minutes changes between updates. Initially sleep for 0 seconds
-        // so that our `self.timer.sleep()` expires immediately.
-        let sleep_for = Duration::from_secs(0);
-        let stream = stream::unfold(sleep_for, move |sleep_for| {
-            // Avoid having to move self into the .map() closure.
-            let attr = self.attr.clone();
-            Some(self.timer.sleep(sleep_for).map(move |()| {
-                let now = Local::now();
-                let formatted = now.format("%Y-%m-%d %a %I:%M %p").to_string();
-                let texts = vec![Text {
-                    attr: attr,
-                    text: formatted,
-                    stretch: false,
-                }];
-
-                let sleep_for = Duration::from_secs(60 - u64::from(now.second()));
-                (texts, sleep_for)
-            }))
-        })
-        .then(|r| r.context("Error in tokio_timer stream"))
-        .map_err(|e| e.into());
-
-        Ok(Box::new(stream))
+        self.stream_with_events(Box::pin(stream::empty()))
+    }
+
+    fn stream_with_events(self: Box<Self>, events: EventStream) -> Result<WidgetStream> {
+        if self.formats.is_empty() {
+            bail!("Clock has no formats to show");
+        }
+        let state = ClockState {
+            zone: self.timezone,
+            clock: *self,
+            format_idx: 0,
+            events,
+            calendar: None,
+            // Update immediately.
+            wake: Box::pin(time::sleep(Duration::from_secs(0))),
+        };
+        let stream = stream::unfold(state, |mut state| async move {
+            let texts = state.next().await;
+            Some((texts, state))
+        });
+
+        Ok(Box::pin(stream))
+    }
+}
+
+/// The state of a running clock.
+struct ClockState {
+    clock: Clock,
+    zone: Option<Tz>,
+    /// The index of the format currently shown.
+    format_idx: usize,
+    events: EventStream,
+    calendar: Option<Calendar>,
+    /// Fires when the clock next needs updating.
+    wake: Pin<Box<Sleep>>,
+}
+
+impl ClockState {
+    /// Waits until the clock needs updating, handling any events in the
+    /// meantime, and returns its new texts.
+    async fn next(&mut self) -> Result<Vec<Text>> {
+        loop {
+            let calendar = &mut self.calendar;
+            let calendar_events = async move {
+                match calendar {
+                    Some(calendar) => calendar.events().next().await,
+                    None => future::pending().await,
+                }
+            };
+            tokio::select! {
+                () = &mut self.wake => return Ok(self.tick()),
+                Some(event) = self.events.next() => self.handle_event(&event),
+                Some(event) = calendar_events => {
+                    let close = event.and_then(|event| match self.calendar {
+                        Some(ref mut calendar) => calendar.handle_event(&event),
+                        None => Ok(false),
+                    });
+                    match close {
+                        Ok(false) => {}
+                        Ok(true) => self.calendar = None,
+                        Err(e) => {
+                            warn!("Closing calendar: {}", e);
+                            self.calendar = None;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn tick(&mut self) -> Vec<Text> {
+        let now = Utc::now();
+        let format = &self.clock.formats[self.format_idx];
+        let formatted = format_time(now, self.zone.as_ref(), format);
+        let texts = vec![Text::new(self.clock.attr.clone(), formatted)];
+
+        // Wake just after the next second or minute begins.
+        let until_next_second = Duration::from_secs(1)
+            - Duration::from_nanos(u64::from(now.nanosecond().min(999_999_999)));
+        let sleep_for = if shows_seconds(format) {
+            until_next_second
+        } else {
+            until_next_second + Duration::from_secs(59 - u64::from(now.second().min(59)))
+        };
+        self.wake.as_mut().reset(Instant::now() + sleep_for);
+
+        if let Some(ref mut calendar) = self.calendar {
+            let today = local_time(now, self.zone.as_ref()).naive_local().date();
+            if let Err(e) = calendar.set_today(today) {
+                warn!("Closing calendar: {}", e);
+                self.calendar = None;
+            }
+        }
+
+        texts
+    }
+
+    fn handle_event(&mut self, event: &Event) {
+        let click = match *event {
+            Event::ButtonPress(ref click) => click,
+            _ => return,
+        };
+        match click.button {
+            Button::Left if self.clock.formats.len() > 1 => {
+                self.format_idx = (self.format_idx + 1) % self.clock.formats.len();
+                // Show the new format straight away.
+                self.wake.as_mut().reset(Instant::now());
+            }
+            Button::Right if self.clock.calendar => self.toggle_calendar(),
+            _ => {}
+        }
+    }
+
+    fn toggle_calendar(&mut self) {
+        // Dropping the calendar closes it.
+        if self.calendar.take().is_some() {
+            return;
+        }
+
+        let today = local_time(Utc::now(), self.zone.as_ref())
+            .naive_local()
+            .date();
+        let calendar = self
+            .clock
+            .conn
+            .get()
+            .and_then(|conn| Calendar::open(conn, &self.clock.attr, today));
+        match calendar {
+            Ok(calendar) => self.calendar = Some(calendar),
+            Err(e) => warn!("Failed to open calendar: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detects_formats_showing_seconds() {
+        assert!(shows_seconds("%H:%M:%S"));
+        assert!(shows_seconds("%T"));
+        assert!(shows_seconds("%-S"));
+        assert!(!shows_seconds("%Y-%m-%d %a %I:%M %p"));
+        assert!(!shows_seconds("%%S"));
+    }
+
+    #[test]
+    fn rejects_invalid_formats() {
+        assert!(Clock::check_format("%Y-%m-%d %a %I:%M %p").is_ok());
+        assert!(Clock::check_format("100%% %H:%M").is_ok());
+        assert!(Clock::check_format("%Q").is_err());
+        assert!(Clock::check_format("%H:%M %").is_err());
+    }
+
+    #[test]
+    fn formats_time_in_zone() {
+        let zone = "America/New_York".parse::<Tz>().unwrap();
+        let summer = Utc.with_ymd_and_hms(2024, 7, 1, 12, 0, 0).unwrap();
+        let winter = Utc.with_ymd_and_hms(2024, 12, 1, 12, 0, 0).unwrap();
+        assert_eq!(format_time(summer, Some(&zone), "%H:%M %Z"), "08:00 EDT");
+        assert_eq!(format_time(winter, Some(&zone), "%H:%M %Z"), "07:00 EST");
+        assert_eq!(
+            local_time(winter, Some(&zone)).naive_local().date(),
+            NaiveDate::from_ymd_opt(2024, 12, 1).unwrap()
+        );
+        assert!(Clock::check_timezone("UTC").is_ok());
+        let error = Clock::check_timezone("America/NewYork").unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Unknown time zone \"America/NewYork\""));
     }
 }
@@ -0,0 +1,389 @@
+//! The month calendar which pops up under the clock.
+
+use std::rc::Rc;
+
+use cairo::{Context, Format, ImageSurface, Operator};
+use chrono::{Datelike, NaiveDate};
+use failure::{format_err, ResultExt};
+
+use crate::bar::{cairo_surface_for_xcb_window, get_root_visual_type};
+use crate::text::{Attributes, Color, ComputedText, Padding, Text};
+use crate::widgets::Button;
+use crate::x11::{Connection, Subscription, WindowWatcher};
+use crate::Result;
+
+const WEEKDAYS: [&str; 7] = ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"];
+/// The most weeks a month can span. Every month is shown with this many rows,
+/// so that the popup doesn't change size as it's scrolled.
+const MAX_WEEKS: usize = 6;
+/// The space around the calendar, inside the popup.
+const MARGIN: f64 = 8.0;
+
+/// Returns the days of a month laid out in weeks, starting on Monday. Days
+/// before the 1st and after the end of the month are `None`.
+pub(super) fn weeks(year: i32, month: u32) -> Vec<[Option<u32>; 7]> {
+    let first = match NaiveDate::from_ymd_opt(year, month, 1) {
+        Some(first) => first,
+        None => return Vec::new(),
+    };
+    let (next_year, next_month) = add_months(year, month, 1);
+    let days = NaiveDate::from_ymd_opt(next_year, next_month, 1)
+        .map_or(31, |next| next.pred_opt().map_or(31, |last| last.day()));
+
+    let mut weeks = Vec::new();
+    let mut week = [None; 7];
+    let mut weekday = first.weekday().num_days_from_monday() as usize;
+    for day in 1..=days {
+        week[weekday] = Some(day);
+        weekday += 1;
+        if weekday == 7 {
+            weeks.push(week);
+            week = [None; 7];
+            weekday = 0;
+        }
+    }
+    if weekday > 0 {
+        weeks.push(week);
+    }
+    weeks
+}
+
+/// Returns the month `delta` months after the given one.
+pub(super) fn add_months(year: i32, month: u32, delta: i32) -> (i32, u32) {
+    let months = year * 12 + month as i32 - 1 + delta;
+    (months.div_euclid(12), months.rem_euclid(12) as u32 + 1)
+}
+
+/// Returns where to put a popup of the given size, so that it's next to the
+/// bar (if the pointer is over it) and under the pointer, but on the screen.
+///
+/// The bar is given as its `(x, y, width, height)`. It's assumed to be along
+/// whichever edge of the screen it's closest to, so the popup goes below a
+/// bar in the top half of the screen, above one in the bottom half, and
+/// beside a vertical bar.
+pub(super) fn place(
+    size: (f64, f64),
+    pointer: (f64, f64),
+    bar: Option<(f64, f64, f64, f64)>,
+    screen: (f64, f64),
+) -> (f64, f64) {
+    let (width, height) = size;
+    let (pointer_x, pointer_y) = pointer;
+    let clamp = |value: f64, size: f64, max: f64| value.min(max - size).max(0.0);
+
+    let (x, y) = match bar {
+        Some((_, y, w, h)) if w >= h => {
+            let y = if y + h / 2.0 < screen.1 / 2.0 {
+                y + h
+            } else {
+                y - height
+            };
+            (pointer_x - width / 2.0, y)
+        }
+        Some((x, _, w, _)) => {
+            let x = if x + w / 2.0 < screen.0 / 2.0 {
+                x + w
+            } else {
+                x - width
+            };
+            (x, pointer_y - height / 2.0)
+        }
+        None => (pointer_x - width / 2.0, pointer_y),
+    };
+    (clamp(x, width, screen.0), clamp(y, height, screen.1))
+}
+
+/// A popup window showing a month calendar, with today highlighted.
+///
+/// Scrolling over the popup shows the previous or next month, and clicking it
+/// closes it. The window is destroyed when this is dropped.
+pub(super) struct Calendar {
+    conn: Rc<Connection>,
+    watcher: WindowWatcher,
+    events: Subscription<xcb::GenericEvent>,
+    window: xcb::Window,
+    surface: cairo::Surface,
+    attr: Attributes,
+    background: Color,
+    /// The size of each day in the calendar.
+    cell: (f64, f64),
+    /// The height of the month's title.
+    title_height: f64,
+    width: f64,
+    today: NaiveDate,
+    shown: (i32, u32),
+}
+
+impl Calendar {
+    /// Opens the calendar next to the pointer, showing the month of `today`.
+    /// Texts are shown with the font and colors of `attr`.
+    pub(super) fn open(
+        conn: Rc<Connection>,
+        attr: &Attributes,
+        today: NaiveDate,
+    ) -> Result<Calendar> {
+        let background = attr.bg_color.clone().unwrap_or_else(Color::black);
+        let attr = Attributes {
+            bg_color: None,
+            padding: Padding::new(4.0, 4.0, 2.0, 2.0),
+            ..attr.clone()
+        };
+
+        // Measure the widest day and month title, so that the popup stays the
+        // same size whichever month it shows.
+        let scratch = ImageSurface::create(Format::ARgb32, 1, 1)
+            .map_err(|status| format_err!("Failed to create image surface: {:?}", status))?;
+        let mut cell: (f64, f64) = (0.0, 0.0);
+        let days = (1..=31).map(|day| day.to_string());
+        for label in WEEKDAYS.iter().map(|&s| s.to_owned()).chain(days) {
+            let computed = text(&attr, label).compute(&scratch)?;
+            cell = (cell.0.max(computed.width), cell.1.max(computed.height));
+        }
+        let (mut title_width, mut title_height): (f64, f64) = (0.0, 0.0);
+        for month in 1..=12 {
+            let computed = text(&attr, title(today.year(), month)).compute(&scratch)?;
+            title_width = title_width.max(computed.width);
+            title_height = title_height.max(computed.height);
+        }
+        let width = (cell.0 * 7.0).max(title_width) + MARGIN * 2.0;
+        let height = title_height + cell.1 * (MAX_WEEKS + 1) as f64 + MARGIN * 2.0;
+        let (width, height) = (width.ceil(), height.ceil());
+
+        let screen_idx = conn.screen_idx();
+        let screen = conn
+            .get_setup()
+            .roots()
+            .nth(screen_idx as usize)
+            .ok_or_else(|| format_err!("Invalid screen"))?;
+        let root = screen.root();
+
+        // The pointer is (probably) still over the clock that was clicked, so
+        // we pop up next to whichever window it's over.
+        let pointer = xcb::query_pointer(&conn, root)
+            .get_reply()
+            .context("Failed to query pointer")?;
+        let bar = if pointer.child() != xcb::NONE {
+            xcb::get_geometry(&conn, pointer.child())
+                .get_reply()
+                .ok()
+                .map(|g| {
+                    let (x, y) = (f64::from(g.x()), f64::from(g.y()));
+                    (x, y, f64::from(g.width()), f64::from(g.height()))
+                })
+        } else {
+            None
+        };
+        let (x, y) = place(
+            (width, height),
+            (f64::from(pointer.root_x()), f64::from(pointer.root_y())),
+            bar,
+            (
+                f64::from(screen.width_in_pixels()),
+                f64::from(screen.height_in_pixels()),
+            ),
+        );
+
+        // The popup is override-redirect, so that the WM leaves it where we
+        // put it.
+        let window = conn.generate_id();
+        let values = [
+            (xcb::CW_BACK_PIXEL, screen.black_pixel()),
+            (xcb::CW_OVERRIDE_REDIRECT, 1),
+            (
+                xcb::CW_EVENT_MASK,
+                xcb::EVENT_MASK_EXPOSURE | xcb::EVENT_MASK_BUTTON_PRESS,
+            ),
+        ];
+        let visual = get_root_visual_type(&conn, &screen);
+        xcb::create_window(
+            &conn,
+            xcb::COPY_FROM_PARENT as u8,
+            window,
+            root,
+            x as i16,
+            y as i16,
+            width as u16,
+            height as u16,
+            0,
+            xcb::WINDOW_CLASS_INPUT_OUTPUT as u16,
+            visual.visual_id(),
+            &values,
+        );
+        xcb_util::ewmh::set_wm_window_type(&conn, window, &[conn.WM_WINDOW_TYPE_POPUP_MENU()]);
+        let surface =
+            cairo_surface_for_xcb_window(&conn, visual, window, width as i32, height as i32);
+
+        let (watcher, events) = conn.watch_windows();
+        watcher.watch(window);
+        // The popup is drawn when it's first exposed.
+        xcb::map_window(&conn, window);
+        conn.flush();
+
+        Ok(Calendar {
+            conn,
+            watcher,
+            events,
+            window,
+            surface,
+            attr,
+            background,
+            cell,
+            title_height,
+            width,
+            today,
+            shown: (today.year(), today.month()),
+        })
+    }
+
+    /// Returns the stream of X events for the popup.
+    pub(super) fn events(&mut self) -> &mut Subscription<xcb::GenericEvent> {
+        &mut self.events
+    }
+
+    /// Handles an X event for the popup, returning whether it should be
+    /// closed.
+    pub(super) fn handle_event(&mut self, event: &xcb::GenericEvent) -> Result<bool> {
+        match event.response_type() & !0x80 {
+            xcb::EXPOSE => self.redraw()?,
+            xcb::BUTTON_PRESS => {
+                let event: &xcb::ButtonPressEvent = unsafe { xcb::cast_event(event) };
+                let delta = match Button::from_x11(event.detail()) {
+                    Button::ScrollUp | Button::ScrollLeft => -1,
+                    Button::ScrollDown | Button::ScrollRight => 1,
+                    _ => return Ok(true),
+                };
+                self.shown = add_months(self.shown.0, self.shown.1, delta);
+                self.redraw()?;
+            }
+            _ => {}
+        }
+        Ok(false)
+    }
+
+    /// Highlights `today`, redrawing the calendar if the date has changed.
+    pub(super) fn set_today(&mut self, today: NaiveDate) -> Result<()> {
+        if today != self.today {
+            // Follow the date into the next month, unless the user has
+            // scrolled away from it.
+            if self.shown == (self.today.year(), self.today.month()) {
+                self.shown = (today.year(), today.month());
+            }
+            self.today = today;
+            self.redraw()?;
+        }
+        Ok(())
+    }
+
+    fn redraw(&self) -> Result<()> {
+        let context = Context::new(&self.surface);
+        context.set_operator(Operator::Source);
+        self.background.apply_to_context(&context);
+        context.paint();
+
+        let (year, month) = self.shown;
+        let mut title = text(&self.attr, title(year, month)).compute(&self.surface)?;
+        title.x = ((self.width - title.width) / 2.0).round();
+        title.y = MARGIN;
+        title.render(&self.surface, &self.background)?;
+
+        // Right-align each day in its cell, with today's colors inverted.
+        let left = ((self.width - self.cell.0 * 7.0) / 2.0).round();
+        let top = MARGIN + self.title_height;
+        let highlight = Attributes {
+            fg_color: self.background.clone(),
+            bg_color: Some(self.attr.fg_color.clone()),
+            ..self.attr.clone()
+        };
+        let header = WEEKDAYS
+            .iter()
+            .map(|&label| Some((label.to_owned(), false)));
+        let rows = weeks(year, month).into_iter().flat_map(|week| {
+            week.iter()
+                .map(|day| {
+                    day.map(|day| {
+                        let date = NaiveDate::from_ymd_opt(year, month, day);
+                        (day.to_string(), date == Some(self.today))
+                    })
+                })
+                .collect::<Vec<_>>()
+        });
+        for (i, cell) in header.chain(rows).enumerate() {
+            let (label, is_today) = match cell {
+                Some(cell) => cell,
+                None => continue,
+            };
+            let attr = if is_today { &highlight } else { &self.attr };
+            let mut computed = text(attr, label).compute(&self.surface)?;
+            fill_cell(&mut computed, self.cell);
+            computed.x = left + (i % 7) as f64 * self.cell.0;
+            computed.y = top + (i / 7) as f64 * self.cell.1;
+            computed.render(&self.surface, &self.background)?;
+        }
+
+        self.conn.flush();
+        Ok(())
+    }
+}
+
+impl Drop for Calendar {
+    fn drop(&mut self) {
+        self.watcher.unwatch(self.window);
+        xcb::destroy_window(&self.conn, self.window);
+        self.conn.flush();
+    }
+}
+
+fn text(attr: &Attributes, text: String) -> Text {
+    Text::new(attr.clone(), text)
+}
+
+fn title(year: i32, month: u32) -> String {
+    NaiveDate::from_ymd_opt(year, month, 1)
+        .map(|date| date.format("%B %Y").to_string())
+        .unwrap_or_default()
+}
+
+/// Widens a text to fill its cell, keeping the text at the right of it.
+fn fill_cell(text: &mut ComputedText, (width, height): (f64, f64)) {
+    text.attr.padding.left += width - text.width;
+    text.attr.padding.bottom += height - text.height;
+    text.width = width;
+    text.height = height;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lays_out_months_from_monday() {
+        // October 2026 starts on a Thursday.
+        let october = weeks(2026, 10);
+        assert_eq!(october.len(), 5);
+        assert_eq!(
+            october[0],
+            [None, None, None, Some(1), Some(2), Some(3), Some(4)]
+        );
+        assert_eq!(october[4][5], Some(31));
+        assert_eq!(weeks(2024, 2).iter().flatten().flatten().max(), Some(&29));
+        assert_eq!(add_months(2026, 1, -1), (2025, 12));
+        assert_eq!(add_months(2026, 12, 1), (2027, 1));
+    }
+
+    #[test]
+    fn places_popup_beside_bar() {
+        let screen = (1000.0, 800.0);
+        let size = (200.0, 100.0);
+        // Under a top bar, centred on the pointer.
+        let top = Some((0.0, 0.0, 1000.0, 20.0));
+        assert_eq!(place(size, (500.0, 10.0), top, screen), (400.0, 20.0));
+        // Kept on the screen.
+        assert_eq!(place(size, (990.0, 10.0), top, screen), (800.0, 20.0));
+        // Above a bottom bar.
+        let bottom = Some((0.0, 780.0, 1000.0, 20.0));
+        assert_eq!(place(size, (50.0, 790.0), bottom, screen), (0.0, 680.0));
+        // Left of a bar on the right.
+        let right = Some((980.0, 0.0, 20.0, 800.0));
+        assert_eq!(place(size, (990.0, 400.0), right, screen), (780.0, 350.0));
+    }
+}
@@ -0,0 +1,120 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use futures::{future, stream, StreamExt};
+
+use super::{Event, EventStream, Widget, WidgetStream};
+use crate::text::{Attributes, Text};
+use crate::{Cnx, Result};
+
+/// Shows text sent to it by another program.
+///
+/// This widget shows whatever text it was last sent over Cnx's IPC socket,
+/// e.g. with `cnx-msg send <widget> <text>`, which makes it easy to show the
+/// output of a script in the bar. Sending an empty text hides the widget. See
+/// the [`ipc`] module for more details. Use [`with_markup()`] to style parts
+/// of the text with Pango markup.
+///
+/// The widget should be added with [`Cnx::add_named_widget_to()`], so that
+/// scripts can refer to it by name.
+///
+/// [`ipc`]: ../ipc/index.html
+/// [`with_markup()`]: #method.with_markup
+/// [`Cnx::add_named_widget_to()`]: ../struct.Cnx.html#method.add_named_widget_to
+#[derive(Clone)]
+pub struct Custom {
+    attr: Attributes,
+    // Shared between clones, so that the widget keeps showing the last text it
+    // was sent when it's restarted.
+    text: Rc<RefCell<String>>,
+    markup: bool,
+}
+
+impl Custom {
+    /// Creates a new Custom widget.
+    ///
+    /// Creates a new `Custom` widget, whose text will be displayed with the
+    /// given [`Attributes`]. It shows nothing until it's sent some text.
+    ///
+    /// The [`Cnx`] instance is borrowed during construction. However, it is not
+    /// borrowed for the lifetime of the widget. See the [`cnx_add_widget!()`]
+    /// for more discussion about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: Color::white(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// let widget = Custom::new(&cnx, attr.clone());
+    /// cnx.add_named_widget_to(Region::Right, "status", widget);
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(_cnx: &Cnx, attr: Attributes) -> Custom {
+        Custom {
+            attr,
+            text: Rc::default(),
+            markup: false,
+        }
+    }
+
+    /// Sets the text shown until the widget is sent some other text.
+    pub fn with_text<S: Into<String>>(self, text: S) -> Custom {
+        *self.text.borrow_mut() = text.into();
+        self
+    }
+
+    /// Sets whether the text is interpreted as [Pango markup], e.g.
+    /// `<b>bold</b>`. Invalid markup is shown as it is.
+    ///
+    /// [Pango markup]: https://docs.gtk.org/Pango/pango_markup.html
+    pub fn with_markup(mut self, markup: bool) -> Custom {
+        self.markup = markup;
+        self
+    }
+
+    fn tick(&self) -> Vec<Text> {
+        let text = self.text.borrow();
+        if text.is_empty() {
+            return Vec::new();
+        }
+        vec![Text::new(self.attr.clone(), text.clone()).with_markup(self.markup)]
+    }
+}
+
+impl Widget for Custom {
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        self.stream_with_events(Box::pin(stream::empty()))
+    }
+
+    fn stream_with_events(self: Box<Self>, events: EventStream) -> Result<WidgetStream> {
+        let initial = stream::once(future::ready(Ok(self.tick())));
+        let updates = events.filter_map(move |event| {
+            let texts = match event {
+                Event::Message(text) => {
+                    *self.text.borrow_mut() = text;
+                    Some(Ok(self.tick()))
+                }
+                _ => None,
+            };
+            future::ready(texts)
+        });
+        Ok(Box::pin(initial.chain(updates)))
+    }
+}
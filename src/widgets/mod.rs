@@ -1,9 +1,19 @@
 //! Built-in widgets
 
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
 use failure::Error;
-use futures::{Async, Poll, Stream};
+use futures::channel::mpsc::{self, UnboundedSender};
+use futures::Stream;
+use log::*;
+use tokio::time::{self, Instant, Sleep};
 
-use crate::text::Text;
+use crate::text::{Attributes, Color, Font, Padding, Text};
 use crate::Result;
 
 /// The stream of `Vec<Text>` returned by each widget.
@@ -13,155 +23,730 @@ use crate::Result;
 /// refer to the documentation on the [`Widget`] trait.
 ///
 /// [`Widget`]: trait.Widget.html
-pub type WidgetStream = Box<dyn Stream<Item = Vec<Text>, Error = Error>>;
+pub type WidgetStream = Pin<Box<dyn Stream<Item = Result<Vec<Text>>>>>;
+
+/// The stream of [`Event`]s given to each widget.
+///
+/// See [`Widget::stream_with_events()`] for more information.
+///
+/// [`Event`]: enum.Event.html
+/// [`Widget::stream_with_events()`]: trait.Widget.html#method.stream_with_events
+pub type EventStream = Pin<Box<dyn Stream<Item = Event>>>;
+
+/// A mouse button, as reported in a [`Click`].
+///
+/// [`Click`]: struct.Click.html
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Button {
+    /// The primary (usually left) mouse button.
+    Left,
+    /// The middle mouse button (or clicking the scroll wheel).
+    Middle,
+    /// The secondary (usually right) mouse button.
+    Right,
+    /// Scrolling up with the scroll wheel.
+    ScrollUp,
+    /// Scrolling down with the scroll wheel.
+    ScrollDown,
+    /// Scrolling left with the scroll wheel.
+    ScrollLeft,
+    /// Scrolling right with the scroll wheel.
+    ScrollRight,
+    /// Any other button, identified by its X11 button number.
+    Other(u8),
+}
+
+impl Button {
+    pub(crate) fn from_x11(detail: u8) -> Button {
+        match detail {
+            1 => Button::Left,
+            2 => Button::Middle,
+            3 => Button::Right,
+            4 => Button::ScrollUp,
+            5 => Button::ScrollDown,
+            6 => Button::ScrollLeft,
+            7 => Button::ScrollRight,
+            other => Button::Other(other),
+        }
+    }
+
+    /// Returns whether this "button" is actually the scroll wheel.
+    pub fn is_scroll(self) -> bool {
+        matches!(
+            self,
+            Button::ScrollUp | Button::ScrollDown | Button::ScrollLeft | Button::ScrollRight
+        )
+    }
+}
+
+/// The keyboard modifiers held down during a [`Click`].
+///
+/// [`Click`]: struct.Click.html
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub control: bool,
+    /// The `Mod1` modifier, which is usually `Alt`.
+    pub alt: bool,
+    /// The `Mod4` modifier, which is usually the "Windows" key.
+    pub logo: bool,
+}
+
+impl Modifiers {
+    pub(crate) fn from_x11(state: u16) -> Modifiers {
+        let state = u32::from(state);
+        Modifiers {
+            shift: state & xcb::MOD_MASK_SHIFT != 0,
+            control: state & xcb::MOD_MASK_CONTROL != 0,
+            alt: state & xcb::MOD_MASK_1 != 0,
+            logo: state & xcb::MOD_MASK_4 != 0,
+        }
+    }
+}
+
+/// Details of a mouse button press or release over a widget.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Click {
+    /// The button that was pressed or released.
+    pub button: Button,
+    /// The keyboard modifiers that were held down at the time.
+    pub modifiers: Modifiers,
+    /// The index of the [`Text`] under the pointer, in the `Vec<Text>` most
+    /// recently returned by the widget.
+    ///
+    /// [`Text`]: ../text/struct.Text.html
+    pub index: usize,
+    /// The horizontal position of the pointer, relative to the left edge of
+    /// the [`Text`].
+    ///
+    /// [`Text`]: ../text/struct.Text.html
+    pub x: f64,
+    /// The vertical position of the pointer, relative to the top edge of the
+    /// [`Text`].
+    ///
+    /// [`Text`]: ../text/struct.Text.html
+    pub y: f64,
+}
+
+/// An event sent to a widget.
+///
+/// Widgets receive these through the [`EventStream`] given to
+/// [`Widget::stream_with_events()`].
+///
+/// [`EventStream`]: type.EventStream.html
+/// [`Widget::stream_with_events()`]: trait.Widget.html#method.stream_with_events
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// A mouse button was pressed over one of the widget's texts. Scrolling is
+    /// reported as a press of one of the scroll "buttons".
+    ButtonPress(Click),
+    /// A mouse button was released over one of the widget's texts. No release
+    /// events are sent for scrolling.
+    ButtonRelease(Click),
+    /// A message was sent to the widget over Cnx's IPC socket, e.g. with
+    /// `cnx-msg send <widget> <message>`. See the [`ipc`] module.
+    ///
+    /// [`ipc`]: ../ipc/index.html
+    Message(String),
+}
 
 /// The main trait implemented by all widgets.
 ///
 /// This simple trait defines a widget. A widget is essentially just a
-/// [`futures::Stream<Item = Vec<Text>, ...>`][widget-stream] and this trait
+/// [`futures::Stream<Item = Result<Vec<Text>>>`][widget-stream] and this trait
 /// just defines a standard way to get at that stream.
 ///
+/// Streams are polled by a single-threaded [`tokio`] runtime, so they need not
+/// be `Send`. They may use anything that runs on a Tokio runtime, such as
+/// `tokio::time` or `tokio::io::unix::AsyncFd`.
+///
+/// If a widget's stream returns an error, Cnx logs the error, shows an
+/// [`ErrorPlaceholder`] in its place and later restarts the widget by calling
+/// [`stream_with_events()`] on a fresh clone of it. An error only ends the
+/// widget's stream, not the whole bar.
+///
 /// Please note that this is currently considered **unstable**. This trait is
 /// very likely to change in the future.
 ///
-/// [widget-stream]: https://docs.rs/futures/0.1.15/futures/stream/trait.Stream.html
+/// [`ErrorPlaceholder`]: enum.ErrorPlaceholder.html
+/// [`stream_with_events()`]: #method.stream_with_events
+///
+/// [widget-stream]: https://docs.rs/futures/0.3/futures/stream/trait.Stream.html
+/// [`tokio`]: https://tokio.rs/
 pub trait Widget {
-    ///
+    /// Consumes the widget and returns the stream of its `Vec<Text>` updates.
     fn stream(self: Box<Self>) -> Result<WidgetStream>;
+
+    /// Consumes the widget and returns the stream of its `Vec<Text>` updates,
+    /// given a stream of the [`Event`]s (e.g. mouse clicks) it receives.
+    ///
+    /// This is what Cnx calls to get a widget's stream. Widgets which want to
+    /// respond to events should override it, typically by selecting over the
+    /// given `events` and their own stream. The default implementation
+    /// ignores all events and returns [`stream()`].
+    ///
+    /// [`Event`]: enum.Event.html
+    /// [`stream()`]: #tymethod.stream
+    fn stream_with_events(self: Box<Self>, events: EventStream) -> Result<WidgetStream> {
+        drop(events);
+        self.stream()
+    }
+
+    /// Returns whether the widget only shows windows embedded in Cnx's own
+    /// bar, such as the [`Tray`]'s icons.
+    ///
+    /// Such widgets have nothing to show when Cnx writes to stdout rather than
+    /// drawing its bar, so they are skipped in the other [`OutputMode`]s. The
+    /// default implementation returns `false`.
+    ///
+    /// [`Tray`]: struct.Tray.html
+    /// [`OutputMode`]: ../enum.OutputMode.html
+    fn embeds_windows(&self) -> bool {
+        false
+    }
 }
 
 macro_rules! timer_widget {
-    ($widget:ty, $timer:ident, $interval:ident, $tick:ident) => {
+    ($widget:ty, $interval:ident, $tick:ident) => {
         impl crate::widgets::Widget for $widget {
             fn stream(self: Box<Self>) -> crate::Result<crate::widgets::WidgetStream> {
-                use failure::Error;
-                use futures::{stream, Stream};
+                use futures::StreamExt;
+                use tokio::time::{self, MissedTickBehavior};
+                use tokio_stream::wrappers::IntervalStream;
 
-                // The Timer will only fire after the first interval. To avoid
-                // waiting for the initial state, call the tick ourselves.
-                let initial = stream::once::<_, Error>(self.$tick());
+                // The first tick completes immediately, so we don't have to
+                // wait an interval for the initial state. If we miss ticks
+                // (e.g. because the machine was suspended), don't try to catch
+                // up on them.
+                let mut interval = time::interval(self.$interval);
+                interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                let text_stream = IntervalStream::new(interval).map(move |_| self.$tick());
 
-                let timer_stream = self.$timer.interval(self.$interval);
-                let text_stream = timer_stream
-                    .map_err(|e| e.into())
-                    .and_then(move |_| self.$tick());
-
-                Ok(Box::new(initial.chain(text_stream)))
+                Ok(Box::pin(text_stream))
             }
         }
     };
 }
 
 macro_rules! x_properties_widget {
-    ($widget:ty, $handle:ident, $on_change:ident; [ $( $property:ident ),+ ])  => {
+    ($widget:ty, $conn:ident, $on_change:ident; [ $( $property:ident ),+ ]) => {
+        x_properties_widget!(
+            @impl $widget, $conn, $on_change,
+            |_: &$widget, _: &crate::x11::Connection, _: i32, _: &crate::widgets::Event| -> crate::Result<()> {
+                Ok(())
+            };
+            [ $( $property ),+ ]
+        );
+    };
+    ($widget:ty, $conn:ident, $on_change:ident, $on_event:ident; [ $( $property:ident ),+ ]) => {
+        x_properties_widget!(
+            @impl $widget, $conn, $on_change, <$widget>::$on_event;
+            [ $( $property ),+ ]
+        );
+    };
+    (@impl $widget:ty, $conn:ident, $on_change:ident, $on_event:expr; [ $( $property:ident ),+ ]) => {
         impl crate::widgets::Widget for $widget {
             fn stream(self: Box<Self>) -> crate::Result<crate::widgets::WidgetStream> {
+                self.stream_with_events(Box::pin(futures::stream::empty()))
+            }
+
+            fn stream_with_events(
+                self: Box<Self>,
+                events: crate::widgets::EventStream,
+            ) -> crate::Result<crate::widgets::WidgetStream> {
                 use std::rc::Rc;
 
-                use failure::{format_err, Error, ResultExt};
-                use futures::{stream, Stream};
-                use xcb;
-                use xcb::xproto::{PropertyNotifyEvent, PROPERTY_NOTIFY};
-
-                use crate::bar::XcbEventStream;
-
-                let (xcb_conn, screen_idx) = xcb::Connection::connect(None)
-                    .context("Failed to connect to X server")?;
-                let root_window = xcb_conn
-                    .get_setup()
-                    .roots()
-                    .nth(screen_idx as usize)
-                    .ok_or_else(|| format_err!("Invalid screen"))?
-                    .root();
-                let ewmh_conn = ewmh::Connection::connect(xcb_conn)
-                    .map_err(|(e, _)| e)
-                    .context("Failed to wrap xcb::Connection in ewmh::Connection")?;
-                let conn = Rc::new(ewmh_conn);
+                use futures::{future, stream, StreamExt};
 
-                let properties = [ $( conn.$property() ),+ ];
+                let conn = self.$conn.get()?;
+                let screen_idx = conn.screen_idx();
+                let widget: Rc<$widget> = Rc::new(*self);
 
-                // Register for all PROPERTY_CHANGE events. We'll filter out the ones
-                // that are interesting below.
-                let attributes = [(xcb::CW_EVENT_MASK, xcb::EVENT_MASK_PROPERTY_CHANGE)];
-                xcb::change_window_attributes(&conn, root_window, &attributes);
-                conn.flush();
+                // Subscribe to changes of our properties on the root window
+                // (and any other window the widget selects property changes
+                // for), before getting the initial contents of the widget, so
+                // that we don't miss any changes in between.
+                let properties = [ $( conn.$property() ),+ ];
+                let changes = conn.watch_properties(&properties);
 
                 // Pretend there was an initial property change to get the initial
-                // contents of the widget, then allow our stream of XCB events to
-                // call the callback for actual changes.
-                let initial = stream::once::<_, Error>(self.$on_change(&conn, screen_idx));
-
-                let xcb_stream = XcbEventStream::new(conn.clone(), &self.$handle)?;
-                let text_stream = xcb_stream.filter_map(move |event| {
-                    if event.response_type() == PROPERTY_NOTIFY {
-                        let event: &PropertyNotifyEvent = unsafe { xcb::cast_event(&event) };
-                        if properties.iter().any(|p| *p == event.atom()) {
-                            // We don't actually care about the event, just that
-                            // it occurred.
-                            return Some(());
-                        }
+                // contents of the widget, then allow our stream of property
+                // changes to call the callback for actual changes.
+                let initial = stream::once(future::ready(widget.$on_change(&conn, screen_idx)));
+
+                let text_stream = changes.map({
+                    let widget = widget.clone();
+                    let conn = conn.clone();
+                    // We don't actually care which property changed, just
+                    // that one did.
+                    move |change| change.and_then(|_| widget.$on_change(&conn, screen_idx))
+                });
+
+                // Pass any events to the widget. They don't produce any text
+                // themselves: if they cause a property to change, we'll get a
+                // PROPERTY_NOTIFY which will update the widget.
+                let on_event = $on_event;
+                let events = events.filter_map(move |event| {
+                    if let Err(e) = on_event(&*widget, &conn, screen_idx, &event) {
+                        log::warn!("Failed to handle event {:?}: {}", event, e);
                     }
-                    None
-                }).and_then(move |()| {
-                    self.$on_change(&conn, screen_idx)
+                    future::ready(None)
                 });
 
-                Ok(Box::new(initial.chain(text_stream)))
+                Ok(Box::pin(stream::select(initial.chain(text_stream), events)))
             }
         }
-    }
+    };
 }
 
 // Defined after macros because of macro scoping rules:
 mod active_window_title;
 mod battery;
 mod clock;
+mod custom;
 mod pager;
 mod sensors;
+mod timer;
+mod tray;
 #[cfg(feature = "volume-widget")]
 mod volume;
 
 pub use self::active_window_title::ActiveWindowTitle;
 pub use self::battery::Battery;
 pub use self::clock::Clock;
+pub use self::custom::Custom;
 pub use self::pager::Pager;
-pub use self::sensors::Sensors;
+pub use self::sensors::{SensorKind, SensorSource, Sensors};
+pub use self::timer::Timer;
+pub use self::tray::Tray;
 #[cfg(feature = "volume-widget")]
 pub use self::volume::Volume;
 
+/// What to show in place of a widget whose stream has failed.
+///
+/// When a widget's stream returns an error, Cnx logs the error and shows this
+/// placeholder instead of the widget's texts until the widget is restarted.
+/// Widgets are restarted after a delay, which doubles each time the widget
+/// fails without having produced any texts in between.
+///
+/// Passed to [`Cnx::set_error_placeholder()`]. The default is a red `!`.
+///
+/// [`Cnx::set_error_placeholder()`]: ../struct.Cnx.html#method.set_error_placeholder
+#[derive(Clone, Debug, PartialEq)]
+pub enum ErrorPlaceholder {
+    /// Hide the widget.
+    Hidden,
+    /// Show the given text in the given color. The font and padding of the
+    /// text the widget last showed are used, if it showed any.
+    Text(String, Color),
+}
+
+impl Default for ErrorPlaceholder {
+    fn default() -> ErrorPlaceholder {
+        ErrorPlaceholder::Text("!".to_owned(), Color::red())
+    }
+}
+
+impl ErrorPlaceholder {
+    fn texts(&self, attr: Option<&Attributes>) -> Vec<Text> {
+        match self {
+            ErrorPlaceholder::Hidden => Vec::new(),
+            ErrorPlaceholder::Text(text, color) => {
+                let attr = match attr {
+                    Some(attr) => Attributes {
+                        fg_color: color.clone(),
+                        ..attr.clone()
+                    },
+                    None => Attributes {
+                        font: Font::new("monospace"),
+                        fg_color: color.clone(),
+                        bg_color: None,
+                        padding: Padding::new(0.0, 0.0, 0.0, 0.0),
+                    },
+                };
+                vec![Text::new(attr, text.clone())]
+            }
+        }
+    }
+}
+
+/// Creates a fresh copy of a widget, so that it can be restarted.
+pub(crate) type WidgetFactory = Box<dyn Fn() -> Box<dyn Widget>>;
+
+/// The delay before restarting a widget which has failed once.
+const INITIAL_RESTART_DELAY: Duration = Duration::from_secs(1);
+/// The longest we'll wait before restarting a widget.
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(300);
+
+/// Returns how long to wait before restarting a widget which has failed this
+/// many times in a row: twice as long as the last time, up to a limit.
+fn restart_delay(failures: u32) -> Duration {
+    INITIAL_RESTART_DELAY
+        .checked_mul(1 << failures.min(16))
+        .map_or(MAX_RESTART_DELAY, |delay| delay.min(MAX_RESTART_DELAY))
+}
+
+/// The senders used to pass events to each widget.
+///
+/// A new channel is created each time a widget is (re)started, so this is
+/// shared between the `WidgetList` and whoever is sending the events.
+#[derive(Clone, Default)]
+pub(crate) struct EventSenders(Rc<RefCell<Vec<Option<UnboundedSender<Event>>>>>);
+
+impl EventSenders {
+    /// Sends an event to the widget with the given index.
+    pub fn send(&self, widget_idx: usize, event: Event) {
+        trace!("Sending event to widget {}: {:?}", widget_idx, event);
+        if let Some(Some(sender)) = self.0.borrow().get(widget_idx) {
+            // Widgets which aren't interested in events drop their end of the
+            // channel, so we ignore any errors.
+            let _ = sender.unbounded_send(event);
+        }
+    }
+}
+
+enum WidgetState {
+    /// The widget's stream needs to be (re)created.
+    Starting,
+    Running(WidgetStream),
+    /// The widget has failed, and will be restarted once the timer fires.
+    Failed(Pin<Box<Sleep>>),
+    /// The widget's stream has ended, so it will never update again.
+    Finished,
+}
+
+/// A widget whose stream is restarted if it fails.
+struct WidgetSlot {
+    idx: usize,
+    name: Option<String>,
+    factory: WidgetFactory,
+    state: WidgetState,
+    // The number of times the widget has failed in a row. A widget only stops
+    // failing in a row once it runs for longer than it last waited to restart.
+    failures: u32,
+    // When the widget's stream was last started.
+    started: Option<Instant>,
+    // The attributes of the text the widget last showed, used to show the
+    // error placeholder in a similar style.
+    attr: Option<Attributes>,
+}
+
+impl WidgetSlot {
+    /// Describes the widget for logging, by its index and any name.
+    fn describe(&self) -> String {
+        match self.name {
+            Some(ref name) => format!("{} ({:?})", self.idx, name),
+            None => self.idx.to_string(),
+        }
+    }
+
+    fn start(&mut self, event_senders: &EventSenders) -> Result<WidgetStream> {
+        let (sender, receiver) = mpsc::unbounded();
+        event_senders.0.borrow_mut()[self.idx] = Some(sender);
+        self.started = Some(Instant::now());
+        (self.factory)().stream_with_events(Box::pin(receiver))
+    }
+
+    fn fail(&mut self, cx: &mut Context<'_>, error: &Error) {
+        let ran_for = self.started.take().map(|started| started.elapsed());
+        if self.failures > 0
+            && ran_for.is_some_and(|ran_for| ran_for > restart_delay(self.failures - 1))
+        {
+            self.failures = 0;
+        }
+        let delay = restart_delay(self.failures);
+        error!(
+            "Widget {} failed, restarting in {}s: {}",
+            self.describe(),
+            delay.as_secs(),
+            error
+        );
+        self.failures += 1;
+
+        let mut sleep = Box::pin(time::sleep(delay));
+        // Poll the timer, so that we're woken when it fires.
+        let _ = sleep.as_mut().poll(cx);
+        self.state = WidgetState::Failed(sleep);
+    }
+
+    /// Returns the widget's new texts, if it has any. If the widget fails, this
+    /// returns the error placeholder.
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+        event_senders: &EventSenders,
+        placeholder: &ErrorPlaceholder,
+    ) -> Option<Vec<Text>> {
+        loop {
+            let result = match self.state {
+                WidgetState::Starting => self.start(event_senders).map(WidgetState::Running),
+                WidgetState::Running(ref mut stream) => match stream.as_mut().poll_next(cx) {
+                    Poll::Ready(Some(Ok(texts))) => {
+                        if let Some(text) = texts.first() {
+                            self.attr = Some(text.attr.clone());
+                        }
+                        return Some(texts);
+                    }
+                    Poll::Ready(Some(Err(e))) => Err(e),
+                    Poll::Ready(None) => {
+                        debug!("Widget {} finished", self.describe());
+                        Ok(WidgetState::Finished)
+                    }
+                    Poll::Pending => return None,
+                },
+                WidgetState::Failed(ref mut sleep) => match sleep.as_mut().poll(cx) {
+                    Poll::Ready(()) => Ok(WidgetState::Starting),
+                    Poll::Pending => return None,
+                },
+                WidgetState::Finished => return None,
+            };
+
+            match result {
+                Ok(state) => self.state = state,
+                Err(e) => {
+                    self.fail(cx, &e);
+                    return Some(placeholder.texts(self.attr.as_ref()));
+                }
+            }
+        }
+    }
+}
+
 pub(crate) struct WidgetList {
-    vec: Vec<Box<dyn Stream<Item = Vec<Text>, Error = Error>>>,
+    slots: Vec<WidgetSlot>,
+    placeholder: ErrorPlaceholder,
+    event_senders: EventSenders,
 }
 
 impl WidgetList {
-    pub fn new(widgets: Vec<Box<dyn Widget>>) -> Result<WidgetList> {
-        Ok(WidgetList {
-            vec: widgets
-                .into_iter()
-                .map(|w| w.stream())
-                .collect::<Result<Vec<_>>>()?,
-        })
+    pub fn new(
+        widgets: Vec<(Option<String>, WidgetFactory)>,
+        placeholder: ErrorPlaceholder,
+    ) -> WidgetList {
+        let event_senders = EventSenders(Rc::new(RefCell::new(vec![None; widgets.len()])));
+        let slots = widgets
+            .into_iter()
+            .enumerate()
+            .map(|(idx, (name, factory))| WidgetSlot {
+                idx,
+                name,
+                factory,
+                state: WidgetState::Starting,
+                failures: 0,
+                started: None,
+                attr: None,
+            })
+            .collect();
+        WidgetList {
+            slots,
+            placeholder,
+            event_senders,
+        }
+    }
+
+    /// Returns the senders used to send events to each widget.
+    pub fn event_senders(&self) -> EventSenders {
+        self.event_senders.clone()
+    }
+
+    /// Returns the name the widget with the given index was added with.
+    pub fn name(&self, widget_idx: usize) -> Option<&str> {
+        self.slots.get(widget_idx)?.name.as_deref()
+    }
+
+    /// Returns the index of the widget with the given name or, failing that,
+    /// the widget whose index is given as a string.
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.slots
+            .iter()
+            .position(|slot| slot.name.as_deref() == Some(name))
+            .or_else(|| name.parse().ok().filter(|&idx| idx < self.slots.len()))
+    }
+
+    /// Restarts the widget with the given index, as if it had just been
+    /// added. It's started the next time the list is polled.
+    pub fn restart(&mut self, widget_idx: usize) {
+        let slot = &mut self.slots[widget_idx];
+        debug!("Restarting widget {}", slot.describe());
+        slot.state = WidgetState::Starting;
+        slot.failures = 0;
     }
 }
 
 impl Stream for WidgetList {
     type Item = Vec<Option<Vec<Text>>>;
-    type Error = Error;
-
-    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
-        let mut all_texts: Vec<Option<Vec<Text>>> = Vec::new();
-        for stream in &mut self.vec {
-            match stream.poll() {
-                Ok(Async::Ready(Some(widget_texts))) => all_texts.push(Some(widget_texts)),
-                Ok(_) => all_texts.push(None),
-                Err(e) => return Err(e),
-            }
-        }
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let WidgetList {
+            slots,
+            placeholder,
+            event_senders,
+        } = self.get_mut();
+        let all_texts: Vec<Option<Vec<Text>>> = slots
+            .iter_mut()
+            .map(|slot| slot.poll(cx, event_senders, placeholder))
+            .collect();
 
         if !all_texts.iter().any(|o| o.is_some()) {
-            return Ok(Async::NotReady);
+            return Poll::Pending;
+        }
+
+        Poll::Ready(Some(all_texts))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use failure::format_err;
+    use futures::{future, stream, StreamExt};
+
+    use super::*;
+
+    /// A widget which shows `ok` and then fails after `lifetime`, or fails to
+    /// start at all if it has no lifetime.
+    #[derive(Clone)]
+    struct Flaky {
+        lifetime: Option<Duration>,
+    }
+
+    impl Widget for Flaky {
+        fn stream(self: Box<Self>) -> Result<WidgetStream> {
+            let lifetime = match self.lifetime {
+                Some(lifetime) => lifetime,
+                None => return Err(format_err!("failed to start")),
+            };
+            let attr = Attributes {
+                font: Font::new("monospace"),
+                fg_color: Color::white(),
+                bg_color: None,
+                padding: Padding::new(0.0, 0.0, 0.0, 0.0),
+            };
+            let texts = stream::once(future::ready(Ok(vec![Text::new(attr, "ok")])));
+            let failure = stream::once(async move {
+                time::sleep(lifetime).await;
+                Err(format_err!("failed"))
+            });
+            Ok(Box::pin(texts.chain(failure)))
         }
+    }
+
+    fn widget_list(widgets: Vec<(Option<&str>, Flaky)>) -> WidgetList {
+        let widgets = widgets
+            .into_iter()
+            .map(|(name, widget)| {
+                let factory: WidgetFactory = Box::new(move || Box::new(widget.clone()));
+                (name.map(str::to_owned), factory)
+            })
+            .collect();
+        WidgetList::new(widgets, ErrorPlaceholder::default())
+    }
+
+    /// Returns the texts of the only widget in the list, and the number of
+    /// seconds since `since` at which they were shown.
+    async fn next_texts(list: &mut WidgetList, since: Instant) -> (String, u64) {
+        let texts = list.next().await.unwrap().remove(0).unwrap();
+        let text = texts.iter().map(|text| text.text.as_str()).collect();
+        (text, since.elapsed().as_secs())
+    }
+
+    #[test]
+    fn restart_delay_doubles_up_to_limit() {
+        let delays: Vec<u64> = (0..11).map(|n| restart_delay(n).as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16, 32, 64, 128, 256, 300, 300]);
+        assert_eq!(restart_delay(u32::MAX), MAX_RESTART_DELAY);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failing_widget_backs_off() {
+        let mut list = widget_list(vec![(None, Flaky { lifetime: None })]);
+        let start = Instant::now();
+        let mut times = Vec::new();
+        for _ in 0..11 {
+            let (text, time) = next_texts(&mut list, start).await;
+            assert_eq!(text, "!");
+            times.push(time);
+        }
+        assert_eq!(times, [0, 1, 3, 7, 15, 31, 63, 127, 255, 511, 811]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn widget_failing_after_texts_backs_off() {
+        let lifetime = Some(Duration::from_millis(100));
+        let mut list = widget_list(vec![(None, Flaky { lifetime })]);
+        let start = Instant::now();
+        let mut shown = Vec::new();
+        for _ in 0..8 {
+            shown.push(next_texts(&mut list, start).await);
+        }
+        let shown: Vec<(&str, u64)> = shown
+            .iter()
+            .map(|(text, time)| (text.as_str(), *time))
+            .collect();
+        assert_eq!(
+            shown,
+            [
+                ("ok", 0),
+                ("!", 0),
+                ("ok", 1),
+                ("!", 1),
+                ("ok", 3),
+                ("!", 3),
+                ("ok", 7),
+                ("!", 7)
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn long_running_widget_restarts_quickly() {
+        let lifetime = Some(Duration::from_secs(600));
+        let mut list = widget_list(vec![(None, Flaky { lifetime })]);
+        let start = Instant::now();
+        let mut shown = Vec::new();
+        for _ in 0..6 {
+            shown.push(next_texts(&mut list, start).await);
+        }
+        let shown: Vec<(&str, u64)> = shown
+            .iter()
+            .map(|(text, time)| (text.as_str(), *time))
+            .collect();
+        assert_eq!(
+            shown,
+            [
+                ("ok", 0),
+                ("!", 600),
+                ("ok", 601),
+                ("!", 1201),
+                ("ok", 1202),
+                ("!", 1802)
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn restart_starts_widget_straight_away() {
+        let mut list = widget_list(vec![(None, Flaky { lifetime: None })]);
+        let start = Instant::now();
+        for _ in 0..4 {
+            next_texts(&mut list, start).await;
+        }
+        list.restart(0);
+        assert_eq!(next_texts(&mut list, start).await, ("!".to_owned(), 7));
+        // Restarting also forgets its earlier failures.
+        assert_eq!(next_texts(&mut list, start).await, ("!".to_owned(), 8));
+    }
 
-        Ok(Async::Ready(Some(all_texts)))
+    #[test]
+    fn finds_widgets_by_name_or_index() {
+        let list = widget_list(vec![
+            (Some("clock"), Flaky { lifetime: None }),
+            (None, Flaky { lifetime: None }),
+        ]);
+        assert_eq!(list.find("clock"), Some(0));
+        assert_eq!(list.find("1"), Some(1));
+        assert_eq!(list.find("2"), None);
+        assert_eq!(list.find("volume"), None);
+        assert_eq!(list.name(0), Some("clock"));
+        assert_eq!(list.name(1), None);
     }
 }
@@ -1,7 +1,8 @@
-use tokio_core::reactor::Handle;
 use xcb_util::ewmh;
 
+use super::{Button, Event};
 use crate::text::{Attributes, Text};
+use crate::x11::LazyConnection;
 use crate::{Cnx, Result};
 
 /// Shows the WM's workspaces/groups, highlighting whichever is currently
@@ -11,11 +12,20 @@ use crate::{Cnx, Result};
 /// `_NET_NUMBER_OF_DESKTOPS` and `_NET_DESKTOP_NAMES` and
 /// `_NET_CURRENT_DESKTOP` properties. The active workspace is highlighted.
 ///
+/// Clicking on a workspace switches to it, and scrolling over the widget
+/// switches to the previous (scrolling up) or next (scrolling down) workspace.
+/// By default, scrolling stops at the first and last workspaces; use
+/// [`with_wrap_around()`] to cycle around instead.
+///
+/// [`with_wrap_around()`]: #method.with_wrap_around
+///
 /// [`EWMH`]: https://specifications.freedesktop.org/wm-spec/wm-spec-latest.html
+#[derive(Clone)]
 pub struct Pager {
-    tokio_handle: Handle,
+    conn: LazyConnection,
     active_attr: Attributes,
     inactive_attr: Attributes,
+    wrap_around: bool,
 }
 
 impl Pager {
@@ -26,10 +36,10 @@ impl Pager {
     ///  all inactive groups, and the `active_attr` [`Attributes`] for the
     ///  currently active group.
     ///
-    ///  The [`Cnx`] instance is borrowed during construction in order to get
-    ///  access to handles of its event loop. However, it is not borrowed for
-    ///  the lifetime of the widget. See the [`cnx_add_widget!()`] for more
-    ///  discussion about the lifetime of the borrow.
+    ///  The [`Cnx`] instance is borrowed during construction. However, it is
+    ///  not borrowed for the lifetime of the widget. See the
+    ///  [`cnx_add_widget!()`] for more discussion about the lifetime of the
+    ///  borrow.
     ///
     /// [`Attributes`]: ../text/struct.Attributes.html
     /// [`Cnx`]: ../struct.Cnx.html
@@ -63,12 +73,48 @@ impl Pager {
     /// ```
     pub fn new(cnx: &Cnx, active_attr: Attributes, inactive_attr: Attributes) -> Pager {
         Pager {
-            tokio_handle: cnx.handle(),
+            conn: cnx.x_connection(),
             active_attr,
             inactive_attr,
+            wrap_around: false,
         }
     }
 
+    /// Sets whether scrolling past the first or last workspace cycles around
+    /// to the other end.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate cnx;
+    /// #
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// # let attr = Attributes {
+    /// #     font: Font::new("SourceCodePro 21"),
+    /// #     fg_color: Color::white(),
+    /// #     bg_color: None,
+    /// #     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    /// # };
+    /// # let active_attr = attr.clone();
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx_add_widget!(
+    ///     cnx,
+    ///     Pager::new(&cnx, active_attr, attr.clone()).with_wrap_around(true)
+    /// );
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn with_wrap_around(mut self, wrap_around: bool) -> Pager {
+        self.wrap_around = wrap_around;
+        self
+    }
+
     fn on_change(&self, conn: &ewmh::Connection, screen_idx: i32) -> Result<Vec<Text>> {
         let number = ewmh::get_number_of_desktops(conn, screen_idx)
             .get_reply()
@@ -100,18 +146,98 @@ impl Pager {
                 } else {
                     self.inactive_attr.clone()
                 };
-                Text {
-                    attr,
-                    text: name.to_owned(),
-                    stretch: false,
-                }
+                Text::new(attr, name.to_owned())
             })
             .collect())
     }
+
+    fn on_event(&self, conn: &ewmh::Connection, screen_idx: i32, event: &Event) -> Result<()> {
+        let click = match *event {
+            Event::ButtonPress(ref click) => click,
+            _ => return Ok(()),
+        };
+
+        let number = ewmh::get_number_of_desktops(conn, screen_idx)
+            .get_reply()
+            .unwrap_or(0) as usize;
+        let current = ewmh::get_current_desktop(conn, screen_idx)
+            .get_reply()
+            .unwrap_or(0) as usize;
+        if let Some(desktop) =
+            target_desktop(click.button, click.index, current, number, self.wrap_around)
+        {
+            // Ask the WM to switch desktop, as described in EWMH.
+            ewmh::request_change_current_desktop(
+                conn,
+                screen_idx,
+                desktop as u32,
+                xcb::CURRENT_TIME,
+            );
+            conn.flush();
+        }
+
+        Ok(())
+    }
 }
 
-x_properties_widget!(Pager, tokio_handle, on_change; [
+x_properties_widget!(Pager, conn, on_change, on_event; [
     NUMBER_OF_DESKTOPS,
     CURRENT_DESKTOP,
     DESKTOP_NAMES
 ]);
+
+/// Returns the desktop to switch to when `button` is pressed over the Text at
+/// `index`, given the `current` desktop and the `number` of desktops.
+///
+/// Returns `None` if the desktop shouldn't change, either because the button
+/// doesn't do anything there or because it would pick the current desktop or
+/// one which doesn't exist.
+fn target_desktop(
+    button: Button,
+    index: usize,
+    current: usize,
+    number: usize,
+    wrap_around: bool,
+) -> Option<usize> {
+    if number == 0 {
+        return None;
+    }
+    let desktop = match button {
+        // We show one Text per desktop, in order.
+        Button::Left => index,
+        Button::ScrollUp if current > 0 => current - 1,
+        Button::ScrollUp if wrap_around => number - 1,
+        Button::ScrollDown if current + 1 < number => current + 1,
+        Button::ScrollDown if wrap_around => 0,
+        _ => return None,
+    };
+    Some(desktop).filter(|&desktop| desktop != current && desktop < number)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn clicks_switch_to_desktop() {
+        assert_eq!(target_desktop(Button::Left, 2, 0, 4, false), Some(2));
+        // Clicking the current desktop, or a Text past the last desktop,
+        // does nothing.
+        assert_eq!(target_desktop(Button::Left, 0, 0, 4, false), None);
+        assert_eq!(target_desktop(Button::Left, 4, 0, 4, false), None);
+        assert_eq!(target_desktop(Button::Right, 2, 0, 4, false), None);
+        assert_eq!(target_desktop(Button::Left, 0, 0, 0, false), None);
+    }
+
+    #[test]
+    fn scrolling_stops_at_ends_unless_wrapping() {
+        assert_eq!(target_desktop(Button::ScrollUp, 0, 2, 4, false), Some(1));
+        assert_eq!(target_desktop(Button::ScrollDown, 0, 2, 4, false), Some(3));
+        assert_eq!(target_desktop(Button::ScrollUp, 0, 0, 4, false), None);
+        assert_eq!(target_desktop(Button::ScrollDown, 0, 3, 4, false), None);
+        assert_eq!(target_desktop(Button::ScrollUp, 0, 0, 4, true), Some(3));
+        assert_eq!(target_desktop(Button::ScrollDown, 0, 3, 4, true), Some(0));
+        // There's nowhere to wrap around to with a single desktop.
+        assert_eq!(target_desktop(Button::ScrollDown, 0, 0, 1, true), None);
+    }
+}
@@ -1,59 +1,192 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::process::Command;
 use std::time::Duration;
 
 use failure::ResultExt;
-use lazy_static::lazy_static;
-use regex::Regex;
-use tokio_timer::Timer;
 
 use crate::text::{Attributes, Text};
 use crate::{Cnx, Result};
 
-#[derive(Debug, PartialEq)]
-struct Value<'a> {
-    temp: &'a str,
-    units: &'a str,
+mod hwmon;
+mod lm_sensors;
+
+/// The kinds of reading [`Sensors`] can show.
+///
+/// [`Sensors`]: struct.Sensors.html
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum SensorKind {
+    /// A temperature, in degrees Celsius (or Fahrenheit, if `sensors` was
+    /// configured to show them).
+    Temperature,
+    /// A fan speed, in RPM.
+    Fan,
+    /// A voltage, in volts.
+    Voltage,
+    /// A power draw, in watts.
+    Power,
+    /// A current, in amperes.
+    Current,
 }
 
-/// Parses the output of the `sensors` executable from `lm_sensors`.
-fn parse_sensors_output(output: &str) -> Result<HashMap<&str, Value<'_>>> {
-    lazy_static! {
-        static ref RE: Regex = Regex::new(
-            // Note: we ignore + but capture -
-            r"\n(?P<name>[\w ]+):\s+\+?(?P<temp>-?\d+\.\d+).(?P<units>[C|F])"
-        ).expect("Failed to compile regex for parsing sensors output");
+impl SensorKind {
+    /// All of the kinds of reading, which are shown by default.
+    pub const ALL: [SensorKind; 5] = [
+        SensorKind::Temperature,
+        SensorKind::Fan,
+        SensorKind::Voltage,
+        SensorKind::Power,
+        SensorKind::Current,
+    ];
+
+    /// Returns the kind, unit and scale (the number its raw value must be
+    /// divided by to give that unit) of the hwmon sensor with the given name,
+    /// such as `temp1` or `fan2`.
+    fn from_sensor_name(name: &str) -> Option<(SensorKind, &'static str, f64)> {
+        let prefix = name.trim_end_matches(|c: char| c.is_ascii_digit());
+        if prefix.len() == name.len() {
+            return None;
+        }
+        match prefix {
+            "temp" => Some((SensorKind::Temperature, "°C", 1000.0)),
+            "fan" => Some((SensorKind::Fan, "RPM", 1.0)),
+            "in" => Some((SensorKind::Voltage, "V", 1000.0)),
+            "power" => Some((SensorKind::Power, "W", 1_000_000.0)),
+            "curr" => Some((SensorKind::Current, "A", 1000.0)),
+            _ => None,
+        }
     }
 
-    let mut map = HashMap::new();
-    for mat in RE.captures_iter(output) {
-        // These .unwraps() are harmless. If we have a match, we have these groups.
-        map.insert(
-            mat.name("name").unwrap().as_str(),
-            Value {
-                temp: mat.name("temp").unwrap().as_str(),
-                units: mat.name("units").unwrap().as_str(),
-            },
-        );
+    fn default_format(self) -> &'static str {
+        match self {
+            SensorKind::Temperature | SensorKind::Power => "{value:.1}{unit}",
+            SensorKind::Fan => "{value:.0} {unit}",
+            SensorKind::Voltage | SensorKind::Current => "{value:.2}{unit}",
+        }
+    }
+}
+
+/// A single reading from a sensor.
+#[derive(Clone, Debug, PartialEq)]
+struct Reading {
+    /// The names the chip can be referred to by, e.g. `coretemp` and
+    /// `hwmon1`.
+    chips: Vec<String>,
+    /// The names the sensor can be referred to by, e.g. `Core 0` and `temp2`.
+    labels: Vec<String>,
+    kind: SensorKind,
+    value: f64,
+    unit: String,
+}
+
+impl Reading {
+    /// Returns whether `name` refers to this reading. It may be given as
+    /// `chip/label`, or just as `label` to match that label on any chip.
+    fn matches(&self, name: &str) -> bool {
+        match name.find('/') {
+            Some(idx) => {
+                let (chip, label) = (&name[..idx], &name[idx + 1..]);
+                self.chips.iter().any(|c| c == chip) && self.labels.iter().any(|l| l == label)
+            }
+            None => self.labels.iter().any(|l| l == name),
+        }
+    }
+}
+
+/// Formats `reading` using `format`, replacing `{value}` with its value,
+/// `{unit}` with its unit and `{label}` with its label. The value's precision
+/// may be given as e.g. `{value:.1}`; otherwise it's shown as is.
+fn format_reading(format: &str, reading: &Reading) -> String {
+    let mut formatted = String::new();
+    let mut rest = format;
+    while let Some(open) = rest.find('{') {
+        formatted.push_str(&rest[..open]);
+        rest = &rest[open..];
+        let close = match rest.find('}') {
+            Some(close) => close,
+            None => break,
+        };
+        let placeholder = &rest[1..close];
+        let (name, precision) = match placeholder.find(":.") {
+            Some(idx) => (&placeholder[..idx], placeholder[idx + 2..].parse().ok()),
+            None => (placeholder, None),
+        };
+        match (name, precision) {
+            ("value", Some(precision)) => {
+                formatted.push_str(&format!("{:.*}", precision, reading.value))
+            }
+            ("value", None) => formatted.push_str(&reading.value.to_string()),
+            ("unit", None) => formatted.push_str(&reading.unit),
+            ("label", None) => {
+                formatted.push_str(reading.labels.first().map_or("", String::as_str))
+            }
+            _ => formatted.push_str(&rest[..=close]),
+        }
+        rest = &rest[close + 1..];
     }
+    formatted.push_str(rest);
+    formatted
+}
 
-    Ok(map)
+/// Where [`Sensors`] reads its sensors from.
+///
+/// [`Sensors`]: struct.Sensors.html
+#[derive(Clone, Debug, PartialEq)]
+pub enum SensorSource {
+    /// Read the kernel's hwmon and thermal zone interfaces directly from the
+    /// sysfs mounted at the given path, which is usually `/sys`.
+    Sysfs(PathBuf),
+    /// Run the `sensors` executable from [`lm_sensors`] and parse its output.
+    /// A chip may be named by its full name (e.g. `coretemp-isa-0000`) or the
+    /// part before the first `-` (e.g. `coretemp`).
+    ///
+    /// [`lm_sensors`]: https://wiki.archlinux.org/index.php/lm_sensors
+    LmSensors,
+    /// Run `sensors -j` and parse its JSON output, which doesn't depend on the
+    /// locale. This needs `lm_sensors` 3.5 or later. Chips are named as for
+    /// [`LmSensors`], and a sensor may also be named by its feature (e.g.
+    /// `temp2`).
+    ///
+    /// [`LmSensors`]: #variant.LmSensors
+    LmSensorsJson,
+}
+
+impl Default for SensorSource {
+    fn default() -> SensorSource {
+        SensorSource::Sysfs(PathBuf::from("/sys"))
+    }
 }
 
-/// Shows the temperature from one or more sensors.
+/// Shows the readings from one or more sensors.
 ///
-/// This widget shows the temperature reported by one or more sensors from the
-/// output of the `sensors` command, which is part of the [`lm_sensors`]
-/// package.
+/// This widget shows the temperature, fan speed, voltage, power or current
+/// reported by one or more sensors. By default, readings are read from the
+/// kernel's [hwmon] chips (in `/sys/class/hwmon/`) and thermal zones (in
+/// `/sys/class/thermal/`). Use [`with_source()`] to read them from elsewhere.
 ///
-/// It expects the `sensors` executable to be available in the `PATH`.
+/// Sensors are given as `chip/label`, such as `coretemp/Core 0`, or just as
+/// `label` to use the first chip with that label. A chip may be named by its
+/// `name` (e.g. `coretemp`) or its directory (e.g. `hwmon1`), and a sensor by
+/// its label (e.g. `Core 0`) or its file's name (e.g. `temp2` or `fan1`).
+/// Thermal zones are on the chip `thermal`, labelled by their type (e.g.
+/// `x86_pkg_temp`) or directory (e.g. `thermal_zone0`). Sensors which can't be
+/// found are shown as `?`.
 ///
-/// [`lm_sensors`]: https://wiki.archlinux.org/index.php/lm_sensors
+/// Use [`with_kinds()`] to only match some kinds of reading, and
+/// [`with_format()`] to change how each kind is shown.
+///
+/// [hwmon]: https://www.kernel.org/doc/html/latest/hwmon/sysfs-interface.html
+/// [`with_source()`]: #method.with_source
+/// [`with_kinds()`]: #method.with_kinds
+/// [`with_format()`]: #method.with_format
+#[derive(Clone)]
 pub struct Sensors {
-    timer: Timer,
     update_interval: Duration,
     attr: Attributes,
     sensors: Vec<String>,
+    source: SensorSource,
+    kinds: Vec<SensorKind>,
+    formats: HashMap<SensorKind, String>,
 }
 
 impl Sensors {
@@ -63,12 +196,12 @@ impl Sensors {
     /// given [`Attributes`].
     ///
     /// A list of sensor names should be passed as the `sensors` argument. (You
-    /// can discover the names by running the `sensors` utility in a terminal).
+    /// can discover the labels by running the `sensors` utility in a terminal,
+    /// or by looking in `/sys/class/hwmon/`).
     ///
-    /// The [`Cnx`] instance is borrowed during construction in order to get
-    /// access to handles of its event loop. However, it is not borrowed for the
-    /// lifetime of the widget. See the [`cnx_add_widget!()`] for more discussion
-    /// about the lifetime of the borrow.
+    /// The [`Cnx`] instance is borrowed during construction. However, it is not
+    /// borrowed for the lifetime of the widget. See the [`cnx_add_widget!()`]
+    /// for more discussion about the lifetime of the borrow.
     ///
     /// [`Attributes`]: ../text/struct.Attributes.html
     /// [`Cnx`]: ../struct.Cnx.html
@@ -101,90 +234,211 @@ impl Sensors {
     /// # }
     /// # fn main() { run().unwrap(); }
     /// ```
-    pub fn new<S: Into<String>>(cnx: &Cnx, attr: Attributes, sensors: Vec<S>) -> Sensors {
+    pub fn new<S: Into<String>>(_cnx: &Cnx, attr: Attributes, sensors: Vec<S>) -> Sensors {
         Sensors {
-            timer: cnx.timer(),
             update_interval: Duration::from_secs(60),
             attr,
             sensors: sensors.into_iter().map(Into::into).collect(),
+            source: SensorSource::default(),
+            kinds: SensorKind::ALL.to_vec(),
+            formats: HashMap::new(),
         }
     }
 
+    /// Sets where readings are read from.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate cnx;
+    /// #
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// # let attr = Attributes {
+    /// #     font: Font::new("SourceCodePro 21"),
+    /// #     fg_color: Color::white(),
+    /// #     bg_color: None,
+    /// #     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    /// # };
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// let sensors = Sensors::new(&cnx, attr.clone(), vec!["Core 0"])
+    ///     .with_source(SensorSource::LmSensors);
+    /// cnx_add_widget!(cnx, sensors);
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn with_source(mut self, source: SensorSource) -> Sensors {
+        self.source = source;
+        self
+    }
+
+    /// Sets which kinds of reading sensor names are matched against. By
+    /// default, every kind is.
+    ///
+    /// This is useful when a chip gives the same label to, say, a temperature
+    /// and a fan.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate cnx;
+    /// #
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// # let attr = Attributes {
+    /// #     font: Font::new("SourceCodePro 21"),
+    /// #     fg_color: Color::white(),
+    /// #     bg_color: None,
+    /// #     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    /// # };
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// let fans = Sensors::new(&cnx, attr.clone(), vec!["thinkpad/CPU"])
+    ///     .with_kinds(vec![SensorKind::Fan]);
+    /// cnx_add_widget!(cnx, fans);
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn with_kinds<I: IntoIterator<Item = SensorKind>>(mut self, kinds: I) -> Sensors {
+        self.kinds = kinds.into_iter().collect();
+        self
+    }
+
+    /// Sets how readings of the given kind are shown.
+    ///
+    /// In `format`, `{value}` is replaced with the reading's value, `{unit}`
+    /// with its unit (such as `°C` or `RPM`) and `{label}` with the sensor's
+    /// label. The number of decimal places may be given as e.g. `{value:.1}`.
+    /// The defaults are `{value:.1}{unit}` for temperatures and power,
+    /// `{value:.0} {unit}` for fans and `{value:.2}{unit}` for voltages and
+    /// currents.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate cnx;
+    /// #
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// # let attr = Attributes {
+    /// #     font: Font::new("SourceCodePro 21"),
+    /// #     fg_color: Color::white(),
+    /// #     bg_color: None,
+    /// #     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    /// # };
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// let sensors = Sensors::new(&cnx, attr.clone(), vec!["Core 0", "fan1"])
+    ///     .with_format(SensorKind::Temperature, "{value:.0}°")
+    ///     .with_format(SensorKind::Fan, "{label}: {value:.0}");
+    /// cnx_add_widget!(cnx, sensors);
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn with_format<S: Into<String>>(mut self, kind: SensorKind, format: S) -> Sensors {
+        self.formats.insert(kind, format.into());
+        self
+    }
+
+    fn read(&self) -> Result<Vec<Reading>> {
+        let readings = match self.source {
+            SensorSource::Sysfs(ref root) => hwmon::read_sensors(root),
+            SensorSource::LmSensors => {
+                let output = run_sensors(&[])?;
+                lm_sensors::parse_sensors_output(&output)
+            }
+            SensorSource::LmSensorsJson => {
+                let output = run_sensors(&["-j"])?;
+                lm_sensors::parse_json_output(&output)
+                    .context("Failed to parse `sensors -j` output")?
+            }
+        };
+        Ok(readings)
+    }
+
     fn tick(&self) -> Result<Vec<Text>> {
-        let output = Command::new("sensors")
-            .output()
-            .context("Failed to run `sensors`")?;
-        let string = String::from_utf8(output.stdout).context("Invalid UTF-8 in sensors output")?;
-        let parsed = parse_sensors_output(&string).context("Failed to parse `sensors` output")?;
-        self.sensors
+        let readings = self.read()?;
+        Ok(self
+            .sensors
             .iter()
             .map(|sensor_name| {
-                let text = parsed
-                    .get::<str>(sensor_name)
-                    .map_or("?".to_owned(), |&Value { temp, units }| {
-                        format!("{}°{}", temp, units)
+                let text = readings
+                    .iter()
+                    .filter(|reading| self.kinds.contains(&reading.kind))
+                    .find(|reading| reading.matches(sensor_name))
+                    .map_or("?".to_owned(), |reading| {
+                        let format = self
+                            .formats
+                            .get(&reading.kind)
+                            .map_or(reading.kind.default_format(), String::as_str);
+                        format_reading(format, reading)
                     });
-                Ok(Text {
-                    attr: self.attr.clone(),
-                    text,
-                    stretch: false,
-                })
+                Text::new(self.attr.clone(), text)
             })
-            .collect()
+            .collect())
     }
 }
 
-timer_widget!(Sensors, timer, update_interval, tick);
+/// Runs the `sensors` executable with the given arguments, returning its
+/// output.
+fn run_sensors(args: &[&str]) -> Result<String> {
+    let output = Command::new("sensors")
+        .args(args)
+        .output()
+        .context("Failed to run `sensors`")?;
+    Ok(String::from_utf8(output.stdout).context("Invalid UTF-8 in sensors output")?)
+}
+
+timer_widget!(Sensors, update_interval, tick);
 
 #[cfg(test)]
 mod test {
-    use super::{parse_sensors_output, Value};
+    use super::*;
+
+    fn reading(kind: SensorKind, value: f64, unit: &str) -> Reading {
+        Reading {
+            chips: vec!["thinkpad".to_owned()],
+            labels: vec!["CPU".to_owned(), "fan1".to_owned()],
+            kind,
+            value,
+            unit: unit.to_owned(),
+        }
+    }
 
     #[test]
-    fn works() {
-        let output = r#"applesmc-isa-0300
-Adapter: ISA adapter
-Right Side  :    0 RPM  (min = 2000 RPM, max = 6199 RPM)
-Ts1S:         -127.0 C
-Ts2S:          +34.0 F
-
-coretemp-isa-0000
-Adapter: ISA adapter
-Package id 0:  +58.0 C  (high = +105.0 C, crit = +105.0 C)
-Core 0:        +53.0 C  (high = +105.0 C, crit = +105.0 C)
-Core 1:        +58.0 C  (high = +105.0 C, crit = +105.0 C)
-"#;
-
-        let parsed = parse_sensors_output(output).unwrap();
+    fn formats_readings() {
+        let temp = reading(SensorKind::Temperature, 53.26, "°C");
+        let fan = reading(SensorKind::Fan, 2045.0, "RPM");
+        let volts = reading(SensorKind::Voltage, 12.1, "V");
         assert_eq!(
-            parsed.get("Core 0"),
-            Some(&Value {
-                temp: "53.0",
-                units: "C",
-            })
+            format_reading(SensorKind::Temperature.default_format(), &temp),
+            "53.3°C"
         );
         assert_eq!(
-            parsed.get("Core 1"),
-            Some(&Value {
-                temp: "58.0",
-                units: "C",
-            })
+            format_reading(SensorKind::Fan.default_format(), &fan),
+            "2045 RPM"
         );
         assert_eq!(
-            parsed.get("Ts1S"),
-            Some(&Value {
-                temp: "-127.0",
-                units: "C",
-            })
+            format_reading(SensorKind::Voltage.default_format(), &volts),
+            "12.10V"
         );
+        assert_eq!(format_reading("{label}: {value}", &fan), "CPU: 2045");
         assert_eq!(
-            parsed.get("Ts2S"),
-            Some(&Value {
-                temp: "34.0",
-                units: "F",
-            })
+            format_reading("{value:.0}° {other} {", &temp),
+            "53° {other} {"
         );
-
-        assert_eq!(parsed.len(), 5);
     }
 }
@@ -0,0 +1,209 @@
+//! Readings from the kernel's [hwmon] and thermal zone interfaces in sysfs.
+//!
+//! Each hwmon chip has a directory `class/hwmon/hwmonN` holding its `name` and
+//! an input file for each sensor, along with an optional label: `tempN_input`
+//! in millidegrees Celsius, `fanN_input` in RPM, `inN_input` in millivolts,
+//! `powerN_input` (or `powerN_average`) in microwatts and `currN_input` in
+//! milliamperes. Each thermal zone has a directory
+//! `class/thermal/thermal_zoneN` holding its `type` and `temp`.
+//!
+//! [hwmon]: https://www.kernel.org/doc/html/latest/hwmon/sysfs-interface.html
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{Reading, SensorKind};
+
+/// Reads every sensor under the sysfs mounted at `root` (usually `/sys`).
+/// Sensors which can't be read are skipped.
+pub(super) fn read_sensors(root: &Path) -> Vec<Reading> {
+    let mut readings = Vec::new();
+
+    for (dir_name, dir) in numbered_entries(&root.join("class/hwmon"), "hwmon") {
+        let mut chips = Vec::new();
+        // Older drivers keep their files in the device's directory instead.
+        let dir = if dir.join("name").exists() {
+            dir
+        } else {
+            dir.join("device")
+        };
+        if let Some(name) = read_trimmed(&dir.join("name")) {
+            chips.push(name);
+        }
+        chips.push(dir_name);
+
+        for (input, path) in sensor_inputs(&dir) {
+            let sensor = &input[..input.rfind('_').unwrap_or(input.len())];
+            let (kind, unit, scale) = match SensorKind::from_sensor_name(sensor) {
+                Some(kind) => kind,
+                None => continue,
+            };
+            let raw = match read_number(&path) {
+                Some(value) => value,
+                None => continue,
+            };
+            let mut labels = Vec::new();
+            if let Some(label) = read_trimmed(&dir.join(format!("{}_label", sensor))) {
+                labels.push(label);
+            }
+            labels.push(sensor.to_owned());
+            readings.push(Reading {
+                chips: chips.clone(),
+                labels,
+                kind,
+                value: raw / scale,
+                unit: unit.to_owned(),
+            });
+        }
+    }
+
+    for (dir_name, dir) in numbered_entries(&root.join("class/thermal"), "thermal_zone") {
+        let millidegrees = match read_number(&dir.join("temp")) {
+            Some(value) => value,
+            None => continue,
+        };
+        let mut labels = Vec::new();
+        if let Some(kind) = read_trimmed(&dir.join("type")) {
+            labels.push(kind);
+        }
+        labels.push(dir_name);
+        readings.push(Reading {
+            chips: vec!["thermal".to_owned()],
+            labels,
+            kind: SensorKind::Temperature,
+            value: millidegrees / 1000.0,
+            unit: "°C".to_owned(),
+        });
+    }
+
+    readings
+}
+
+/// Returns the input files of the sensors in `dir`, such as `temp1_input`,
+/// ordered by their kind and number. Power sensors which only report an
+/// average use their `powerN_average` file instead.
+fn sensor_inputs(dir: &Path) -> Vec<(String, PathBuf)> {
+    let mut inputs = Vec::new();
+    for &prefix in &["temp", "fan", "in", "power", "curr"] {
+        let entries = numbered_entries(dir, prefix);
+        for (name, path) in &entries {
+            let is_input = name.ends_with("_input")
+                || name.strip_suffix("_average").is_some_and(|sensor| {
+                    !entries
+                        .iter()
+                        .any(|(other, _)| *other == format!("{}_input", sensor))
+                });
+            if is_input {
+                inputs.push((name.clone(), path.clone()));
+            }
+        }
+    }
+    inputs
+}
+
+/// Returns the entries of `dir` named `<prefix><number>...`, such as
+/// `hwmon2` or `temp1_input`, ordered by their number.
+fn numbered_entries(dir: &Path, prefix: &str) -> Vec<(String, PathBuf)> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut numbered: Vec<_> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name().into_string().ok()?;
+            let rest = name.strip_prefix(prefix)?;
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let number: u32 = rest[..digits].parse().ok()?;
+            Some((number, name.clone(), entry.path()))
+        })
+        .collect();
+    numbered.sort();
+    numbered
+        .into_iter()
+        .map(|(_, name, path)| (name, path))
+        .collect()
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    let contents = fs::read_to_string(path).ok()?;
+    let trimmed = contents.trim();
+    if trimmed.is_empty() {
+        None
+    } else {
+        Some(trimmed.to_owned())
+    }
+}
+
+fn read_number(path: &Path) -> Option<f64> {
+    read_trimmed(path)?.parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::process;
+
+    use super::*;
+
+    fn write(root: &Path, path: &str, contents: &str) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn reads_fake_sysfs() {
+        let root = env::temp_dir().join(format!("cnx-hwmon-test-{}", process::id()));
+        write(&root, "class/hwmon/hwmon0/name", "acpitz\n");
+        write(&root, "class/hwmon/hwmon0/temp1_input", "27800\n");
+        write(&root, "class/hwmon/hwmon1/name", "coretemp\n");
+        write(&root, "class/hwmon/hwmon1/temp1_input", "58000\n");
+        write(&root, "class/hwmon/hwmon1/temp1_label", "Package id 0\n");
+        write(&root, "class/hwmon/hwmon1/temp2_input", "53000\n");
+        write(&root, "class/hwmon/hwmon1/temp2_label", "Core 0\n");
+        write(&root, "class/hwmon/hwmon1/temp2_max", "105000\n");
+        // Unreadable sensors are skipped.
+        write(&root, "class/hwmon/hwmon1/temp3_input", "");
+        write(&root, "class/hwmon/hwmon2/name", "thinkpad\n");
+        write(&root, "class/hwmon/hwmon2/fan1_input", "2045\n");
+        write(&root, "class/hwmon/hwmon2/fan1_label", "CPU\n");
+        write(&root, "class/hwmon/hwmon2/in0_input", "12100\n");
+        write(&root, "class/hwmon/hwmon2/intrusion0_alarm", "0\n");
+        write(&root, "class/hwmon/hwmon2/power1_average", "15250000\n");
+        write(&root, "class/hwmon/hwmon2/power2_input", "5000000\n");
+        write(&root, "class/hwmon/hwmon2/power2_average", "4000000\n");
+        write(&root, "class/hwmon/hwmon2/curr1_input", "1500\n");
+        write(&root, "class/hwmon/hwmon10/device/name", "nvme\n");
+        write(&root, "class/hwmon/hwmon10/device/temp1_input", "-1500\n");
+        write(&root, "class/thermal/thermal_zone0/type", "x86_pkg_temp\n");
+        write(&root, "class/thermal/thermal_zone0/temp", "59000\n");
+
+        let readings = read_sensors(&root);
+        fs::remove_dir_all(&root).unwrap();
+
+        let find = |name: &str| readings.iter().find(|r| r.matches(name)).map(|r| r.value);
+        let kind = |name: &str| readings.iter().find(|r| r.matches(name)).map(|r| r.kind);
+        assert_eq!(readings.len(), 10);
+        assert_eq!(find("acpitz/temp1"), Some(27.8));
+        assert_eq!(find("coretemp/Core 0"), Some(53.0));
+        assert_eq!(find("Core 0"), Some(53.0));
+        assert_eq!(find("hwmon1/temp1"), Some(58.0));
+        assert_eq!(find("nvme/temp1"), Some(-1.5));
+        assert_eq!(find("thermal/x86_pkg_temp"), Some(59.0));
+        assert_eq!(find("thermal/thermal_zone0"), Some(59.0));
+        assert_eq!(find("acpitz/Core 0"), None);
+        assert_eq!(find("temp3"), None);
+
+        assert_eq!(find("thinkpad/CPU"), Some(2045.0));
+        assert_eq!(kind("thinkpad/CPU"), Some(SensorKind::Fan));
+        assert_eq!(find("thinkpad/in0"), Some(12.1));
+        assert_eq!(kind("thinkpad/in0"), Some(SensorKind::Voltage));
+        assert_eq!(find("thinkpad/power1"), Some(15.25));
+        assert_eq!(find("thinkpad/power2"), Some(5.0));
+        assert_eq!(find("thinkpad/curr1"), Some(1.5));
+        assert_eq!(kind("thinkpad/curr1"), Some(SensorKind::Current));
+    }
+}
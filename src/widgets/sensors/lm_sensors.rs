@@ -0,0 +1,284 @@
+//! Readings parsed from the output of the `sensors` executable from
+//! `lm_sensors`, either as text or, with `sensors -j`, as JSON.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::Value;
+
+use super::{Reading, SensorKind};
+use crate::Result;
+
+/// Returns the names a chip can be referred to by: its full name, such as
+/// `coretemp-isa-0000`, and the part before the first `-`, such as `coretemp`.
+fn chip_names(chip: &str) -> Vec<String> {
+    let mut names = vec![chip.to_owned()];
+    if let Some(idx) = chip.find('-') {
+        names.push(chip[..idx].to_owned());
+    }
+    names
+}
+
+/// Parses the text output of the `sensors` executable.
+///
+/// Each chip starts with a line holding just its name, followed by a line for
+/// each sensor such as `Core 0:  +53.0°C  (high = +105.0°C)`. Lines whose
+/// value isn't a temperature, fan speed, voltage, power or current are
+/// skipped.
+pub(super) fn parse_sensors_output(output: &str) -> Vec<Reading> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(
+            // Note: we ignore + but capture -. The degree sign is optional, as
+            // it may be mangled (or missing) depending on the locale.
+            r"^\+?(?P<value>-?\d+(?:\.\d+)?)\s*[^\w\s]?(?P<unit>C|F|RPM|mV|V|mW|W|mA|A)\b"
+        ).expect("Failed to compile regex for parsing sensors output");
+    }
+
+    let mut readings = Vec::new();
+    let mut chips = Vec::new();
+    for line in output.lines() {
+        let idx = match line.find(':') {
+            Some(idx) => idx,
+            None => {
+                // Continuation lines are indented, while chip names aren't.
+                if !line.is_empty() && !line.starts_with(char::is_whitespace) {
+                    chips = chip_names(line.trim());
+                }
+                continue;
+            }
+        };
+        let (label, value) = (line[..idx].trim(), line[idx + 1..].trim());
+        let mat = match RE.captures(value) {
+            Some(mat) => mat,
+            None => continue,
+        };
+        // These .unwraps() are harmless. If we have a match, we have these groups.
+        let value: f64 = match mat.name("value").unwrap().as_str().parse() {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+        let (kind, unit, scale) = match mat.name("unit").unwrap().as_str() {
+            "C" => (SensorKind::Temperature, "°C", 1.0),
+            "F" => (SensorKind::Temperature, "°F", 1.0),
+            "RPM" => (SensorKind::Fan, "RPM", 1.0),
+            "mV" => (SensorKind::Voltage, "V", 1000.0),
+            "V" => (SensorKind::Voltage, "V", 1.0),
+            "mW" => (SensorKind::Power, "W", 1000.0),
+            "W" => (SensorKind::Power, "W", 1.0),
+            "mA" => (SensorKind::Current, "A", 1000.0),
+            _ => (SensorKind::Current, "A", 1.0),
+        };
+        readings.push(Reading {
+            chips: chips.clone(),
+            labels: vec![label.to_owned()],
+            kind,
+            value: value / scale,
+            unit: unit.to_owned(),
+        });
+    }
+
+    readings
+}
+
+/// Parses the JSON output of `sensors -j`.
+///
+/// This maps each chip's name to its features, each of which maps its label
+/// to its subfeatures, such as `{"temp2_input": 53.0, "temp2_max": 105.0}`.
+/// Values are already in degrees Celsius, RPM, volts, watts or amperes.
+pub(super) fn parse_json_output(output: &str) -> Result<Vec<Reading>> {
+    let chips: serde_json::Map<String, Value> = serde_json::from_str(output)?;
+
+    let mut readings = Vec::new();
+    for (chip, features) in &chips {
+        let features = match features.as_object() {
+            Some(features) => features,
+            None => continue,
+        };
+        for (label, subfeatures) in features {
+            // Skip entries such as "Adapter", which aren't features.
+            let subfeatures = match subfeatures.as_object() {
+                Some(subfeatures) => subfeatures,
+                None => continue,
+            };
+            let input = subfeatures
+                .iter()
+                .find(|(name, _)| name.ends_with("_input"))
+                .or_else(|| {
+                    subfeatures
+                        .iter()
+                        .find(|(name, _)| name.ends_with("_average"))
+                });
+            let (name, value) = match input {
+                Some((name, value)) => match value.as_f64() {
+                    Some(value) => (name, value),
+                    None => continue,
+                },
+                None => continue,
+            };
+            let sensor = &name[..name.rfind('_').unwrap_or(name.len())];
+            let (kind, unit, _) = match SensorKind::from_sensor_name(sensor) {
+                Some(kind) => kind,
+                None => continue,
+            };
+            readings.push(Reading {
+                chips: chip_names(chip),
+                labels: vec![label.clone(), sensor.to_owned()],
+                kind,
+                value,
+                unit: unit.to_owned(),
+            });
+        }
+    }
+
+    Ok(readings)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn find<'a>(readings: &'a [Reading], name: &str) -> Option<(SensorKind, f64, &'a str)> {
+        readings
+            .iter()
+            .find(|r| r.matches(name))
+            .map(|r| (r.kind, r.value, r.unit.as_str()))
+    }
+
+    #[test]
+    fn works() {
+        let output = r#"applesmc-isa-0300
+Adapter: ISA adapter
+Right Side  :    0 RPM  (min = 2000 RPM, max = 6199 RPM)
+Ts1S:         -127.0 C
+Ts2S:          +34.0 F
+
+coretemp-isa-0000
+Adapter: ISA adapter
+Package id 0:  +58.0 C  (high = +105.0 C, crit = +105.0 C)
+Core 0:        +53.0 C  (high = +105.0 C, crit = +105.0 C)
+Core 1:        +58.0 C  (high = +105.0 C, crit = +105.0 C)
+"#;
+
+        let parsed = parse_sensors_output(output);
+        assert_eq!(
+            find(&parsed, "Core 0"),
+            Some((SensorKind::Temperature, 53.0, "°C"))
+        );
+        assert_eq!(
+            find(&parsed, "Core 1"),
+            Some((SensorKind::Temperature, 58.0, "°C"))
+        );
+        assert_eq!(
+            find(&parsed, "Ts1S"),
+            Some((SensorKind::Temperature, -127.0, "°C"))
+        );
+        assert_eq!(
+            find(&parsed, "Ts2S"),
+            Some((SensorKind::Temperature, 34.0, "°F"))
+        );
+        assert_eq!(
+            find(&parsed, "Right Side"),
+            Some((SensorKind::Fan, 0.0, "RPM"))
+        );
+        assert_eq!(
+            find(&parsed, "coretemp/Core 0"),
+            Some((SensorKind::Temperature, 53.0, "°C"))
+        );
+        assert_eq!(find(&parsed, "applesmc/Core 0"), None);
+
+        assert_eq!(parsed.len(), 6);
+    }
+
+    #[test]
+    fn parses_other_units() {
+        let output = "nct6775-isa-0290
+Adapter: ISA adapter
+Vcore:          +1.02 V  (min =  +0.00 V, max =  +1.74 V)
+in1:          +640.00 mV (min =  +0.00 V, max =  +0.00 V)
+fan2:          1205 RPM  (min =    0 RPM)
+SYSTIN:         +35.0°C  (high =  +0.0°C, hyst =  +0.0°C)
+                         sensor = thermistor
+intrusion0:    ALARM
+
+BAT0-acpi-0
+Adapter: ACPI interface
+in0:          12.30 V
+power1:        8.25 W
+curr1:       500.00 mA
+";
+
+        let parsed = parse_sensors_output(output);
+        assert_eq!(
+            find(&parsed, "Vcore"),
+            Some((SensorKind::Voltage, 1.02, "V"))
+        );
+        assert_eq!(find(&parsed, "in1"), Some((SensorKind::Voltage, 0.64, "V")));
+        assert_eq!(
+            find(&parsed, "fan2"),
+            Some((SensorKind::Fan, 1205.0, "RPM"))
+        );
+        assert_eq!(
+            find(&parsed, "nct6775/SYSTIN"),
+            Some((SensorKind::Temperature, 35.0, "°C"))
+        );
+        assert_eq!(
+            find(&parsed, "BAT0/in0"),
+            Some((SensorKind::Voltage, 12.3, "V"))
+        );
+        assert_eq!(
+            find(&parsed, "power1"),
+            Some((SensorKind::Power, 8.25, "W"))
+        );
+        assert_eq!(
+            find(&parsed, "curr1"),
+            Some((SensorKind::Current, 0.5, "A"))
+        );
+        assert_eq!(parsed.len(), 7);
+    }
+
+    #[test]
+    fn parses_json() {
+        let output = r#"{
+   "coretemp-isa-0000":{
+      "Adapter": "ISA adapter",
+      "Core 0":{
+         "temp2_input": 53.000,
+         "temp2_max": 105.000,
+         "temp2_crit_alarm": 0.000
+      }
+   },
+   "thinkpad-isa-0000":{
+      "Adapter": "ISA adapter",
+      "fan1":{
+         "fan1_input": 2045.000
+      },
+      "power1":{
+         "power1_average": 15.250
+      },
+      "intrusion0":{
+         "intrusion0_alarm": 0.000
+      }
+   }
+}"#;
+
+        let parsed = parse_json_output(output).unwrap();
+        assert_eq!(
+            find(&parsed, "coretemp/Core 0"),
+            Some((SensorKind::Temperature, 53.0, "°C"))
+        );
+        assert_eq!(
+            find(&parsed, "coretemp-isa-0000/temp2"),
+            Some((SensorKind::Temperature, 53.0, "°C"))
+        );
+        assert_eq!(
+            find(&parsed, "thinkpad/fan1"),
+            Some((SensorKind::Fan, 2045.0, "RPM"))
+        );
+        assert_eq!(
+            find(&parsed, "power1"),
+            Some((SensorKind::Power, 15.25, "W"))
+        );
+        assert_eq!(parsed.len(), 3);
+
+        assert!(parse_json_output("not json").is_err());
+    }
+}
@@ -0,0 +1,543 @@
+use std::cell::RefCell;
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use futures::{future, stream, StreamExt};
+use log::*;
+use tokio::time;
+
+use super::{Button, Event, EventStream, Widget, WidgetStream};
+use crate::text::{Attributes, Color, Text};
+use crate::{Cnx, Result};
+
+/// Shows a stopwatch, a countdown or a pomodoro timer.
+///
+/// The timer starts paused. Left-clicking it starts or pauses it, and
+/// right-clicking it resets it. It can also be controlled by sending it the
+/// messages `start`, `pause`, `toggle` or `reset` over Cnx's IPC socket, e.g.
+/// with `cnx-msg send <widget> toggle`, so it should be added with
+/// [`Cnx::add_named_widget_to()`].
+///
+/// A [`countdown()`] stops when it reaches zero, while a [`pomodoro()`] timer
+/// alternates between counting down a period of work and a break. Their text
+/// changes to a `warning_color` for the last tenth of each countdown. Use
+/// [`with_command()`] to run a command whenever a countdown ends.
+///
+/// The timer keeps running if the widget is restarted, e.g. with `cnx-msg
+/// refresh <widget>`, but starts afresh when Cnx's configuration is reloaded.
+///
+/// [`Cnx::add_named_widget_to()`]: ../struct.Cnx.html#method.add_named_widget_to
+/// [`countdown()`]: #method.countdown
+/// [`pomodoro()`]: #method.pomodoro
+/// [`with_command()`]: #method.with_command
+#[derive(Clone)]
+pub struct Timer {
+    attr: Attributes,
+    warning_color: Color,
+    mode: Mode,
+    command: Option<String>,
+    // Shared between clones, so that the timer isn't reset when the widget is
+    // restarted.
+    state: Rc<RefCell<State>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mode {
+    Stopwatch,
+    Countdown(Duration),
+    Pomodoro { work: Duration, rest: Duration },
+}
+
+impl Timer {
+    /// Creates a new stopwatch, which counts up from zero.
+    ///
+    /// The [`Cnx`] instance is borrowed during construction. However, it is not
+    /// borrowed for the lifetime of the widget. See the [`cnx_add_widget!()`]
+    /// for more discussion about the lifetime of the borrow.
+    ///
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: Color::white(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx.add_named_widget_to(Region::Right, "stopwatch", Timer::stopwatch(&cnx, attr));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn stopwatch(_cnx: &Cnx, attr: Attributes) -> Timer {
+        Timer {
+            warning_color: attr.fg_color.clone(),
+            attr,
+            mode: Mode::Stopwatch,
+            command: None,
+            state: Rc::new(RefCell::new(State::new(Mode::Stopwatch))),
+        }
+    }
+
+    /// Creates a new countdown from `duration` to zero, whose text changes to
+    /// `warning_color` when a tenth of it remains.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// # let attr = Attributes {
+    /// #     font: Font::new("SourceCodePro 21"),
+    /// #     fg_color: Color::white(),
+    /// #     bg_color: None,
+    /// #     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    /// # };
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// let tea = Timer::countdown(&cnx, attr, Duration::from_secs(4 * 60), Color::red())
+    ///     .with_command("notify-send 'Tea is ready'");
+    /// cnx.add_named_widget_to(Region::Right, "tea", tea);
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn countdown(
+        _cnx: &Cnx,
+        attr: Attributes,
+        duration: Duration,
+        warning_color: Color,
+    ) -> Timer {
+        Timer {
+            attr,
+            warning_color,
+            mode: Mode::Countdown(duration),
+            command: None,
+            state: Rc::new(RefCell::new(State::new(Mode::Countdown(duration)))),
+        }
+    }
+
+    /// Creates a new pomodoro timer, which counts down `work` and then
+    /// `rest`, over and over, showing `Work` or `Break` before the time
+    /// remaining. Its text changes to `warning_color` when a tenth of either
+    /// remains.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// # let attr = Attributes {
+    /// #     font: Font::new("SourceCodePro 21"),
+    /// #     fg_color: Color::white(),
+    /// #     bg_color: None,
+    /// #     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    /// # };
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// let minutes = |n: u64| Duration::from_secs(n * 60);
+    /// let pomodoro = Timer::pomodoro(&cnx, attr, minutes(25), minutes(5), Color::red());
+    /// cnx.add_named_widget_to(Region::Right, "pomodoro", pomodoro);
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn pomodoro(
+        _cnx: &Cnx,
+        attr: Attributes,
+        work: Duration,
+        rest: Duration,
+        warning_color: Color,
+    ) -> Timer {
+        Timer {
+            attr,
+            warning_color,
+            mode: Mode::Pomodoro { work, rest },
+            command: None,
+            state: Rc::new(RefCell::new(State::new(Mode::Pomodoro { work, rest }))),
+        }
+    }
+
+    /// Runs a shell command whenever a countdown, or a pomodoro timer's period
+    /// of work or break, ends.
+    ///
+    /// The command is run with `sh -c`, with `CNX_TIMER_PHASE` set to
+    /// `countdown`, `work` or `break` to say what has just ended. Cnx doesn't
+    /// wait for it to finish.
+    pub fn with_command<S: Into<String>>(mut self, command: S) -> Timer {
+        self.command = Some(command.into());
+        self
+    }
+
+    fn texts(&self, state: &State, now: Instant) -> Vec<Text> {
+        let shown = state.shown(now);
+        let mut attr = self.attr.clone();
+        if let Some(length) = state.phase.length() {
+            if shown <= length / 10 {
+                attr.fg_color = self.warning_color.clone();
+            }
+        }
+        let time = format_duration(shown);
+        let text = match state.phase {
+            Phase::Work(_) => format!("Work {}", time),
+            Phase::Break(_) => format!("Break {}", time),
+            Phase::Stopwatch | Phase::Countdown(_) => time,
+        };
+        vec![Text::new(attr, text)]
+    }
+
+    /// Runs the command, if there is one, for the end of the given phase.
+    fn run_command(&self, ended: Phase) {
+        let command = match self.command {
+            Some(ref command) => command,
+            None => return,
+        };
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .env("CNX_TIMER_PHASE", ended.name())
+            .stdin(Stdio::null())
+            .spawn();
+        match child {
+            // Wait for the command on another thread, so that it doesn't
+            // linger as a zombie once it's done.
+            Ok(mut child) => {
+                thread::spawn(move || child.wait());
+            }
+            Err(e) => warn!("Failed to run timer command {:?}: {}", command, e),
+        }
+    }
+
+    /// Handles an event, returning whether it changed the timer.
+    fn handle_event(&self, state: &mut State, event: &Event, now: Instant) -> bool {
+        let action = match *event {
+            Event::ButtonPress(ref click) => match click.button {
+                Button::Left => "toggle",
+                Button::Right => "reset",
+                _ => return false,
+            },
+            Event::Message(ref message) => message.trim(),
+            Event::ButtonRelease(_) => return false,
+        };
+        match action {
+            "start" => state.start(now),
+            "pause" => state.pause(now),
+            "toggle" if state.is_running() => state.pause(now),
+            "toggle" => state.start(now),
+            "reset" => *state = State::new(self.mode),
+            _ => {
+                warn!("Unknown timer command: {:?}", action);
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl Widget for Timer {
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        self.stream_with_events(Box::pin(stream::empty()))
+    }
+
+    fn stream_with_events(self: Box<Self>, events: EventStream) -> Result<WidgetStream> {
+        let initial = self.texts(&self.state.borrow(), Instant::now());
+        let stream = stream::unfold((self, events), |(timer, mut events)| async move {
+            loop {
+                let wait = timer.state.borrow().until_change(Instant::now());
+                let wake = async move {
+                    match wait {
+                        Some(wait) => time::sleep(wait).await,
+                        None => future::pending().await,
+                    }
+                };
+                tokio::select! {
+                    () = wake => {}
+                    Some(event) = events.next() => {
+                        let mut state = timer.state.borrow_mut();
+                        if !timer.handle_event(&mut state, &event, Instant::now()) {
+                            continue;
+                        }
+                    }
+                }
+
+                let now = Instant::now();
+                let mut state = timer.state.borrow_mut();
+                if let Some(ended) = state.update(now) {
+                    timer.run_command(ended);
+                }
+                let texts = timer.texts(&state, now);
+                drop(state);
+                return Some((Ok(texts), (timer, events)));
+            }
+        });
+
+        Ok(Box::pin(
+            stream::once(future::ready(Ok(initial))).chain(stream),
+        ))
+    }
+}
+
+/// What the timer is currently timing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Phase {
+    Stopwatch,
+    Countdown(Duration),
+    Work(Duration),
+    Break(Duration),
+}
+
+impl Phase {
+    /// Returns how long the phase lasts, if it ends.
+    fn length(self) -> Option<Duration> {
+        match self {
+            Phase::Stopwatch => None,
+            Phase::Countdown(length) | Phase::Work(length) | Phase::Break(length) => Some(length),
+        }
+    }
+
+    /// Returns the name given to the command run when the phase ends.
+    fn name(self) -> &'static str {
+        match self {
+            Phase::Stopwatch => "stopwatch",
+            Phase::Countdown(_) => "countdown",
+            Phase::Work(_) => "work",
+            Phase::Break(_) => "break",
+        }
+    }
+}
+
+/// The state of a running timer.
+#[derive(Debug, PartialEq)]
+struct State {
+    mode: Mode,
+    phase: Phase,
+    /// The time elapsed in this phase before the timer was last started.
+    elapsed: Duration,
+    /// When the timer was last started, if it's running.
+    started: Option<Instant>,
+}
+
+impl State {
+    fn new(mode: Mode) -> State {
+        let phase = match mode {
+            Mode::Stopwatch => Phase::Stopwatch,
+            Mode::Countdown(length) => Phase::Countdown(length),
+            Mode::Pomodoro { work, .. } => Phase::Work(work),
+        };
+        State {
+            mode,
+            phase,
+            elapsed: Duration::from_secs(0),
+            started: None,
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        self.started.is_some()
+    }
+
+    fn elapsed(&self, now: Instant) -> Duration {
+        self.elapsed
+            + self
+                .started
+                .map_or(Duration::from_secs(0), |started| now - started)
+    }
+
+    fn start(&mut self, now: Instant) {
+        if self.started.is_some() {
+            return;
+        }
+        // Starting a finished countdown starts it again.
+        if self.phase.length() == Some(self.elapsed) {
+            self.elapsed = Duration::from_secs(0);
+        }
+        self.started = Some(now);
+    }
+
+    fn pause(&mut self, now: Instant) {
+        self.elapsed = self.elapsed(now);
+        self.started = None;
+    }
+
+    /// Returns the time to show: the time elapsed for a stopwatch, or the
+    /// time remaining (rounded up to the second) for a countdown.
+    fn shown(&self, now: Instant) -> Duration {
+        let elapsed = self.elapsed(now);
+        match self.phase.length() {
+            Some(length) if elapsed < length => {
+                let remaining = length - elapsed;
+                let round_up = if remaining.subsec_nanos() > 0 { 1 } else { 0 };
+                Duration::from_secs(remaining.as_secs() + round_up)
+            }
+            Some(_) => Duration::from_secs(0),
+            None => Duration::from_secs(elapsed.as_secs()),
+        }
+    }
+
+    /// Returns how long until the time shown changes, or `None` if the timer
+    /// is paused.
+    fn until_change(&self, now: Instant) -> Option<Duration> {
+        self.started?;
+        let elapsed = self.elapsed(now);
+        let nanos = match self.phase.length() {
+            Some(length) if elapsed < length => (length - elapsed).subsec_nanos(),
+            Some(_) => 0,
+            None => 1_000_000_000 - elapsed.subsec_nanos(),
+        };
+        Some(if nanos == 0 {
+            Duration::from_secs(1)
+        } else {
+            Duration::from_nanos(u64::from(nanos))
+        })
+    }
+
+    /// Ends the current phase if its time is up, returning the phase that
+    /// ended. A countdown stops at zero, while a pomodoro timer moves on to
+    /// its next phase.
+    fn update(&mut self, now: Instant) -> Option<Phase> {
+        let length = self.phase.length()?;
+        if self.started.is_none() || self.elapsed(now) < length {
+            return None;
+        }
+        let ended = self.phase;
+        match (self.mode, self.phase) {
+            (Mode::Pomodoro { rest, .. }, Phase::Work(_)) => {
+                self.phase = Phase::Break(rest);
+                self.elapsed = Duration::from_secs(0);
+                self.started = Some(now);
+            }
+            (Mode::Pomodoro { work, .. }, Phase::Break(_)) => {
+                self.phase = Phase::Work(work);
+                self.elapsed = Duration::from_secs(0);
+                self.started = Some(now);
+            }
+            _ => {
+                self.elapsed = length;
+                self.started = None;
+            }
+        }
+        Some(ended)
+    }
+}
+
+/// Formats a duration as `M:SS`, or `H:MM:SS` if it's an hour or more.
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::text::{Font, Padding};
+    use crate::Position;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(format_duration(secs(0)), "0:00");
+        assert_eq!(format_duration(secs(25 * 60)), "25:00");
+        assert_eq!(format_duration(secs(3600 + 61)), "1:01:01");
+    }
+
+    #[test]
+    fn stopwatch_pauses_and_resumes() {
+        let now = Instant::now();
+        let mut state = State::new(Mode::Stopwatch);
+        assert_eq!(state.until_change(now), None);
+        state.start(now);
+        state.pause(now + secs(5));
+        assert_eq!(state.shown(now + secs(60)), secs(5));
+        state.start(now + secs(60));
+        assert_eq!(state.shown(now + secs(62)), secs(7));
+        assert_eq!(
+            state.until_change(now + Duration::from_millis(62_250)),
+            Some(Duration::from_millis(750))
+        );
+    }
+
+    #[test]
+    fn countdown_stops_at_zero() {
+        let now = Instant::now();
+        let mut state = State::new(Mode::Countdown(secs(60)));
+        state.start(now);
+        assert_eq!(state.shown(now + Duration::from_millis(500)), secs(60));
+        assert_eq!(state.update(now + secs(59)), None);
+        assert_eq!(
+            state.update(now + secs(61)),
+            Some(Phase::Countdown(secs(60)))
+        );
+        assert!(!state.is_running());
+        assert_eq!(state.shown(now + secs(61)), secs(0));
+        // Starting it again restarts it.
+        state.start(now + secs(70));
+        assert_eq!(state.shown(now + secs(70)), secs(60));
+    }
+
+    #[test]
+    fn pomodoro_alternates_work_and_breaks() {
+        let now = Instant::now();
+        let mode = Mode::Pomodoro {
+            work: secs(25),
+            rest: secs(5),
+        };
+        let mut state = State::new(mode);
+        state.start(now);
+        assert_eq!(state.update(now + secs(25)), Some(Phase::Work(secs(25))));
+        assert_eq!(state.phase, Phase::Break(secs(5)));
+        assert_eq!(state.update(now + secs(30)), Some(Phase::Break(secs(5))));
+        assert_eq!(state.phase, Phase::Work(secs(25)));
+        assert!(state.is_running());
+    }
+
+    #[tokio::test]
+    async fn restarting_keeps_timer_running() {
+        let cnx = Cnx::new(Position::Top).unwrap();
+        let attr = Attributes {
+            font: Font::new("monospace"),
+            fg_color: Color::white(),
+            bg_color: None,
+            padding: Padding::new(0.0, 0.0, 0.0, 0.0),
+        };
+        let timer = Timer::stopwatch(&cnx, attr);
+
+        let start = stream::iter(vec![Event::Message("start".to_owned())]);
+        let mut texts = Box::new(timer.clone())
+            .stream_with_events(Box::pin(start))
+            .unwrap();
+        texts.next().await.unwrap().unwrap();
+        texts.next().await.unwrap().unwrap();
+        drop(texts);
+
+        // The restarted widget carries on from where the old one was.
+        let mut texts = Box::new(timer.clone()).stream().unwrap();
+        texts.next().await.unwrap().unwrap();
+        assert!(timer.state.borrow().is_running());
+    }
+}
@@ -0,0 +1,513 @@
+use std::rc::Rc;
+
+use failure::{format_err, ResultExt};
+use futures::{future, stream, StreamExt};
+use log::*;
+use xcb;
+
+use super::{Widget, WidgetStream};
+use crate::text::{Attributes, Padding, Text};
+use crate::x11::{Connection, LazyConnection, WindowWatcher};
+use crate::{Cnx, Result};
+
+// Opcodes from the System Tray and XEmbed specifications.
+const SYSTEM_TRAY_REQUEST_DOCK: u32 = 0;
+const XEMBED_EMBEDDED_NOTIFY: u32 = 0;
+const XEMBED_VERSION: u32 = 0;
+const XEMBED_MAPPED: u32 = 1 << 0;
+const SYSTEM_TRAY_ORIENTATION_HORZ: u32 = 0;
+
+fn intern_atom(conn: &xcb::Connection, name: &str) -> Result<xcb::Atom> {
+    let reply = xcb::intern_atom(conn, false, name)
+        .get_reply()
+        .with_context(|_| format!("Failed to intern atom {}", name))?;
+    Ok(reply.atom())
+}
+
+/// Hosts system tray icons, such as those of `nm-applet` or `blueman`.
+///
+/// This widget implements the [`System Tray`] protocol. It acquires the
+/// `_NET_SYSTEM_TRAY_Sn` selection for the screen and embeds the icons of
+/// any applications that ask to be docked, using [`XEmbed`]. Icons are shown
+/// left-to-right in the order they were docked, and are sized to fit the
+/// height of the bar. Icons which clear the `XEMBED_MAPPED` flag in their
+/// `_XEMBED_INFO` property are hidden until they set it again. The widget
+/// takes up no space when there are no icons to show.
+///
+/// Only one application can act as the system tray at any one time. If
+/// another system tray is already running, this widget will return an error.
+/// If Cnx shows a bar on multiple monitors, the icons are shown on the bar on
+/// the primary monitor.
+///
+/// The icons can only be embedded in Cnx's own bar, so this widget is skipped
+/// (with a warning) when another [`OutputMode`] is used.
+///
+/// [`OutputMode`]: ../enum.OutputMode.html
+/// [`System Tray`]: https://specifications.freedesktop.org/systemtray-spec/systemtray-spec-latest.html
+/// [`XEmbed`]: https://specifications.freedesktop.org/xembed-spec/xembed-spec-latest.html
+#[derive(Clone)]
+pub struct Tray {
+    conn: LazyConnection,
+    attr: Attributes,
+}
+
+impl Tray {
+    /// Creates a new Tray widget.
+    ///
+    /// Creates a new `Tray` widget. The font of the given [`Attributes`] is
+    /// used to determine the height of the widget, and so the size of the
+    /// icons.
+    ///
+    /// The [`Cnx`] instance is borrowed during construction. However, it is not
+    /// borrowed for the lifetime of the widget. See the [`cnx_add_widget!()`]
+    /// for more discussion about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate cnx;
+    /// #
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: Color::white(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx_add_widget!(cnx, Region::Right, Tray::new(&cnx, attr.clone()));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(cnx: &Cnx, attr: Attributes) -> Tray {
+        Tray {
+            conn: cnx.x_connection(),
+            attr,
+        }
+    }
+}
+
+impl Widget for Tray {
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        let conn = self.conn.get()?;
+        let (watcher, events) = conn.watch_windows();
+
+        let mut tray = TrayManager::new(conn, watcher, self.attr)?;
+        let initial = stream::once(future::ready(Ok(tray.texts())));
+
+        let text_stream = events.filter_map(move |event| {
+            let texts = event.and_then(|event| tray.handle_event(&event));
+            future::ready(texts.transpose())
+        });
+
+        Ok(Box::pin(initial.chain(text_stream)))
+    }
+
+    fn embeds_windows(&self) -> bool {
+        true
+    }
+}
+
+/// Owns the system tray selection and the window that icons are docked into.
+///
+/// The selection is released and the icons are given back to the root window
+/// when this is dropped.
+struct TrayManager {
+    conn: Rc<Connection>,
+    watcher: WindowWatcher,
+    root: xcb::Window,
+    window: xcb::Window,
+    selection: xcb::Atom,
+    opcode: xcb::Atom,
+    xembed: xcb::Atom,
+    xembed_info: xcb::Atom,
+    attr: Attributes,
+    icons: Icons,
+    icon_size: u16,
+}
+
+impl TrayManager {
+    fn new(conn: Rc<Connection>, watcher: WindowWatcher, attr: Attributes) -> Result<TrayManager> {
+        let screen_idx = conn.screen_idx();
+        let (root, black_pixel) = {
+            let screen = conn
+                .get_setup()
+                .roots()
+                .nth(screen_idx as usize)
+                .ok_or_else(|| format_err!("Invalid screen"))?;
+            (screen.root(), screen.black_pixel())
+        };
+
+        let selection = intern_atom(&conn, &format!("_NET_SYSTEM_TRAY_S{}", screen_idx))?;
+        let opcode = intern_atom(&conn, "_NET_SYSTEM_TRAY_OPCODE")?;
+        let orientation = intern_atom(&conn, "_NET_SYSTEM_TRAY_ORIENTATION")?;
+        let xembed = intern_atom(&conn, "_XEMBED")?;
+        let xembed_info = intern_atom(&conn, "_XEMBED_INFO")?;
+
+        let owner = xcb::get_selection_owner(&conn, selection)
+            .get_reply()
+            .context("Failed to get system tray selection owner")?
+            .owner();
+        if owner != xcb::NONE {
+            return Err(format_err!("Another system tray is already running"));
+        }
+
+        // Create the window that icons will be docked into. It stays unmapped
+        // until the bar embeds it, and is override-redirect so that the WM
+        // doesn't try to manage it in the meantime.
+        let window = conn.generate_id();
+        let values = [
+            (xcb::CW_BACK_PIXEL, black_pixel),
+            (xcb::CW_OVERRIDE_REDIRECT, 1),
+            (xcb::CW_EVENT_MASK, xcb::EVENT_MASK_STRUCTURE_NOTIFY),
+        ];
+        xcb::create_window(
+            &conn,
+            xcb::COPY_FROM_PARENT as u8,
+            window,
+            root,
+            0,
+            0,
+            1,
+            1,
+            0,
+            xcb::WINDOW_CLASS_INPUT_OUTPUT as u16,
+            xcb::COPY_FROM_PARENT,
+            &values,
+        );
+        xcb::change_property(
+            &conn,
+            xcb::PROP_MODE_REPLACE as u8,
+            window,
+            orientation,
+            xcb::ATOM_CARDINAL,
+            32,
+            &[SYSTEM_TRAY_ORIENTATION_HORZ],
+        );
+        watcher.watch(window);
+
+        xcb::set_selection_owner(&conn, window, selection, xcb::CURRENT_TIME);
+        let owner = xcb::get_selection_owner(&conn, selection)
+            .get_reply()
+            .context("Failed to get system tray selection owner")?
+            .owner();
+        if owner != window {
+            xcb::destroy_window(&conn, window);
+            return Err(format_err!("Failed to acquire system tray selection"));
+        }
+
+        // Tell any applications waiting for a system tray that we're here.
+        let data =
+            xcb::ClientMessageData::from_data32([xcb::CURRENT_TIME, selection, window, 0, 0]);
+        let event = xcb::ClientMessageEvent::new(32, root, conn.MANAGER(), data);
+        xcb::send_event(&conn, false, root, xcb::EVENT_MASK_STRUCTURE_NOTIFY, &event);
+        conn.flush();
+
+        Ok(TrayManager {
+            conn,
+            watcher,
+            root,
+            window,
+            selection,
+            opcode,
+            xembed,
+            xembed_info,
+            attr,
+            icons: Icons::default(),
+            icon_size: 0,
+        })
+    }
+
+    /// Returns the placeholder text which the bar replaces with our window.
+    ///
+    /// The text is empty, but padded to be wide enough for all of the icons.
+    fn texts(&self) -> Vec<Text> {
+        let width = f64::from(self.icons.width(u32::from(self.icon_size)));
+        let mut attr = self.attr.clone();
+        attr.padding = Padding::new(width, 0.0, 0.0, 0.0);
+        vec![Text::new(attr, "").with_embed(self.window)]
+    }
+
+    /// Handles an X event, returning new texts if the width of the tray has
+    /// changed.
+    fn handle_event(&mut self, event: &xcb::GenericEvent) -> Result<Option<Vec<Text>>> {
+        let changed = match event.response_type() & !0x80 {
+            xcb::CLIENT_MESSAGE => {
+                let event: &xcb::ClientMessageEvent = unsafe { xcb::cast_event(event) };
+                let data = event.data().data32();
+                if event.window() == self.window
+                    && event.type_() == self.opcode
+                    && data[1] == SYSTEM_TRAY_REQUEST_DOCK
+                {
+                    self.dock(data[2])
+                } else {
+                    false
+                }
+            }
+            xcb::DESTROY_NOTIFY => {
+                let event: &xcb::DestroyNotifyEvent = unsafe { xcb::cast_event(event) };
+                self.forget(event.window())
+            }
+            xcb::REPARENT_NOTIFY => {
+                // The application may take its icon back by reparenting it.
+                let event: &xcb::ReparentNotifyEvent = unsafe { xcb::cast_event(event) };
+                event.parent() != self.window && self.forget(event.window())
+            }
+            xcb::PROPERTY_NOTIFY => {
+                // The icon wants to be shown or hidden.
+                let event: &xcb::PropertyNotifyEvent = unsafe { xcb::cast_event(event) };
+                event.atom() == self.xembed_info && self.update_mapped(event.window())
+            }
+            xcb::CONFIGURE_NOTIFY => {
+                // The bar has resized our window. Resize the icons to match.
+                let event: &xcb::ConfigureNotifyEvent = unsafe { xcb::cast_event(event) };
+                if event.window() == self.window && event.height() != self.icon_size {
+                    self.icon_size = event.height();
+                    self.layout_icons();
+                    true
+                } else {
+                    false
+                }
+            }
+            xcb::SELECTION_CLEAR => {
+                let event: &xcb::SelectionClearEvent = unsafe { xcb::cast_event(event) };
+                if event.selection() == self.selection {
+                    return Err(format_err!("Another application took over the system tray"));
+                }
+                false
+            }
+            _ => false,
+        };
+        self.conn.flush();
+
+        Ok(if changed { Some(self.texts()) } else { None })
+    }
+
+    /// Docks an icon, returning whether the width of the tray has changed.
+    fn dock(&mut self, icon: xcb::Window) -> bool {
+        if self.icons.contains(icon) {
+            return false;
+        }
+        debug!("Docking system tray icon {}", icon);
+
+        self.watcher.watch(icon);
+        self.conn.select_input(
+            icon,
+            xcb::EVENT_MASK_STRUCTURE_NOTIFY | xcb::EVENT_MASK_PROPERTY_CHANGE,
+        );
+        // If we exit without giving the icon back, the X server will reparent
+        // it to the root window rather than destroying it.
+        xcb::change_save_set(&self.conn, xcb::SET_MODE_INSERT as u8, icon);
+        xcb::reparent_window(&self.conn, icon, self.window, 0, 0);
+
+        let data = xcb::ClientMessageData::from_data32([
+            xcb::CURRENT_TIME,
+            XEMBED_EMBEDDED_NOTIFY,
+            0,
+            self.window,
+            XEMBED_VERSION,
+        ]);
+        let event = xcb::ClientMessageEvent::new(32, icon, self.xembed, data);
+        xcb::send_event(&self.conn, false, icon, xcb::EVENT_MASK_NO_EVENT, &event);
+
+        let mapped = self.wants_mapping(icon);
+        self.icons.dock(icon, mapped);
+        self.layout_icons();
+        if mapped {
+            xcb::map_window(&self.conn, icon);
+        }
+        mapped
+    }
+
+    /// Maps or unmaps an icon after its `_XEMBED_INFO` has changed, returning
+    /// whether the width of the tray has changed.
+    fn update_mapped(&mut self, icon: xcb::Window) -> bool {
+        if !self.icons.contains(icon) {
+            return false;
+        }
+        let mapped = self.wants_mapping(icon);
+        if !self.icons.set_mapped(icon, mapped) {
+            return false;
+        }
+        if mapped {
+            xcb::map_window(&self.conn, icon);
+        } else {
+            xcb::unmap_window(&self.conn, icon);
+        }
+        self.layout_icons();
+        true
+    }
+
+    /// Returns whether an icon wants to be shown, going by the `XEMBED_MAPPED`
+    /// flag in its `_XEMBED_INFO` property.
+    fn wants_mapping(&self, icon: xcb::Window) -> bool {
+        let reply = xcb::get_property(
+            &self.conn,
+            false,
+            icon,
+            self.xembed_info,
+            self.xembed_info,
+            0,
+            2,
+        )
+        .get_reply();
+        match reply {
+            Ok(ref reply) if reply.value_len() >= 2 => reply.value::<u32>()[1] & XEMBED_MAPPED != 0,
+            // Plenty of icons don't set the property at all, and other trays
+            // show them anyway.
+            _ => true,
+        }
+    }
+
+    /// Forgets about an icon, returning whether it was one of ours.
+    fn forget(&mut self, icon: xcb::Window) -> bool {
+        if !self.icons.forget(icon) {
+            return false;
+        }
+        debug!("Removing system tray icon {}", icon);
+        self.watcher.unwatch(icon);
+        self.conn.forget_window(icon);
+        self.layout_icons();
+        true
+    }
+
+    fn layout_icons(&self) {
+        // Until the bar has told us how tall we are, we don't know how big
+        // the icons should be. X doesn't allow windows to be 0px big.
+        let size = u32::from(self.icon_size.max(1));
+        for (icon, x) in self.icons.layout(size) {
+            let values = [
+                (xcb::CONFIG_WINDOW_X as u16, x),
+                (xcb::CONFIG_WINDOW_Y as u16, 0),
+                (xcb::CONFIG_WINDOW_WIDTH as u16, size),
+                (xcb::CONFIG_WINDOW_HEIGHT as u16, size),
+            ];
+            xcb::configure_window(&self.conn, icon, &values);
+        }
+    }
+}
+
+impl Drop for TrayManager {
+    fn drop(&mut self) {
+        // Hand the icons and the selection back, so that another system tray
+        // can take over.
+        for icon in self.icons.windows() {
+            xcb::unmap_window(&self.conn, icon);
+            xcb::reparent_window(&self.conn, icon, self.root, 0, 0);
+            self.watcher.unwatch(icon);
+            self.conn.deselect_input(
+                icon,
+                xcb::EVENT_MASK_STRUCTURE_NOTIFY | xcb::EVENT_MASK_PROPERTY_CHANGE,
+            );
+        }
+        xcb::set_selection_owner(&self.conn, xcb::NONE, self.selection, xcb::CURRENT_TIME);
+        xcb::destroy_window(&self.conn, self.window);
+        self.watcher.unwatch(self.window);
+        self.conn.flush();
+    }
+}
+
+/// The docked icons, in the order they were docked, and whether each of them
+/// wants to be shown.
+#[derive(Default)]
+struct Icons(Vec<(xcb::Window, bool)>);
+
+impl Icons {
+    fn contains(&self, icon: xcb::Window) -> bool {
+        self.0.iter().any(|&(i, _)| i == icon)
+    }
+
+    fn windows(&self) -> impl Iterator<Item = xcb::Window> + '_ {
+        self.0.iter().map(|&(icon, _)| icon)
+    }
+
+    /// Adds an icon after the others, returning whether it's new.
+    fn dock(&mut self, icon: xcb::Window, mapped: bool) -> bool {
+        if self.contains(icon) {
+            return false;
+        }
+        self.0.push((icon, mapped));
+        true
+    }
+
+    /// Removes an icon, returning whether it was docked.
+    fn forget(&mut self, icon: xcb::Window) -> bool {
+        let len = self.0.len();
+        self.0.retain(|&(i, _)| i != icon);
+        self.0.len() != len
+    }
+
+    /// Records whether an icon wants to be shown, returning whether that has
+    /// changed.
+    fn set_mapped(&mut self, icon: xcb::Window, mapped: bool) -> bool {
+        match self.0.iter_mut().find(|(i, _)| *i == icon) {
+            Some((_, m)) if *m != mapped => {
+                *m = mapped;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the x coordinate of each of the icons which are shown, when
+    /// they're `size` pixels square.
+    fn layout(&self, size: u32) -> Vec<(xcb::Window, u32)> {
+        self.0
+            .iter()
+            .filter(|&&(_, mapped)| mapped)
+            .enumerate()
+            .map(|(i, &(icon, _))| (icon, i as u32 * size))
+            .collect()
+    }
+
+    /// Returns the width taken up by the icons which are shown.
+    fn width(&self, size: u32) -> u32 {
+        self.layout(size).len() as u32 * size
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lays_out_shown_icons() {
+        let mut icons = Icons::default();
+        assert!(icons.dock(10, true));
+        assert!(icons.dock(11, false));
+        assert!(icons.dock(12, true));
+        assert!(!icons.dock(10, true));
+        assert_eq!(icons.layout(16), [(10, 0), (12, 16)]);
+        assert_eq!(icons.width(16), 32);
+
+        assert!(icons.set_mapped(11, true));
+        assert!(!icons.set_mapped(11, true));
+        assert!(!icons.set_mapped(13, true));
+        assert_eq!(icons.layout(16), [(10, 0), (11, 16), (12, 32)]);
+        assert_eq!(icons.width(0), 0);
+    }
+
+    #[test]
+    fn forgotten_icons_make_room() {
+        let mut icons = Icons::default();
+        icons.dock(10, true);
+        icons.dock(11, true);
+        icons.dock(12, true);
+        assert!(icons.forget(11));
+        assert!(!icons.forget(11));
+        assert!(!icons.contains(11));
+        assert_eq!(icons.layout(20), [(10, 0), (12, 20)]);
+        assert_eq!(icons.windows().collect::<Vec<_>>(), [10, 12]);
+    }
+}
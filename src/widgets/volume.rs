@@ -1,17 +1,18 @@
 use std::io;
-use std::os::unix::io::RawFd;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::task::{Context, Poll};
 
-use alsa::mixer::{SelemChannelId, SelemId};
+use alsa::mixer::{Selem, SelemChannelId, SelemId};
 use alsa::{self, Mixer, PollDescriptors};
 use failure::{format_err, Error, ResultExt};
-use futures::{Async, Poll, Stream};
-use mio::event::Evented;
-use mio::unix::EventedFd;
-use mio::{self, PollOpt, Ready, Token};
-use tokio_core::reactor::{Handle, PollEvented};
-
-use super::{Widget, WidgetStream};
-use crate::text::{Attributes, Text};
+use futures::{future, stream, Stream, StreamExt};
+use log::*;
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+
+use super::{Button, Event, EventStream, Widget, WidgetStream};
+use crate::text::{Attributes, Icon, Text};
 use crate::{Cnx, Result};
 
 /// Shows the current volume of the default ALSA output.
@@ -19,13 +20,22 @@ use crate::{Cnx, Result};
 /// This widget shows the current volume of the default ALSA output, or '`M`' if
 /// the output is muted.
 ///
+/// Scrolling over the widget raises or lowers the volume, and clicking on it
+/// toggles whether the output is muted.
+///
+/// Use [`with_icon()`] to show an icon, such as a speaker, before the volume.
+///
 /// The widget uses `alsa-lib` to receive events when the volume changes,
 /// avoiding expensive polling. If you do not have `alsa-lib` installed, you
 /// can disable the `volume-widget` feature on the `cnx` crate to avoid
 /// compiling this widget.
+///
+/// [`with_icon()`]: #method.with_icon
+#[derive(Clone)]
 pub struct Volume {
-    handle: Handle,
     attr: Attributes,
+    icon: Option<Icon>,
+    muted_icon: Option<Icon>,
 }
 
 impl Volume {
@@ -34,10 +44,9 @@ impl Volume {
     /// Creates a new `Volume` widget, whose text will be displayed
     /// with the given [`Attributes`].
     ///
-    /// The [`Cnx`] instance is borrowed during construction in order to get
-    /// access to handles of its event loop. However, it is not borrowed for the
-    /// lifetime of the widget. See the [`cnx_add_widget!()`] for more discussion
-    /// about the lifetime of the borrow.
+    /// The [`Cnx`] instance is borrowed during construction. However, it is not
+    /// borrowed for the lifetime of the widget. See the [`cnx_add_widget!()`]
+    /// for more discussion about the lifetime of the borrow.
     ///
     /// [`Attributes`]: ../text/struct.Attributes.html
     /// [`Cnx`]: ../struct.Cnx.html
@@ -67,16 +76,82 @@ impl Volume {
     /// # }
     /// # fn main() { run().unwrap(); }
     /// ```
-    pub fn new(cnx: &Cnx, attr: Attributes) -> Volume {
+    pub fn new(_cnx: &Cnx, attr: Attributes) -> Volume {
         Volume {
-            handle: cnx.handle(),
             attr,
+            icon: None,
+            muted_icon: None,
         }
     }
+
+    /// Shows an icon, such as a speaker, before the volume.
+    pub fn with_icon(mut self, icon: Icon) -> Volume {
+        self.icon = Some(icon);
+        self
+    }
+
+    /// Shows a different icon whilst the output is muted, such as a crossed
+    /// out speaker. Without one, the icon given to [`with_icon()`] is shown.
+    ///
+    /// [`with_icon()`]: #method.with_icon
+    pub fn with_muted_icon(mut self, icon: Icon) -> Volume {
+        self.muted_icon = Some(icon);
+        self
+    }
+}
+
+// FrontLeft has special meaning in ALSA and is the channel that's used when
+// the mixer is mono.
+const CHANNEL: SelemChannelId = SelemChannelId::FrontLeft;
+
+fn master(mixer: &Mixer) -> Result<Selem<'_>> {
+    let master = mixer
+        .find_selem(&SelemId::new("Master", 0))
+        .ok_or_else(|| format_err!("Couldn't open Master channel"))?;
+    Ok(master)
+}
+
+/// Changes the volume/mute status in response to a click or scroll.
+///
+/// We don't need to update our text here - the change will cause an ALSA
+/// event, which will cause the text to be recomputed.
+fn handle_event(mixer_name: &str, event: &Event) -> Result<()> {
+    let button = match *event {
+        Event::ButtonPress(ref click) => click.button,
+        _ => return Ok(()),
+    };
+
+    let mixer = Mixer::new(mixer_name, true)?;
+    let master = master(&mixer)?;
+    match button {
+        Button::Left => {
+            let mute = master.get_playback_switch(CHANNEL)? == 0;
+            master.set_playback_switch_all(if mute { 1 } else { 0 })?;
+        }
+        Button::ScrollUp | Button::ScrollDown => {
+            // Change the volume in steps of 5%.
+            let (min, max) = master.get_playback_volume_range();
+            let step = (max - min) / 20;
+            let volume = master.get_playback_volume(CHANNEL)?;
+            let volume = if button == Button::ScrollUp {
+                volume + step
+            } else {
+                volume - step
+            };
+            master.set_playback_volume_all(volume.max(min).min(max))?;
+        }
+        _ => {}
+    }
+
+    Ok(())
 }
 
 impl Widget for Volume {
     fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        self.stream_with_events(Box::pin(stream::empty()))
+    }
+
+    fn stream_with_events(self: Box<Self>, events: EventStream) -> Result<WidgetStream> {
         let mixer_name = "default";
         // We don't attempt to use the same mixer to listen for events and to
         // recompute the mixer state (in the callback below) as the Mixer seems
@@ -84,110 +159,112 @@ impl Widget for Volume {
         // create a new mixer each time we get an event though.
         let mixer = Mixer::new(mixer_name, true)
             .with_context(|_| format!("Failed to open ALSA mixer: {}", mixer_name))?;
-        let stream = AlsaEventStream::new(&self.handle, mixer)?
-            .and_then(move |()| {
-                // FrontLeft has special meaning in ALSA and is the channel
-                // that's used when the mixer is mono.
-                let channel = SelemChannelId::FrontLeft;
-
+        let stream = AlsaEventStream::new(mixer)?.map(move |event| {
+            let texts = event.and_then(|()| -> Result<Vec<Text>> {
                 let mixer = Mixer::new(mixer_name, true)?;
-                let master = mixer
-                    .find_selem(&SelemId::new("Master", 0))
-                    .ok_or_else(|| format_err!("Couldn't open Master channel"))?;
+                let master = master(&mixer)?;
 
-                let mute = master.get_playback_switch(channel)? == 0;
+                let mute = master.get_playback_switch(CHANNEL)? == 0;
 
-                let text = if !mute {
-                    let volume = master.get_playback_volume(channel)?;
+                let (text, icon) = if !mute {
+                    let volume = master.get_playback_volume(CHANNEL)?;
                     let (min, max) = master.get_playback_volume_range();
                     let percentage = (volume as f64 / (max as f64 - min as f64)) * 100.0;
-                    format!("{:.0}%", percentage)
+                    (format!("{:.0}%", percentage), self.icon.clone())
                 } else {
-                    "M".to_owned()
+                    (
+                        "M".to_owned(),
+                        self.muted_icon.as_ref().or(self.icon.as_ref()).cloned(),
+                    )
                 };
 
-                Ok(vec![Text {
-                    attr: self.attr.clone(),
-                    text: text,
-                    stretch: false,
-                }])
-            })
-            .then(|r| r.context("Error getting ALSA volume information"))
-            .map_err(|e| e.into());
-
-        Ok(Box::new(stream))
-    }
-}
-
-struct AlsaEvented(Mixer);
+                Ok(vec![Text::new(self.attr.clone(), text).with_icon(icon)])
+            });
+            texts
+                .context("Error getting ALSA volume information")
+                .map_err(Error::from)
+        });
 
-impl AlsaEvented {
-    fn mixer(&self) -> &Mixer {
-        &self.0
-    }
+        // Events never produce any text themselves, so filter them all out.
+        let events = events.filter_map(move |event| {
+            if let Err(e) = handle_event(mixer_name, &event) {
+                warn!("Failed to change ALSA volume: {}", e);
+            }
+            future::ready(None)
+        });
 
-    fn fds(&self) -> Vec<RawFd> {
-        self.0
-            .get()
-            .unwrap()
-            .iter()
-            .map(|pollfd| pollfd.fd)
-            .collect()
+        Ok(Box::pin(stream::select(stream, events)))
     }
 }
 
-impl Evented for AlsaEvented {
-    fn register(
-        &self,
-        poll: &mio::Poll,
-        token: Token,
-        interest: Ready,
-        opts: PollOpt,
-    ) -> io::Result<()> {
-        for fd in &self.fds() {
-            EventedFd(fd).register(poll, token, interest, opts)?
-        }
-        Ok(())
-    }
-
-    fn reregister(
-        &self,
-        poll: &mio::Poll,
-        token: Token,
-        interest: Ready,
-        opts: PollOpt,
-    ) -> io::Result<()> {
-        for fd in &self.fds() {
-            EventedFd(fd).reregister(poll, token, interest, opts)?
-        }
-        Ok(())
-    }
+/// One of the file descriptors ALSA wants us to poll.
+struct AlsaFd(RawFd);
 
-    fn deregister(&self, poll: &mio::Poll) -> io::Result<()> {
-        // XXX If the set of fds changes (it won't), should we deregister the
-        // original set?
-        for fd in &self.fds() {
-            EventedFd(fd).deregister(poll)?
-        }
-        Ok(())
+impl AsRawFd for AlsaFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
     }
 }
 
 struct AlsaEventStream {
-    poll: PollEvented<AlsaEvented>,
+    mixer: Mixer,
+    fds: Vec<AsyncFd<AlsaFd>>,
     initial: bool,
 }
 
 impl AlsaEventStream {
-    fn new(handle: &Handle, mixer: Mixer) -> Result<AlsaEventStream> {
+    fn new(mixer: Mixer) -> Result<AlsaEventStream> {
+        let fds = mixer
+            .get()?
+            .iter()
+            .map(|pollfd| AsyncFd::with_interest(AlsaFd(pollfd.fd), Interest::READABLE))
+            .collect::<io::Result<Vec<_>>>()
+            .context("Failed to register ALSA file descriptors")?;
         Ok(AlsaEventStream {
-            poll: PollEvented::new(AlsaEvented(mixer), handle)?,
-            // The first few calls to poll() need to process any existing events.
-            // We don't know what state the fds are in when we give them to tokio
-            // and it's edge-triggered.
+            mixer,
+            fds,
+            // The first call to poll_next() needs to process any existing
+            // events. We don't know what state the fds are in when we give
+            // them to tokio and it's edge-triggered.
             initial: true,
         })
     }
+
+    /// Waits for any of the fds to become readable.
+    fn poll_read_ready(&self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let mut ready = false;
+        // Poll every fd, even after finding one that is ready, so that we're
+        // woken when any of them become ready again.
+        for fd in &self.fds {
+            match fd.poll_read_ready(cx) {
+                Poll::Ready(Ok(mut guard)) => {
+                    guard.clear_ready();
+                    ready = true;
+                }
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e.into())),
+                Poll::Pending => {}
+            }
+        }
+        if ready {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    }
+
+    /// Clears the pending events on the mixer's fds.
+    fn clear_events(&self) -> Result<()> {
+        // Do a poll with a timeout of 0 to figure out exactly which fds were
+        // woken up, followed by a call to revents() which clears the pending
+        // events. We don't actually care what the events are - we're just
+        // using it as a wake-up so we can check the volume again.
+        let ready = alsa::poll::poll_all(&[&self.mixer], 0)?;
+        let poll_descriptors = ready.into_iter().map(|(p, _)| p);
+        for poll_descriptor in poll_descriptors {
+            self.mixer.revents(poll_descriptor.get()?.as_slice())?;
+        }
+        Ok(())
+    }
 }
 
 impl Stream for AlsaEventStream {
@@ -195,33 +272,21 @@ impl Stream for AlsaEventStream {
     // an event. This stream is used only to get woken up when the ALSA state
     // changes - the caller is expected to requery all necessary state when
     // it receives a new item from the stream.
-    type Item = ();
-    type Error = Error;
+    type Item = Result<()>;
 
-    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
         // Always assume we're ready initially, so that we can clear the
         // state of the fds.
-        if !self.initial {
-            if let Async::NotReady = self.poll.poll_read() {
-                return Ok(Async::NotReady);
+        if !this.initial {
+            match this.poll_read_ready(cx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e))),
+                Poll::Pending => return Poll::Pending,
             }
         }
-        self.initial = false;
-
-        // Do a poll with a timeout of 0 to figure out exactly which fds were
-        // woken up, followed by a call to revents() which clears the pending
-        // events. We don't actually care what the events are - we're just
-        // using it as a wake-up so we can check the volume again.
-        let mixer = self.poll.get_ref().mixer();
-        let ready = alsa::poll::poll_all(&[mixer], 0)?;
-        let poll_descriptors = ready.into_iter().map(|(p, _)| p);
-        for poll_descriptor in poll_descriptors {
-            mixer.revents(poll_descriptor.get()?.as_slice())?;
-        }
-        // All events have been consumed - tell Tokio we're interested in waiting
-        // for more again.
-        self.poll.need_read();
+        this.initial = false;
 
-        Ok(Async::Ready(Some(())))
+        Poll::Ready(Some(this.clear_events()))
     }
 }
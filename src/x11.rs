@@ -0,0 +1,406 @@
+//! The X connection shared by the bar and any X-based widgets.
+//!
+//! Cnx opens a single connection to the X server, which is read by one task
+//! that dispatches each event to whoever is interested in it. Widgets that
+//! watch the properties of windows (such as the [`Pager`]) subscribe to the
+//! properties they care about, while the bar and the system tray subscribe to
+//! all events for the windows they own.
+//!
+//! [`Pager`]: ../widgets/struct.Pager.html
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+use failure::{format_err, ResultExt};
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::{Stream, StreamExt};
+use log::*;
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+use tokio::task;
+use xcb_util::ewmh;
+
+use crate::Result;
+
+/// A connection to the X server, shared between the bar and widgets.
+///
+/// This derefs to the underlying `ewmh::Connection`, so it can be used to make
+/// requests as usual.
+pub(crate) struct Connection {
+    conn: ewmh::Connection,
+    screen_idx: i32,
+    root: xcb::Window,
+    event_masks: RefCell<HashMap<xcb::Window, u32>>,
+    subscribers: RefCell<Subscribers>,
+    closed: Cell<bool>,
+}
+
+impl Connection {
+    /// Connects to the X server and starts dispatching its events.
+    ///
+    /// This must be called from within a `tokio::task::LocalSet`, as events
+    /// are read by a task spawned on the current thread.
+    fn connect() -> Result<Rc<Connection>> {
+        let (conn, screen_idx) =
+            xcb::Connection::connect(None).context("Failed to connect to X server")?;
+        let root = conn
+            .get_setup()
+            .roots()
+            .nth(screen_idx as usize)
+            .ok_or_else(|| format_err!("Invalid screen"))?
+            .root();
+        let conn = ewmh::Connection::connect(conn)
+            .map_err(|(e, _)| e)
+            .context("Failed to wrap xcb::Connection in ewmh::Connection")?;
+
+        let conn = Rc::new(Connection {
+            conn,
+            screen_idx,
+            root,
+            event_masks: RefCell::new(HashMap::new()),
+            subscribers: RefCell::new(Subscribers::default()),
+            closed: Cell::new(false),
+        });
+        let events = XcbEventStream::new(conn.clone())?;
+        task::spawn_local(dispatch_events(conn.clone(), events));
+        Ok(conn)
+    }
+
+    pub(crate) fn screen_idx(&self) -> i32 {
+        self.screen_idx
+    }
+
+    pub(crate) fn root(&self) -> xcb::Window {
+        self.root
+    }
+
+    /// Asks for the events in `mask` to be reported for `window`.
+    ///
+    /// The X server only remembers one event mask per window for each
+    /// connection, so this adds to any events already selected through this
+    /// connection rather than replacing them. The mask is remembered until the
+    /// window is destroyed, or until it's given to [`deselect_input()`] or
+    /// [`forget_window()`].
+    ///
+    /// [`deselect_input()`]: #method.deselect_input
+    /// [`forget_window()`]: #method.forget_window
+    pub(crate) fn select_input(&self, window: xcb::Window, mask: u32) {
+        let mut event_masks = self.event_masks.borrow_mut();
+        let selected = event_masks.entry(window).or_insert(0);
+        if *selected & mask != mask {
+            *selected |= mask;
+            xcb::change_window_attributes(&self.conn, window, &[(xcb::CW_EVENT_MASK, *selected)]);
+        }
+    }
+
+    /// Stops the events in `mask` being reported for `window`, leaving any
+    /// other events selected through [`select_input()`].
+    ///
+    /// [`select_input()`]: #method.select_input
+    pub(crate) fn deselect_input(&self, window: xcb::Window, mask: u32) {
+        let mut event_masks = self.event_masks.borrow_mut();
+        let selected = match event_masks.get_mut(&window) {
+            Some(selected) if *selected & mask != 0 => selected,
+            _ => return,
+        };
+        *selected &= !mask;
+        xcb::change_window_attributes(&self.conn, window, &[(xcb::CW_EVENT_MASK, *selected)]);
+        if *selected == 0 {
+            event_masks.remove(&window);
+        }
+    }
+
+    /// Forgets the events selected for `window`, without telling the X
+    /// server. This is for windows we no longer care about, which may be
+    /// destroyed without us seeing it, so that the next window given the same
+    /// ID has its events selected afresh.
+    pub(crate) fn forget_window(&self, window: xcb::Window) {
+        self.event_masks.borrow_mut().remove(&window);
+    }
+
+    /// Returns a stream of the `properties` which have changed, on any window
+    /// which has `EVENT_MASK_PROPERTY_CHANGE` selected.
+    ///
+    /// Property changes are reported for the root window. Use
+    /// [`select_input()`] to see them for other windows.
+    ///
+    /// [`select_input()`]: #method.select_input
+    pub(crate) fn watch_properties(&self, properties: &[xcb::Atom]) -> Subscription<xcb::Atom> {
+        self.select_input(self.root, xcb::EVENT_MASK_PROPERTY_CHANGE);
+        self.conn.flush();
+
+        let (sender, receiver) = mpsc::unbounded();
+        self.subscribers
+            .borrow_mut()
+            .properties
+            .push((properties.to_vec(), sender));
+        Subscription::new(receiver)
+    }
+
+    /// Returns a stream of the events for the windows given to the returned
+    /// [`WindowWatcher`], including property changes for any of them other
+    /// than the root window.
+    ///
+    /// Whoever watches the root window is also given any events which aren't
+    /// about a window that's being watched, such as RandR's notifications.
+    pub(crate) fn watch_windows(
+        self: &Rc<Self>,
+    ) -> (WindowWatcher, Subscription<xcb::GenericEvent>) {
+        let (sender, receiver) = mpsc::unbounded();
+        let mut subscribers = self.subscribers.borrow_mut();
+        let id = subscribers.next_id;
+        subscribers.next_id += 1;
+        subscribers.window_senders.insert(id, sender);
+
+        let watcher = WindowWatcher {
+            conn: self.clone(),
+            id,
+        };
+        (watcher, Subscription::new(receiver))
+    }
+
+    fn dispatch(&self, event: xcb::GenericEvent) {
+        let mut subscribers = self.subscribers.borrow_mut();
+
+        if event.response_type() & !0x80 == xcb::DESTROY_NOTIFY {
+            // Its ID may be reused for a new window, whose events we haven't
+            // selected.
+            let window = unsafe { xcb::cast_event::<xcb::DestroyNotifyEvent>(&event) }.window();
+            self.forget_window(window);
+        }
+
+        let id = if event.response_type() & !0x80 == xcb::PROPERTY_NOTIFY {
+            let (window, atom) = {
+                let event = unsafe { xcb::cast_event::<xcb::PropertyNotifyEvent>(&event) };
+                (event.window(), event.atom())
+            };
+            // Forget about anyone who has stopped listening.
+            subscribers.properties.retain(|(atoms, sender)| {
+                !atoms.contains(&atom) || sender.unbounded_send(atom).is_ok()
+            });
+            // Whoever watches the window is told too, unless it's the root
+            // window, whose properties are only of interest to the above.
+            match subscribers.windows.get(&window) {
+                Some(&id) if window != self.root => Some(id),
+                _ => return,
+            }
+        } else {
+            event_window(&event)
+                .and_then(|window| subscribers.windows.get(&window))
+                .or_else(|| subscribers.windows.get(&self.root))
+                .cloned()
+        };
+        if let Some(id) = id {
+            let sent = subscribers
+                .window_senders
+                .get(&id)
+                .is_some_and(|sender| sender.unbounded_send(event).is_ok());
+            if !sent {
+                subscribers.remove(id);
+            }
+        }
+    }
+
+    /// Ends every subscription, so that their streams return an error.
+    fn close(&self) {
+        self.closed.set(true);
+        *self.subscribers.borrow_mut() = Subscribers::default();
+    }
+}
+
+impl Deref for Connection {
+    type Target = ewmh::Connection;
+
+    fn deref(&self) -> &ewmh::Connection {
+        &self.conn
+    }
+}
+
+async fn dispatch_events(conn: Rc<Connection>, mut events: XcbEventStream) {
+    while let Some(event) = events.next().await {
+        match event {
+            Ok(event) => conn.dispatch(event),
+            Err(e) => {
+                error!("Lost connection to X server: {}", e);
+                break;
+            }
+        }
+    }
+    conn.close();
+}
+
+/// Returns the window an event is about, for the events we route by window.
+fn event_window(event: &xcb::GenericEvent) -> Option<xcb::Window> {
+    let window = unsafe {
+        match event.response_type() & !0x80 {
+            xcb::EXPOSE => xcb::cast_event::<xcb::ExposeEvent>(event).window(),
+            // Button press and release events have the same layout.
+            xcb::BUTTON_PRESS | xcb::BUTTON_RELEASE => {
+                xcb::cast_event::<xcb::ButtonPressEvent>(event).event()
+            }
+            xcb::CLIENT_MESSAGE => xcb::cast_event::<xcb::ClientMessageEvent>(event).window(),
+            xcb::DESTROY_NOTIFY => xcb::cast_event::<xcb::DestroyNotifyEvent>(event).event(),
+            xcb::REPARENT_NOTIFY => xcb::cast_event::<xcb::ReparentNotifyEvent>(event).event(),
+            xcb::CONFIGURE_NOTIFY => xcb::cast_event::<xcb::ConfigureNotifyEvent>(event).event(),
+            xcb::SELECTION_CLEAR => xcb::cast_event::<xcb::SelectionClearEvent>(event).owner(),
+            _ => return None,
+        }
+    };
+    Some(window)
+}
+
+#[derive(Default)]
+struct Subscribers {
+    next_id: usize,
+    windows: HashMap<xcb::Window, usize>,
+    window_senders: HashMap<usize, UnboundedSender<xcb::GenericEvent>>,
+    properties: Vec<(Vec<xcb::Atom>, UnboundedSender<xcb::Atom>)>,
+}
+
+impl Subscribers {
+    fn remove(&mut self, id: usize) {
+        self.window_senders.remove(&id);
+        self.windows.retain(|_, watcher| *watcher != id);
+    }
+}
+
+/// Chooses which windows' events are given to a subscription returned by
+/// [`Connection::watch_windows()`].
+///
+/// Each window's events are only given to one subscription. If a window is
+/// watched by more than one, the last to watch it wins.
+///
+/// [`Connection::watch_windows()`]: struct.Connection.html#method.watch_windows
+pub(crate) struct WindowWatcher {
+    conn: Rc<Connection>,
+    id: usize,
+}
+
+impl WindowWatcher {
+    pub(crate) fn watch(&self, window: xcb::Window) {
+        let mut subscribers = self.conn.subscribers.borrow_mut();
+        subscribers.windows.insert(window, self.id);
+    }
+
+    pub(crate) fn unwatch(&self, window: xcb::Window) {
+        let mut subscribers = self.conn.subscribers.borrow_mut();
+        if subscribers.windows.get(&window) == Some(&self.id) {
+            subscribers.windows.remove(&window);
+        }
+    }
+}
+
+/// A stream of the events dispatched to one subscriber.
+///
+/// If the connection to the X server is lost, the stream returns an error
+/// and then ends.
+pub(crate) struct Subscription<T> {
+    receiver: UnboundedReceiver<T>,
+    ended: bool,
+}
+
+impl<T> Subscription<T> {
+    fn new(receiver: UnboundedReceiver<T>) -> Subscription<T> {
+        Subscription {
+            receiver,
+            ended: false,
+        }
+    }
+}
+
+impl<T> Stream for Subscription<T> {
+    type Item = Result<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.ended {
+            return Poll::Ready(None);
+        }
+        match self.receiver.poll_next_unpin(cx) {
+            Poll::Ready(Some(item)) => Poll::Ready(Some(Ok(item))),
+            Poll::Ready(None) => {
+                self.ended = true;
+                Poll::Ready(Some(Err(format_err!("Lost connection to X server"))))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// A handle to the shared X connection, which connects the first time it's
+/// needed.
+///
+/// `Cnx` owns one of these and gives a copy to each X-based widget, so that
+/// nothing connects to the X server unless something needs to. If the
+/// connection is lost, the next caller of [`get()`] reconnects.
+///
+/// [`get()`]: #method.get
+#[derive(Clone, Default)]
+pub(crate) struct LazyConnection(Rc<RefCell<Option<Rc<Connection>>>>);
+
+impl LazyConnection {
+    pub(crate) fn get(&self) -> Result<Rc<Connection>> {
+        let mut conn = self.0.borrow_mut();
+        match *conn {
+            Some(ref conn) if !conn.closed.get() => Ok(conn.clone()),
+            _ => {
+                let new_conn = Connection::connect()?;
+                *conn = Some(new_conn.clone());
+                Ok(new_conn)
+            }
+        }
+    }
+}
+
+/// The file descriptor of an XCB connection, for use with `AsyncFd`.
+struct XcbFd(Rc<Connection>);
+
+impl AsRawFd for XcbFd {
+    fn as_raw_fd(&self) -> RawFd {
+        let conn: &xcb::Connection = &self.0;
+        unsafe { xcb::ffi::base::xcb_get_file_descriptor(conn.get_raw_conn()) }
+    }
+}
+
+struct XcbEventStream {
+    fd: AsyncFd<XcbFd>,
+}
+
+impl XcbEventStream {
+    fn new(conn: Rc<Connection>) -> Result<XcbEventStream> {
+        let fd = AsyncFd::with_interest(XcbFd(conn), Interest::READABLE)
+            .context("Failed to register XCB connection")?;
+        Ok(XcbEventStream { fd })
+    }
+}
+
+impl Stream for XcbEventStream {
+    type Item = Result<xcb::GenericEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            // XCB may already have read events from the socket (e.g. while
+            // waiting for a reply), so check its queue before waiting for the
+            // socket to become readable.
+            let conn = &self.fd.get_ref().0;
+            if let Some(event) = conn.poll_for_event() {
+                return Poll::Ready(Some(Ok(event)));
+            }
+            // Once the connection has failed, the socket will always look
+            // readable but XCB will never give us another event.
+            if let Err(e) = conn.has_error() {
+                return Poll::Ready(Some(Err(e.into())));
+            }
+
+            match self.fd.poll_read_ready(cx) {
+                Poll::Ready(Ok(mut guard)) => guard.clear_ready(),
+                Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}